@@ -0,0 +1,109 @@
+//! Compiles every `src/<name>.<stage>.glsl` into `$OUT_DIR/<name>.<stage>.spv`,
+//! where `<stage>` is `vertex`, `fragment` or `compute`, and checks that each
+//! fragment shader agrees on its interface with the vertex shader its
+//! pipeline uses, see [`shader_compiler::PIPELINES`].
+
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+};
+
+use naga::{Module, ShaderStage};
+
+#[path = "src/renderer/shader_compiler.rs"]
+mod shader_compiler;
+
+struct Shader {
+	path: PathBuf,
+	stage: ShaderStage,
+	module: Module,
+}
+
+fn main() {
+	println!("cargo:rerun-if-changed=build.rs");
+	// Cargo scans directories recursively, so watching `src` for shaders
+	// being added or removed would rerun this on every edit. Fragment
+	// shaders come and go with their entry in `PIPELINES` anyway, and other
+	// new shaders need this file touched or a clean build.
+	println!("cargo:rerun-if-changed=src/renderer/shader_compiler.rs");
+
+	let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+	let mut shaders = BTreeMap::<String, Vec<Shader>>::new();
+
+	for entry in std::fs::read_dir("src").unwrap() {
+		let path = entry.unwrap().path();
+		let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+			continue;
+		};
+		let Some(stem) = file_name.strip_suffix(".glsl") else {
+			continue;
+		};
+		println!("cargo:rerun-if-changed={}", path.display());
+
+		let Some((name, stage)) = stem.rsplit_once('.') else {
+			fail(&path, "expected a name of the form <name>.<stage>.glsl");
+		};
+		let stage = match stage {
+			"vertex" => ShaderStage::Vertex,
+			"fragment" => ShaderStage::Fragment,
+			"compute" => ShaderStage::Compute,
+			_ => fail(&path, &format!("unknown shader stage {:?}", stage)),
+		};
+
+		let source = std::fs::read_to_string(&path).unwrap();
+		let shader = shader_compiler::compile(&source, stage)
+			.unwrap_or_else(|err| fail(&path, &err.to_string()));
+		std::fs::write(
+			out_dir.join(format!("{}.spv", stem)),
+			shader.to_bytes(),
+		)
+		.unwrap();
+
+		shaders.entry(name.to_string()).or_default().push(Shader {
+			path,
+			stage,
+			module: shader.module,
+		});
+	}
+
+	for (name, stages) in &shaders {
+		let Some(fragment) =
+			stages.iter().find(|s| s.stage == ShaderStage::Fragment)
+		else {
+			continue;
+		};
+		let Some((_, vertex)) = shader_compiler::PIPELINES
+			.iter()
+			.find(|(fragment, _)| fragment == name)
+		else {
+			fail(
+				&fragment.path,
+				"no pipeline uses it, add it to shader_compiler::PIPELINES",
+			);
+		};
+		let Some(vertex) = shaders.get(*vertex).and_then(|stages| {
+			stages.iter().find(|s| s.stage == ShaderStage::Vertex)
+		}) else {
+			fail(
+				&fragment.path,
+				&format!("vertex shader {}.vertex.glsl is missing", vertex),
+			);
+		};
+
+		shader_compiler::check_interface(&vertex.module, &fragment.module)
+			.unwrap_or_else(|err| {
+				fail(
+					&fragment.path,
+					&format!(
+						"{} (vertex shader: {})",
+						err,
+						vertex.path.display()
+					),
+				)
+			});
+	}
+}
+
+fn fail(path: &Path, message: &str) -> ! {
+	panic!("{}: {}", path.display(), message);
+}
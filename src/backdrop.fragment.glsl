@@ -0,0 +1,42 @@
+#version 450
+
+// Draws the blurred backdrop behind a glass element, with the vertex shader
+// of the quad pipeline.
+
+layout(push_constant) uniform PushConstants {
+	vec4 rect;
+	vec2 outputSize;
+	float opacity;
+	float cornerRadius;
+	// 1.0 keeps the colors of the backdrop, more makes them more vivid.
+	float saturation;
+} pc;
+
+layout(set = 0, binding = 0) uniform texture2D backdrop;
+layout(set = 0, binding = 1) uniform sampler backdropSampler;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+// Same as in `quad.fragment.glsl`.
+float coverage() {
+	vec2 halfSize = pc.rect.zw * 0.5;
+	float radius = min(pc.cornerRadius, min(halfSize.x, halfSize.y));
+	vec2 p = abs(fragTexCoord * pc.rect.zw - halfSize) - (halfSize - radius);
+	float distance =
+		length(max(p, 0.0)) + min(max(p.x, p.y), 0.0) - radius;
+
+	return clamp(0.5 - distance, 0.0, 1.0);
+}
+
+void main() {
+	// The blurred copy covers the whole output.
+	vec2 uv = gl_FragCoord.xy / pc.outputSize;
+	vec3 color = texture(sampler2D(backdrop, backdropSampler), uv).rgb;
+
+	float luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+	color = clamp(mix(vec3(luma), color, pc.saturation), 0.0, 1.0);
+
+	outColor = vec4(color, 1.0) * pc.opacity * coverage();
+}
@@ -0,0 +1,38 @@
+#version 450
+
+// Upsampling pass of the dual Kawase blur, see `renderer/blur.rs`.
+
+layout(push_constant) uniform PushConstants {
+	vec4 rect;
+	vec2 outputSize;
+	// Distance of the samples in texels of the source.
+	float offset;
+} pc;
+
+layout(set = 0, binding = 0) uniform texture2D source;
+layout(set = 0, binding = 1) uniform sampler sourceSampler;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+vec4 tap(vec2 uv) {
+	return texture(sampler2D(source, sourceSampler), uv);
+}
+
+void main() {
+	vec2 texel = 1.0 / vec2(textureSize(sampler2D(source, sourceSampler), 0));
+	vec2 halfPixel = texel * 0.5 * pc.offset;
+	vec2 uv = fragTexCoord;
+
+	vec4 sum = tap(uv + vec2(-halfPixel.x * 2.0, 0.0));
+	sum += tap(uv + vec2(-halfPixel.x, halfPixel.y)) * 2.0;
+	sum += tap(uv + vec2(0.0, halfPixel.y * 2.0));
+	sum += tap(uv + vec2(halfPixel.x, halfPixel.y)) * 2.0;
+	sum += tap(uv + vec2(halfPixel.x * 2.0, 0.0));
+	sum += tap(uv + vec2(halfPixel.x, -halfPixel.y)) * 2.0;
+	sum += tap(uv + vec2(0.0, -halfPixel.y * 2.0));
+	sum += tap(uv + vec2(-halfPixel.x, -halfPixel.y)) * 2.0;
+
+	outColor = sum / 12.0;
+}
@@ -0,0 +1,771 @@
+use std::collections::{HashMap, VecDeque};
+
+use wayland_protocols::wp::viewporter::server::wp_viewport::WpViewport;
+use wayland_server::{
+	backend::{ClientId, GlobalId, ObjectId},
+	protocol::{
+		wl_buffer::WlBuffer,
+		wl_callback::WlCallback,
+		wl_compositor::{self, WlCompositor},
+		wl_output::Transform,
+		wl_region::{self, WlRegion},
+		wl_shm,
+		wl_surface::{self, WlSurface},
+	},
+	Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+	WEnum,
+};
+
+use crate::{
+	dmabuf::DmabufBuffer,
+	geometry::{Point, Rectangle, Size},
+	renderer::{SceneElement, TextureId, TextureTransform},
+	seat::CursorImage,
+	shm::ShmBuffer,
+	ServerState,
+};
+
+#[cfg(test)]
+mod tests;
+
+const COMPOSITOR_VERSION: u32 = 6;
+
+/// Whether the client finished drawing into `buffer`. Only dmabufs can
+/// still be written to by the GPU once committed.
+fn buffer_ready(buffer: &WlBuffer) -> bool {
+	buffer
+		.data::<DmabufBuffer>()
+		.is_none_or(DmabufBuffer::is_ready)
+}
+
+/// State of the `wl_compositor` global and every surface and region created
+/// through it.
+pub struct Compositor {
+	pub global: GlobalId,
+	pub surfaces: HashMap<ObjectId, Surface>,
+	pub regions: HashMap<ObjectId, Region>,
+}
+
+impl Compositor {
+	pub fn new(display: &DisplayHandle) -> Self {
+		Self {
+			global: display.create_global::<ServerState, WlCompositor, ()>(
+				COMPOSITOR_VERSION,
+				(),
+			),
+			surfaces: HashMap::new(),
+			regions: HashMap::new(),
+		}
+	}
+
+	/// Sends `done` to the frame callbacks committed since the last call by
+	/// surfaces paced by the output `output`: the first one they are on, or
+	/// the first one of all for surfaces that are on none, e.g. cursors.
+	pub fn send_frame_callbacks(&mut self, output: usize, time: u32) {
+		for surface in self.surfaces.values_mut() {
+			if surface.outputs.first().copied().unwrap_or(0) != output {
+				continue;
+			}
+			for callback in surface.current.frame_callbacks.drain(..) {
+				callback.done(time);
+			}
+		}
+	}
+}
+
+/// A `wl_surface` with its double-buffered state.
+///
+/// Requests only ever modify `pending`, which is applied on top of `current`
+/// when the client commits, or once the parent's state is applied for
+/// synchronized subsurfaces.
+pub struct Surface {
+	pub handle: WlSurface,
+	pub pending: SurfaceState,
+	/// Commits waiting for the client to finish drawing into their buffer,
+	/// oldest first. Later commits wait behind them to keep their order.
+	pub queued: VecDeque<SurfaceState>,
+	/// Committed state that is not applied yet, which only lasts while the
+	/// surface is a synchronized subsurface.
+	pub cached: Option<SurfaceState>,
+	pub current: SurfaceState,
+	/// Contents of the last buffer attached to the surface.
+	pub texture: Option<TextureId>,
+	/// Size in pixels of the last buffer attached to the surface.
+	pub buffer_size: Option<Size>,
+	/// Set once and never changes for the lifetime of the surface.
+	pub role: Option<SurfaceRole>,
+	pub viewport: Option<WpViewport>,
+	/// Indices of the outputs the surface overlaps, which it was sent
+	/// `enter` for on every `wl_output` of its client.
+	pub outputs: Vec<usize>,
+	/// Last scale sent with `preferred_buffer_scale`.
+	pub preferred_buffer_scale: Option<i32>,
+}
+
+impl Surface {
+	fn new(handle: WlSurface) -> Self {
+		Self {
+			handle,
+			pending: SurfaceState::default(),
+			queued: VecDeque::new(),
+			cached: None,
+			current: SurfaceState::default(),
+			texture: None,
+			buffer_size: None,
+			role: None,
+			viewport: None,
+			outputs: Vec::new(),
+			preferred_buffer_scale: None,
+		}
+	}
+
+	/// Whether the client attached a buffer, committed or not, that it did
+	/// not detach since.
+	pub fn has_buffer(&self) -> bool {
+		let latest = std::iter::once(&self.pending)
+			.chain(self.queued.iter().rev())
+			.chain(&self.cached)
+			.chain(std::iter::once(&self.current))
+			.find_map(|state| state.buffer.as_ref());
+		match latest {
+			Some(buffer) => matches!(buffer, BufferAssignment::NewBuffer(_)),
+			None => self.buffer_size.is_some(),
+		}
+	}
+
+	/// Size of the buffer in surface-local coordinates, before the viewport
+	/// crops and scales it, or `None` without a buffer.
+	pub fn buffer_local_size(&self) -> Option<Size> {
+		let buffer_size = self.buffer_size?;
+		let scale = self.current.buffer_scale;
+		let size =
+			Size::new(buffer_size.width / scale, buffer_size.height / scale);
+
+		Some(match self.current.buffer_transform {
+			Transform::_90
+			| Transform::_270
+			| Transform::Flipped90
+			| Transform::Flipped270 => Size::new(size.height, size.width),
+			_ => size,
+		})
+	}
+
+	/// Size in surface-local coordinates, or `None` without a buffer.
+	pub fn size(&self) -> Option<Size> {
+		let buffer_size = self.buffer_local_size()?;
+
+		Some(match (self.current.destination, self.current.source) {
+			(Some(destination), _) => destination,
+			(None, Some([_, _, width, height])) => {
+				Size::new(width as i32, height as i32)
+			}
+			(None, None) => buffer_size,
+		})
+	}
+
+	/// Part of the texture shown by the surface, in normalized texture
+	/// coordinates.
+	pub fn texture_source(&self) -> [f32; 4] {
+		let (Some([x, y, width, height]), Some(size)) =
+			(self.current.source, self.buffer_local_size())
+		else {
+			return SceneElement::WHOLE_TEXTURE;
+		};
+
+		let (buffer_width, buffer_height) =
+			(size.width as f64, size.height as f64);
+		[
+			(x / buffer_width) as f32,
+			(y / buffer_height) as f32,
+			(width / buffer_width) as f32,
+			(height / buffer_height) as f32,
+		]
+	}
+
+	/// How the client transformed the contents of its buffer.
+	pub fn texture_transform(&self) -> TextureTransform {
+		match self.current.buffer_transform {
+			Transform::_90 => TextureTransform::Rotated90,
+			Transform::_180 => TextureTransform::Rotated180,
+			Transform::_270 => TextureTransform::Rotated270,
+			Transform::Flipped => TextureTransform::Flipped,
+			Transform::Flipped90 => TextureTransform::Flipped90,
+			Transform::Flipped180 => TextureTransform::Flipped180,
+			Transform::Flipped270 => TextureTransform::Flipped270,
+			_ => TextureTransform::Normal,
+		}
+	}
+
+	/// Whether the surface accepts input at `point`, in surface-local
+	/// coordinates.
+	pub fn accepts_input(&self, point: Point) -> bool {
+		let Some(size) = self.size() else {
+			return false;
+		};
+
+		Rectangle::from_loc_and_size(Point::default(), size).contains(point)
+			&& self
+				.current
+				.input_region
+				.as_ref()
+				.is_none_or(|region| region.contains(point))
+	}
+
+	/// Queues the pending state, which is cached once its buffer is ready.
+	pub fn queue_commit(&mut self) {
+		let mut state = SurfaceState::default();
+		state.merge(&mut self.pending);
+		self.queued.push_back(state);
+	}
+
+	/// Moves the queued commits into the cache in order, on top of the state
+	/// cached by earlier commits that were not applied yet, up to the first
+	/// one with a buffer that `is_ready` says is still being drawn into.
+	/// Returns whether any commit was cached.
+	pub fn unblock_commits(
+		&mut self,
+		is_ready: impl Fn(&WlBuffer) -> bool,
+	) -> bool {
+		let mut unblocked = false;
+		while let Some(mut state) = self.queued.pop_front() {
+			if let Some(BufferAssignment::NewBuffer(buffer)) = &state.buffer {
+				if !is_ready(buffer) {
+					self.queued.push_front(state);
+					break;
+				}
+			}
+
+			self.cached
+				.get_or_insert_with(SurfaceState::default)
+				.merge(&mut state);
+			unblocked = true;
+		}
+		unblocked
+	}
+
+	/// Applies the cached state, returning whether there was any.
+	pub fn apply_cached(&mut self) -> bool {
+		let Some(mut cached) = self.cached.take() else {
+			return false;
+		};
+
+		self.current.merge(&mut cached);
+		true
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SurfaceRole {
+	XdgToplevel,
+	XdgPopup,
+	Cursor,
+	Subsurface,
+}
+
+#[derive(Clone, Debug)]
+pub enum BufferAssignment {
+	Removed,
+	NewBuffer(WlBuffer),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Damage {
+	/// Damage in surface-local coordinates, from `wl_surface.damage`.
+	Surface(Rectangle),
+	/// Damage in buffer coordinates, from `wl_surface.damage_buffer`.
+	Buffer(Rectangle),
+}
+
+#[derive(Clone, Debug)]
+pub struct SurfaceState {
+	/// `None` when no buffer was attached since the last commit. In the
+	/// current state, this is the assignment not yet imported into the
+	/// surface's texture.
+	pub buffer: Option<BufferAssignment>,
+	/// Position of the new buffer relative to the previous one.
+	pub offset: Point,
+	pub damage: Vec<Damage>,
+	pub frame_callbacks: Vec<WlCallback>,
+	pub buffer_scale: i32,
+	pub buffer_transform: Transform,
+	/// `None` means the whole surface is opaque-unknown, i.e. empty.
+	pub opaque_region: Option<Region>,
+	/// `None` means the whole surface accepts input.
+	pub input_region: Option<Region>,
+	/// Part of the buffer to show, as x, y, width and height in
+	/// surface-local coordinates before cropping and scaling. `None` for the
+	/// whole buffer.
+	pub source: Option<[f64; 4]>,
+	/// Size to scale the buffer to, `None` to keep the size of `source`.
+	pub destination: Option<Size>,
+	/// Subsurfaces stacked below the surface, from bottom to top.
+	pub subsurfaces_below: Vec<ObjectId>,
+	/// Subsurfaces stacked above the surface, from bottom to top.
+	pub subsurfaces_above: Vec<ObjectId>,
+}
+
+impl SurfaceState {
+	/// Applies `newer` on top of this state. Damage and frame callbacks
+	/// accumulate until they are consumed, while the rest replaces it.
+	fn merge(&mut self, newer: &mut SurfaceState) {
+		if let Some(buffer) = newer.buffer.take() {
+			// A buffer replaced before being imported will never be used.
+			if let Some(BufferAssignment::NewBuffer(old)) = &self.buffer {
+				if !matches!(&buffer, BufferAssignment::NewBuffer(new) if new == old)
+				{
+					old.release();
+				}
+			}
+			self.buffer = Some(buffer);
+		}
+		self.offset = std::mem::take(&mut newer.offset);
+		self.damage.append(&mut newer.damage);
+		self.frame_callbacks.append(&mut newer.frame_callbacks);
+		self.buffer_scale = newer.buffer_scale;
+		self.buffer_transform = newer.buffer_transform;
+		self.opaque_region.clone_from(&newer.opaque_region);
+		self.input_region.clone_from(&newer.input_region);
+		self.source = newer.source;
+		self.destination = newer.destination;
+		self.subsurfaces_below.clone_from(&newer.subsurfaces_below);
+		self.subsurfaces_above.clone_from(&newer.subsurfaces_above);
+	}
+
+	/// Forgets a subsurface that is no longer stacked with the surface.
+	pub fn remove_subsurface(&mut self, id: &ObjectId) {
+		self.subsurfaces_below.retain(|other| other != id);
+		self.subsurfaces_above.retain(|other| other != id);
+	}
+}
+
+impl Default for SurfaceState {
+	fn default() -> Self {
+		Self {
+			buffer: None,
+			offset: Point::default(),
+			damage: Vec::new(),
+			frame_callbacks: Vec::new(),
+			buffer_scale: 1,
+			buffer_transform: Transform::Normal,
+			opaque_region: None,
+			input_region: None,
+			source: None,
+			destination: None,
+			subsurfaces_below: Vec::new(),
+			subsurfaces_above: Vec::new(),
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RegionOp {
+	Add,
+	Subtract,
+}
+
+/// A `wl_region`, kept as the list of operations that built it.
+#[derive(Clone, Debug, Default)]
+pub struct Region {
+	pub rects: Vec<(RegionOp, Rectangle)>,
+}
+
+impl Region {
+	/// Whether `point` is inside the region, with later operations taking
+	/// precedence over earlier ones.
+	pub fn contains(&self, point: Point) -> bool {
+		self.rects
+			.iter()
+			.rev()
+			.find(|(_, rect)| rect.contains(point))
+			.is_some_and(|(op, _)| *op == RegionOp::Add)
+	}
+}
+
+impl ServerState {
+	/// Applies the state cached by the commits of a surface, then that of
+	/// its synchronized subsurfaces.
+	pub fn apply_cached_state(&mut self, id: &ObjectId) {
+		let Some(surface) = self.compositor.surfaces.get_mut(id) else {
+			return;
+		};
+		if !surface.apply_cached() {
+			return;
+		}
+
+		self.import_buffer(id);
+		self.viewport_commit(id);
+		self.xdg_commit(id);
+		self.cursor_commit(id);
+		self.subsurface_parent_commit(id);
+	}
+
+	/// Caches the commits of `id` whose buffers are ready, and applies them
+	/// unless it is a synchronized subsurface waiting for its parent.
+	pub fn unblock_commits(&mut self, id: &ObjectId) {
+		let Some(surface) = self.compositor.surfaces.get_mut(id) else {
+			return;
+		};
+		if !surface.unblock_commits(buffer_ready) {
+			return;
+		}
+
+		if !self.subcompositor.is_synchronized(id) {
+			self.apply_cached_state(id);
+		}
+	}
+
+	/// Applies the commits held back by buffers that became ready since.
+	pub fn unblock_all_commits(&mut self) {
+		let blocked: Vec<_> = self
+			.compositor
+			.surfaces
+			.iter()
+			.filter(|(_, surface)| !surface.queued.is_empty())
+			.map(|(id, _)| id.clone())
+			.collect();
+		for id in blocked {
+			self.unblock_commits(&id);
+		}
+	}
+
+	/// Copies the buffer attached by the last commit of `surface` into its
+	/// texture and releases it back to the client.
+	fn import_buffer(&mut self, surface: &ObjectId) {
+		let Some(surface) = self.compositor.surfaces.get_mut(surface) else {
+			return;
+		};
+		// Buffers are always imported whole, which uses up the damage.
+		surface.current.damage.clear();
+		let buffer = surface.current.buffer.take();
+		let Some(renderer) = self.renderer.as_mut() else {
+			// Nothing is shown before there is a renderer, so the client can
+			// have the buffer back right away.
+			if let Some(BufferAssignment::NewBuffer(buffer)) = buffer {
+				buffer.release();
+			}
+			return;
+		};
+
+		match buffer {
+			Some(BufferAssignment::NewBuffer(buffer)) => {
+				let imported = if let Some(data) = buffer.data::<ShmBuffer>() {
+					let result = data.with_data(|image| unsafe {
+						renderer.upload_texture(surface.texture, image)
+					});
+					match result {
+						Ok(result) => {
+							Some((result, Size::new(data.width, data.height)))
+						}
+						Err(err) => {
+							buffer.post_error(
+								wl_shm::Error::InvalidFd,
+								format!("failed to read buffer: {}", err),
+							);
+							None
+						}
+					}
+				} else if let Some(data) = buffer.data::<DmabufBuffer>() {
+					let result = unsafe {
+						renderer
+							.copy_external_image(surface.texture, data.image)
+					};
+					Some((result, data.size()))
+				} else {
+					None
+				};
+
+				match imported {
+					Some((Ok(texture), size)) => {
+						if surface.texture != Some(texture) {
+							let name = format!(
+								"{} of {:?}",
+								surface.handle.id(),
+								surface.handle.client().map(|c| c.id())
+							);
+							unsafe {
+								renderer.set_texture_name(texture, &name)
+							};
+						}
+						surface.texture = Some(texture);
+						surface.buffer_size = Some(size);
+					}
+					Some((Err(err), _)) => {
+						tracing::error!("Failed to import buffer: {}", err)
+					}
+					None => {}
+				}
+
+				buffer.release();
+			}
+			Some(BufferAssignment::Removed) => {
+				surface.buffer_size = None;
+				if let Some(texture) = surface.texture.take() {
+					unsafe { renderer.destroy_texture(texture) }
+						.unwrap_or_else(|err| {
+							tracing::error!(
+								"Failed to destroy texture: {}",
+								err
+							)
+						});
+				}
+			}
+			None => {}
+		}
+	}
+}
+
+impl GlobalDispatch<WlCompositor, ()> for ServerState {
+	fn bind(
+		_state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<WlCompositor>,
+		_global_data: &(),
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		data_init.init(resource, ());
+	}
+}
+
+impl Dispatch<WlCompositor, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		_resource: &WlCompositor,
+		request: wl_compositor::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wl_compositor::Request::CreateSurface { id } => {
+				let surface = data_init.init(id, ());
+				state
+					.compositor
+					.surfaces
+					.insert(surface.id(), Surface::new(surface));
+			}
+			wl_compositor::Request::CreateRegion { id } => {
+				let region = data_init.init(id, ());
+				state
+					.compositor
+					.regions
+					.insert(region.id(), Region::default());
+			}
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<WlSurface, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &WlSurface,
+		request: wl_surface::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		let Some(surface) = state.compositor.surfaces.get_mut(&resource.id())
+		else {
+			return;
+		};
+
+		match request {
+			wl_surface::Request::Destroy => {}
+			wl_surface::Request::Attach { buffer, x, y } => {
+				if resource.version() >= 5 && (x != 0 || y != 0) {
+					resource.post_error(
+						wl_surface::Error::InvalidOffset,
+						"attach offset must be zero, use wl_surface.offset",
+					);
+					return;
+				}
+
+				surface.pending.buffer = Some(match buffer {
+					Some(buffer) => BufferAssignment::NewBuffer(buffer),
+					None => BufferAssignment::Removed,
+				});
+				surface.pending.offset = Point::new(x, y);
+			}
+			wl_surface::Request::Damage {
+				x,
+				y,
+				width,
+				height,
+			} => {
+				surface
+					.pending
+					.damage
+					.push(Damage::Surface(Rectangle::new(x, y, width, height)));
+			}
+			wl_surface::Request::Frame { callback } => {
+				surface
+					.pending
+					.frame_callbacks
+					.push(data_init.init(callback, ()));
+			}
+			wl_surface::Request::SetOpaqueRegion { region } => {
+				surface.pending.opaque_region = region
+					.and_then(|r| state.compositor.regions.get(&r.id()))
+					.cloned();
+			}
+			wl_surface::Request::SetInputRegion { region } => {
+				surface.pending.input_region = region
+					.and_then(|r| state.compositor.regions.get(&r.id()))
+					.cloned();
+			}
+			wl_surface::Request::Commit => {
+				// Commits wait for their buffer in a queue, and then
+				// synchronized subsurfaces for their parent.
+				surface.queue_commit();
+				state.unblock_commits(&resource.id());
+			}
+			wl_surface::Request::SetBufferTransform { transform } => {
+				match transform {
+					WEnum::Value(transform) => {
+						surface.pending.buffer_transform = transform;
+					}
+					WEnum::Unknown(value) => resource.post_error(
+						wl_surface::Error::InvalidTransform,
+						format!("invalid buffer transform {}", value),
+					),
+				}
+			}
+			wl_surface::Request::SetBufferScale { scale } => {
+				if scale < 1 {
+					resource.post_error(
+						wl_surface::Error::InvalidScale,
+						format!("invalid buffer scale {}", scale),
+					);
+					return;
+				}
+
+				surface.pending.buffer_scale = scale;
+			}
+			wl_surface::Request::DamageBuffer {
+				x,
+				y,
+				width,
+				height,
+			} => {
+				surface
+					.pending
+					.damage
+					.push(Damage::Buffer(Rectangle::new(x, y, width, height)));
+			}
+			wl_surface::Request::Offset { x, y } => {
+				surface.pending.offset = Point::new(x, y);
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		resource: &WlSurface,
+		_data: &(),
+	) {
+		state.xdg_shell.remove_surface(&resource.id());
+		state.remove_subsurface(&resource.id());
+		state.subcompositor.orphan_children(&resource.id());
+		state.space.unmap(&resource.id());
+
+		// The surface is gone, so there is nothing to send `leave` to.
+		if state.seat.keyboard.focus.as_ref() == Some(resource) {
+			state.seat.keyboard.focus = None;
+			state.focus_previous_window();
+		}
+		if state
+			.seat
+			.pointer
+			.focus
+			.as_ref()
+			.is_some_and(|focus| focus.surface == *resource)
+		{
+			state.seat.pointer.focus = None;
+		}
+		if matches!(
+			&state.seat.pointer.cursor,
+			CursorImage::Surface { surface, .. } if surface == resource
+		) {
+			state.seat.pointer.cursor = CursorImage::Hidden;
+		}
+
+		let Some(surface) = state.compositor.surfaces.remove(&resource.id())
+		else {
+			return;
+		};
+
+		if let (Some(texture), Some(renderer)) =
+			(surface.texture, state.renderer.as_mut())
+		{
+			unsafe { renderer.destroy_texture(texture) }.unwrap_or_else(
+				|err| tracing::error!("Failed to destroy texture: {}", err),
+			);
+		}
+	}
+}
+
+impl Dispatch<WlRegion, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &WlRegion,
+		request: wl_region::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		let Some(region) = state.compositor.regions.get_mut(&resource.id())
+		else {
+			return;
+		};
+
+		match request {
+			wl_region::Request::Destroy => {}
+			wl_region::Request::Add {
+				x,
+				y,
+				width,
+				height,
+			} => region
+				.rects
+				.push((RegionOp::Add, Rectangle::new(x, y, width, height))),
+			wl_region::Request::Subtract {
+				x,
+				y,
+				width,
+				height,
+			} => region.rects.push((
+				RegionOp::Subtract,
+				Rectangle::new(x, y, width, height),
+			)),
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		resource: &WlRegion,
+		_data: &(),
+	) {
+		state.compositor.regions.remove(&resource.id());
+	}
+}
+
+impl Dispatch<WlCallback, ()> for ServerState {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &WlCallback,
+		_request: <WlCallback as Resource>::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+	}
+}
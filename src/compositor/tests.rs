@@ -0,0 +1,112 @@
+use std::{os::unix::net::UnixStream, sync::Arc};
+
+use wayland_server::{
+	protocol::{wl_buffer::WlBuffer, wl_surface::WlSurface},
+	Client, DataInit, Dispatch, Display, DisplayHandle, Resource,
+};
+
+use super::{BufferAssignment, Damage, Surface};
+use crate::{geometry::Rectangle, ClientState};
+
+struct Objects;
+
+impl Dispatch<WlSurface, ()> for Objects {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &WlSurface,
+		_request: <WlSurface as Resource>::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+	}
+}
+
+impl Dispatch<WlBuffer, ()> for Objects {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &WlBuffer,
+		_request: <WlBuffer as Resource>::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+	}
+}
+
+/// A surface of a new client of `display`, with a buffer of the same client
+/// attached to it.
+fn surface_with_buffer(display: &Display<Objects>) -> (Surface, WlBuffer) {
+	let mut handle = display.handle();
+	let (stream, _peer) = UnixStream::pair().unwrap();
+	let client = handle.insert_client(stream, Arc::new(ClientState)).unwrap();
+
+	let surface = client
+		.create_resource::<WlSurface, (), Objects>(&handle, 6, ())
+		.unwrap();
+	let buffer = client
+		.create_resource::<WlBuffer, (), Objects>(&handle, 1, ())
+		.unwrap();
+
+	let mut surface = Surface::new(surface);
+	surface.pending.buffer = Some(BufferAssignment::NewBuffer(buffer.clone()));
+	(surface, buffer)
+}
+
+fn cached_buffer(surface: &Surface) -> Option<&WlBuffer> {
+	match surface.cached.as_ref()?.buffer.as_ref()? {
+		BufferAssignment::NewBuffer(buffer) => Some(buffer),
+		BufferAssignment::Removed => None,
+	}
+}
+
+#[test]
+fn unready_buffer_does_not_delay_other_clients() {
+	let display = Display::<Objects>::new().unwrap();
+	let (mut blocked, blocked_buffer) = surface_with_buffer(&display);
+	let (mut other, other_buffer) = surface_with_buffer(&display);
+
+	blocked.queue_commit();
+	other.queue_commit();
+
+	let ready = |buffer: &WlBuffer| *buffer == other_buffer;
+	assert!(!blocked.unblock_commits(ready));
+	assert!(blocked.cached.is_none());
+	assert!(other.unblock_commits(ready));
+	assert_eq!(cached_buffer(&other), Some(&other_buffer));
+
+	// Later commits of the blocked surface wait behind it, even without a
+	// buffer of their own.
+	blocked
+		.pending
+		.damage
+		.push(Damage::Surface(Rectangle::new(0, 0, 10, 10)));
+	blocked.queue_commit();
+	assert!(!blocked.unblock_commits(ready));
+	assert_eq!(blocked.queued.len(), 2);
+
+	assert!(blocked.unblock_commits(|_| true));
+	assert!(blocked.queued.is_empty());
+	assert_eq!(cached_buffer(&blocked), Some(&blocked_buffer));
+	assert_eq!(blocked.cached.as_ref().unwrap().damage.len(), 1);
+}
+
+#[test]
+fn buffer_counts_until_detached() {
+	let display = Display::<Objects>::new().unwrap();
+	let (mut surface, _buffer) = surface_with_buffer(&display);
+	assert!(surface.has_buffer());
+
+	surface.queue_commit();
+	assert!(surface.has_buffer());
+	surface.unblock_commits(|_| true);
+	assert!(surface.has_buffer());
+
+	surface.pending.buffer = Some(BufferAssignment::Removed);
+	assert!(!surface.has_buffer());
+	surface.queue_commit();
+	surface.unblock_commits(|_| true);
+	assert!(!surface.has_buffer());
+}
@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+
+use crate::{geometry::Size, renderer::BlurSettings, space::FocusPolicy};
+
+const USAGE: &str = "\
+Usage: neora [options]
+
+Options:
+  --headless           Render offscreen instead of into a window
+  --size WxH           Output size when headless (default 1280x720)
+  --output [WxH][@S]   Add an output, a window unless headless, of logical
+                       size WxH and scale factor S; repeat for several
+  --refresh-rate HZ    Frame rate when headless (default 60)
+  --socket NAME        Name of the Wayland socket to listen on
+  --list-devices       Print the Vulkan devices and exit
+  --focus-follows-mouse
+                       Focus windows on hover instead of on click
+  --blur-radius PX     Blur behind window frames, 0 to disable (default 16)
+  --blur-saturation S  Saturation of the blur, 1 to keep colors (default 1.3)
+  --help               Print this message
+
+Environment:
+  NEORA_VK_DEVICE      Vulkan device to use, by name or UUID
+  NEORA_VK_VALIDATION  1 or 0 to enable or disable the validation layer";
+
+/// An output requested with `--output`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OutputConfig {
+	/// Logical size, or `None` for `--size` when headless and the default
+	/// window size otherwise.
+	pub size: Option<Size>,
+	/// Physical pixels per logical pixel, or `None` for the one of the host
+	/// window, or 1 when headless.
+	pub scale: Option<f64>,
+}
+
+impl OutputConfig {
+	/// Parses `[WxH][@S]`.
+	fn parse(value: &str) -> Option<Self> {
+		let (size, scale) = match value.split_once('@') {
+			Some((size, scale)) => (size, Some(scale)),
+			None => (value, None),
+		};
+
+		let size = if size.is_empty() {
+			None
+		} else {
+			Some(parse_size(size)?)
+		};
+		let scale = match scale {
+			Some(scale) => Some(
+				scale
+					.parse()
+					.ok()
+					.filter(|scale: &f64| scale.is_finite() && *scale > 0.0)?,
+			),
+			None => None,
+		};
+
+		Some(Self { size, scale })
+	}
+}
+
+/// Options given on the command line.
+#[derive(Clone, Debug)]
+pub struct Config {
+	/// Render into offscreen images instead of a window, e.g. in CI.
+	pub headless: bool,
+	/// Output size when headless. Otherwise it follows the window.
+	pub output_size: Size,
+	/// At least one, each shown in its own host window unless headless.
+	pub outputs: Vec<OutputConfig>,
+	/// Frames per second when headless. Otherwise the host paces frames.
+	pub refresh_rate: u32,
+	/// Socket to listen on, or `None` to pick the first free `wayland-N`.
+	pub socket: Option<String>,
+	/// Print the Vulkan devices instead of running.
+	pub list_devices: bool,
+	pub focus_policy: FocusPolicy,
+	pub blur: BlurSettings,
+}
+
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			headless: false,
+			output_size: Size::new(1280, 720),
+			outputs: vec![OutputConfig::default()],
+			refresh_rate: 60,
+			socket: None,
+			list_devices: false,
+			focus_policy: FocusPolicy::default(),
+			blur: BlurSettings::default(),
+		}
+	}
+}
+
+impl Config {
+	/// Parses the arguments of the process, printing the usage and exiting
+	/// on `--help`.
+	pub fn from_args() -> Result<Self> {
+		let mut config = Self::default();
+		let mut outputs = Vec::new();
+		let mut args = std::env::args().skip(1);
+
+		while let Some(arg) = args.next() {
+			let mut value = || {
+				args.next()
+					.with_context(|| format!("Missing value for {}", arg))
+			};
+
+			match arg.as_str() {
+				"--headless" => config.headless = true,
+				"--size" => {
+					let value = value()?;
+					config.output_size = parse_size(&value)
+						.with_context(|| format!("Invalid size {}", value))?;
+				}
+				"--output" => {
+					let value = value()?;
+					outputs.push(OutputConfig::parse(&value).with_context(
+						|| format!("Invalid output {}", value),
+					)?);
+				}
+				"--refresh-rate" => {
+					let value = value()?;
+					config.refresh_rate = value
+						.parse()
+						.ok()
+						.filter(|rate| *rate > 0)
+						.with_context(|| {
+							format!("Invalid refresh rate {}", value)
+						})?;
+				}
+				"--socket" => config.socket = Some(value()?),
+				"--list-devices" => config.list_devices = true,
+				"--focus-follows-mouse" => {
+					config.focus_policy = FocusPolicy::FocusFollowsMouse
+				}
+				"--blur-radius" => {
+					let value = value()?;
+					config.blur.radius = value
+						.parse()
+						.ok()
+						.filter(|radius: &f32| *radius >= 0.0)
+						.with_context(|| {
+							format!("Invalid blur radius {}", value)
+						})?;
+				}
+				"--blur-saturation" => {
+					let value = value()?;
+					config.blur.saturation = value
+						.parse()
+						.ok()
+						.filter(|saturation: &f32| *saturation >= 0.0)
+						.with_context(|| {
+							format!("Invalid blur saturation {}", value)
+						})?;
+				}
+				"--help" => {
+					println!("{}", USAGE);
+					std::process::exit(0);
+				}
+				_ => anyhow::bail!("Unknown argument {}\n\n{}", arg, USAGE),
+			}
+		}
+
+		if !outputs.is_empty() {
+			config.outputs = outputs;
+		}
+
+		Ok(config)
+	}
+
+	/// Time between two frames when headless.
+	pub fn frame_interval(&self) -> Duration {
+		Duration::from_secs(1) / self.refresh_rate
+	}
+}
+
+/// Parses a positive `WxH` size.
+fn parse_size(value: &str) -> Option<Size> {
+	value
+		.split_once('x')
+		.and_then(|(width, height)| {
+			Some(Size::new(width.parse().ok()?, height.parse().ok()?))
+		})
+		.filter(|size| size.width > 0 && size.height > 0)
+}
@@ -0,0 +1,97 @@
+use wayland_protocols::wp::cursor_shape::v1::server::{
+	wp_cursor_shape_device_v1::{self, WpCursorShapeDeviceV1},
+	wp_cursor_shape_manager_v1::{self, WpCursorShapeManagerV1},
+};
+use wayland_server::{
+	backend::GlobalId, protocol::wl_pointer::WlPointer, Client, DataInit,
+	Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+};
+
+use crate::{seat::CursorImage, ServerState};
+
+const CURSOR_SHAPE_VERSION: u32 = 1;
+
+/// State of the `wp_cursor_shape_manager_v1` global, letting clients pick a
+/// cursor from the compositor's theme instead of drawing their own.
+pub struct CursorShape {
+	pub global: GlobalId,
+}
+
+impl CursorShape {
+	pub fn new(display: &DisplayHandle) -> Self {
+		Self {
+			global: display
+				.create_global::<ServerState, WpCursorShapeManagerV1, ()>(
+					CURSOR_SHAPE_VERSION,
+					(),
+				),
+		}
+	}
+}
+
+impl GlobalDispatch<WpCursorShapeManagerV1, ()> for ServerState {
+	fn bind(
+		_state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<WpCursorShapeManagerV1>,
+		_global_data: &(),
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		data_init.init(resource, ());
+	}
+}
+
+impl Dispatch<WpCursorShapeManagerV1, ()> for ServerState {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &WpCursorShapeManagerV1,
+		request: wp_cursor_shape_manager_v1::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wp_cursor_shape_manager_v1::Request::Destroy => {}
+			wp_cursor_shape_manager_v1::Request::GetPointer {
+				cursor_shape_device,
+				pointer,
+			} => {
+				data_init.init(cursor_shape_device, pointer);
+			}
+			// There are no tablets to get a tool from.
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<WpCursorShapeDeviceV1, WlPointer> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &WpCursorShapeDeviceV1,
+		request: wp_cursor_shape_device_v1::Request,
+		data: &WlPointer,
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wp_cursor_shape_device_v1::Request::Destroy => {}
+			wp_cursor_shape_device_v1::Request::SetShape { serial, shape } => {
+				let WEnum::Value(shape) = shape else {
+					resource.post_error(
+						wp_cursor_shape_device_v1::Error::InvalidShape,
+						format!("invalid shape {:?}", shape),
+					);
+					return;
+				};
+
+				if state.seat.pointer.accepts_cursor_from(&data.id(), serial) {
+					state.seat.pointer.cursor = CursorImage::Named(shape);
+				}
+			}
+			_ => unreachable!(),
+		}
+	}
+}
@@ -0,0 +1,174 @@
+use wayland_protocols::xdg::decoration::zv1::server::{
+	zxdg_decoration_manager_v1::{self, ZxdgDecorationManagerV1},
+	zxdg_toplevel_decoration_v1::{self, Mode, ZxdgToplevelDecorationV1},
+};
+use wayland_server::{
+	backend::{ClientId, GlobalId},
+	protocol::wl_surface::WlSurface,
+	Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+	WEnum,
+};
+
+use crate::{xdg_shell::XdgRole, ServerState};
+
+mod frame;
+
+pub use frame::{DecorationPart, Insets};
+
+const DECORATION_MANAGER_VERSION: u32 = 1;
+
+/// State of the `zxdg_decoration_manager_v1` global, through which toplevels
+/// get the server-side title bars and borders drawn by [`frame`].
+pub struct Decoration {
+	pub global: GlobalId,
+}
+
+impl Decoration {
+	pub fn new(display: &DisplayHandle) -> Self {
+		Self {
+			global: display
+				.create_global::<ServerState, ZxdgDecorationManagerV1, ()>(
+					DECORATION_MANAGER_VERSION,
+					(),
+				),
+		}
+	}
+}
+
+impl ServerState {
+	/// Switches a toplevel between server-side and client-side decorations,
+	/// which the client learns from the next configure.
+	fn set_server_side_decorations(&mut self, surface: &WlSurface, ssd: bool) {
+		self.update_toplevel(&surface.id(), |toplevel, output| {
+			toplevel.requested.decorated = ssd;
+
+			// The title bar takes some of the space of maximized windows.
+			if toplevel.requested.maximized && !toplevel.requested.fullscreen {
+				toplevel.requested.size =
+					Some(Insets::maximized_area(output, ssd).size());
+			}
+		});
+	}
+}
+
+impl GlobalDispatch<ZxdgDecorationManagerV1, ()> for ServerState {
+	fn bind(
+		_state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<ZxdgDecorationManagerV1>,
+		_global_data: &(),
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		data_init.init(resource, ());
+	}
+}
+
+impl Dispatch<ZxdgDecorationManagerV1, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		_resource: &ZxdgDecorationManagerV1,
+		request: zxdg_decoration_manager_v1::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			zxdg_decoration_manager_v1::Request::Destroy => {}
+			zxdg_decoration_manager_v1::Request::GetToplevelDecoration {
+				id,
+				toplevel,
+			} => {
+				let Some(surface) = toplevel.data::<WlSurface>().cloned()
+				else {
+					return;
+				};
+				let decoration = data_init.init(id, surface.clone());
+
+				let Some(XdgRole::Toplevel(toplevel)) = state
+					.xdg_shell
+					.surfaces
+					.get_mut(&surface.id())
+					.map(|xdg| &mut xdg.role)
+				else {
+					return;
+				};
+
+				if toplevel.decoration.is_some() {
+					decoration.post_error(
+						zxdg_toplevel_decoration_v1::Error::AlreadyConstructed,
+						"the toplevel already has a decoration object",
+					);
+					return;
+				}
+				let has_buffer = state
+					.compositor
+					.surfaces
+					.get(&surface.id())
+					.is_some_and(|surface| surface.has_buffer());
+				if has_buffer {
+					decoration.post_error(
+						zxdg_toplevel_decoration_v1::Error::UnconfiguredBuffer,
+						"the toplevel already has a buffer attached",
+					);
+					return;
+				}
+
+				toplevel.decoration = Some(decoration);
+
+				// Decorations are server-side unless the client asks
+				// otherwise.
+				state.set_server_side_decorations(&surface, true);
+			}
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<ZxdgToplevelDecorationV1, WlSurface> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		_resource: &ZxdgToplevelDecorationV1,
+		request: zxdg_toplevel_decoration_v1::Request,
+		data: &WlSurface,
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			zxdg_toplevel_decoration_v1::Request::Destroy => {}
+			zxdg_toplevel_decoration_v1::Request::SetMode { mode } => {
+				let ssd = !matches!(mode, WEnum::Value(Mode::ClientSide));
+				state.set_server_side_decorations(data, ssd);
+			}
+			zxdg_toplevel_decoration_v1::Request::UnsetMode => {
+				state.set_server_side_decorations(data, true);
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		resource: &ZxdgToplevelDecorationV1,
+		data: &WlSurface,
+	) {
+		let Some(XdgRole::Toplevel(toplevel)) = state
+			.xdg_shell
+			.surfaces
+			.get_mut(&data.id())
+			.map(|xdg| &mut xdg.role)
+		else {
+			return;
+		};
+		if toplevel.decoration.as_ref() != Some(resource) {
+			return;
+		}
+
+		// Without the object, the client draws its own decorations again.
+		toplevel.decoration = None;
+		state.set_server_side_decorations(data, false);
+	}
+}
@@ -0,0 +1,377 @@
+//! Server-side title bars and borders, drawn as gradients with the window
+//! buttons on top. There is no font rendering, so the title bar does not
+//! show the title of the window.
+
+use wayland_protocols::{
+	wp::cursor_shape::v1::server::wp_cursor_shape_device_v1::Shape,
+	xdg::shell::server::xdg_toplevel::ResizeEdge,
+};
+use wayland_server::backend::ObjectId;
+
+use crate::{
+	geometry::{Point, Rectangle},
+	renderer::{Fill, SceneElement, TextureTransform},
+	space::{resize_cursor, GrabKind},
+	xdg_shell::{ToplevelState, XdgRole},
+	ServerState,
+};
+
+/// Height of the title bar above the window geometry.
+const TITLE_BAR_HEIGHT: i32 = 30;
+/// Width of the borders on the other sides, which double as resize handles.
+const BORDER_WIDTH: i32 = 6;
+/// Distance from a corner of the frame within which both edges are dragged.
+const CORNER_SIZE: i32 = 20;
+const CORNER_RADIUS: f32 = 8.0;
+const BUTTON_SIZE: i32 = 14;
+const BUTTON_SPACING: i32 = 8;
+
+/// Premultiplies a color given with straight alpha.
+const fn glass(r: f32, g: f32, b: f32, a: f32) -> [f32; 4] {
+	[r * a, g * a, b * a, a]
+}
+
+// Frames are translucent blue gradients when active and grey ones
+// otherwise, with a gloss over the upper half of the title bar.
+const ACTIVE_TOP: [f32; 4] = glass(0.45, 0.7, 1.0, 0.7);
+const ACTIVE_BOTTOM: [f32; 4] = glass(0.15, 0.35, 0.75, 0.55);
+const INACTIVE_TOP: [f32; 4] = glass(0.7, 0.75, 0.82, 0.55);
+const INACTIVE_BOTTOM: [f32; 4] = glass(0.45, 0.5, 0.6, 0.45);
+const GLOSS_TOP: [f32; 4] = glass(1.0, 1.0, 1.0, 0.25);
+const GLOSS_BOTTOM: [f32; 4] = glass(1.0, 1.0, 1.0, 0.05);
+
+/// Space taken around the window geometry by server-side decorations.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Insets {
+	pub left: i32,
+	pub top: i32,
+	pub right: i32,
+	pub bottom: i32,
+}
+
+impl Insets {
+	/// Decorations of a toplevel in `state`: none when fullscreen or
+	/// client-side, only the title bar when maximized.
+	pub fn for_state(state: ToplevelState) -> Self {
+		if !state.decorated || state.fullscreen {
+			Self::default()
+		} else if state.maximized {
+			Self {
+				top: TITLE_BAR_HEIGHT,
+				..Self::default()
+			}
+		} else {
+			Self {
+				left: BORDER_WIDTH,
+				top: TITLE_BAR_HEIGHT,
+				right: BORDER_WIDTH,
+				bottom: BORDER_WIDTH,
+			}
+		}
+	}
+
+	/// `rect` grown by the insets.
+	pub fn outset(self, rect: Rectangle) -> Rectangle {
+		Rectangle::new(
+			rect.x - self.left,
+			rect.y - self.top,
+			rect.width + self.left + self.right,
+			rect.height + self.top + self.bottom,
+		)
+	}
+
+	/// Part of `output` left to the window geometry of a maximized toplevel.
+	pub fn maximized_area(output: Rectangle, decorated: bool) -> Rectangle {
+		let insets = Self::for_state(ToplevelState {
+			maximized: true,
+			decorated,
+			..ToplevelState::default()
+		});
+
+		Rectangle::new(
+			output.x,
+			output.y + insets.top,
+			output.width,
+			output.height - insets.top,
+		)
+	}
+}
+
+/// Interactive areas of a server-side frame.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecorationPart {
+	TitleBar,
+	Edge(ResizeEdge),
+	Close,
+	Maximize,
+	Minimize,
+}
+
+impl DecorationPart {
+	const BUTTONS: [Self; 3] = [Self::Close, Self::Maximize, Self::Minimize];
+
+	/// Title bar buttons are circles at the right end, from right to left
+	/// in the order of [`DecorationPart::BUTTONS`].
+	fn button_geometry(self, frame: Rectangle) -> Option<Rectangle> {
+		let index = Self::BUTTONS.iter().position(|part| *part == self)? as i32;
+		let right = frame.x + frame.width - BORDER_WIDTH - BUTTON_SPACING;
+
+		Some(Rectangle::new(
+			right - (index + 1) * BUTTON_SIZE - index * BUTTON_SPACING,
+			frame.y + (TITLE_BAR_HEIGHT - BUTTON_SIZE) / 2,
+			BUTTON_SIZE,
+			BUTTON_SIZE,
+		))
+	}
+
+	/// Neon color of a button.
+	fn button_color(self) -> [f32; 3] {
+		match self {
+			Self::Close => [1.0, 0.2, 0.45],
+			Self::Maximize => [0.2, 0.9, 1.0],
+			_ => [0.75, 0.4, 1.0],
+		}
+	}
+}
+
+/// The edges dragged from `point` on the borders of `frame`, if any.
+fn edges_at(frame: Rectangle, point: Point) -> Option<ResizeEdge> {
+	let near = |distance: i32, within: i32| distance < within;
+	let right = frame.x + frame.width - 1;
+	let bottom = frame.y + frame.height - 1;
+
+	let on_left = near(point.x - frame.x, BORDER_WIDTH);
+	let on_right = near(right - point.x, BORDER_WIDTH);
+	let on_top = near(point.y - frame.y, BORDER_WIDTH);
+	let on_bottom = near(bottom - point.y, BORDER_WIDTH);
+
+	// Near a corner, grabbing either border drags both edges.
+	let sides = on_left || on_right;
+	let ends = on_top || on_bottom;
+	let left = on_left || (ends && near(point.x - frame.x, CORNER_SIZE));
+	let right = on_right || (ends && near(right - point.x, CORNER_SIZE));
+	let top = on_top || (sides && near(point.y - frame.y, CORNER_SIZE));
+	let bottom = on_bottom || (sides && near(bottom - point.y, CORNER_SIZE));
+
+	match (left, right, top, bottom) {
+		(true, _, true, _) => Some(ResizeEdge::TopLeft),
+		(_, true, true, _) => Some(ResizeEdge::TopRight),
+		(true, _, _, true) => Some(ResizeEdge::BottomLeft),
+		(_, true, _, true) => Some(ResizeEdge::BottomRight),
+		(true, _, _, _) => Some(ResizeEdge::Left),
+		(_, true, _, _) => Some(ResizeEdge::Right),
+		(_, _, true, _) => Some(ResizeEdge::Top),
+		(_, _, _, true) => Some(ResizeEdge::Bottom),
+		_ => None,
+	}
+}
+
+impl ServerState {
+	/// State last acknowledged by the toplevel `id`.
+	fn acked_toplevel_state(&self, id: &ObjectId) -> Option<ToplevelState> {
+		match &self.xdg_shell.surfaces.get(id)?.role {
+			XdgRole::Toplevel(toplevel) => Some(toplevel.current),
+			_ => None,
+		}
+	}
+
+	/// Decorations currently drawn around the toplevel `id`.
+	pub fn decoration_insets(&self, id: &ObjectId) -> Insets {
+		self.acked_toplevel_state(id)
+			.map(Insets::for_state)
+			.unwrap_or_default()
+	}
+
+	/// Global geometry of a mapped toplevel including its decorations.
+	pub fn frame_geometry(&self, id: &ObjectId) -> Option<Rectangle> {
+		Some(self.decoration_insets(id).outset(self.window_geometry(id)?))
+	}
+
+	/// The part of the decorations of the toplevel `id` at `point`, ignoring
+	/// the windows above.
+	pub fn decoration_part_at(
+		&self,
+		id: &ObjectId,
+		point: Point,
+	) -> Option<DecorationPart> {
+		let state = self.acked_toplevel_state(id)?;
+		let content = self.window_geometry(id)?;
+		let frame = Insets::for_state(state).outset(content);
+
+		if !frame.contains(point) || content.contains(point) {
+			return None;
+		}
+
+		let button = DecorationPart::BUTTONS.into_iter().find(|part| {
+			part.button_geometry(frame)
+				.is_some_and(|geometry| geometry.contains(point))
+		});
+		if button.is_some() {
+			return button;
+		}
+
+		match edges_at(frame, point).filter(|_| !state.maximized) {
+			Some(edges) => Some(DecorationPart::Edge(edges)),
+			None => Some(DecorationPart::TitleBar),
+		}
+	}
+
+	/// The window whose decorations are topmost at `(x, y)`, with the part
+	/// there.
+	pub fn decoration_under(
+		&self,
+		x: f64,
+		y: f64,
+	) -> Option<(ObjectId, DecorationPart)> {
+		let point = Point::new(x.floor() as i32, y.floor() as i32);
+
+		for window in self.space.visible().rev() {
+			let covered = self.window_surfaces(&window.surface).iter().any(
+				|(id, geometry)| {
+					self.compositor.surfaces.get(id).is_some_and(|surface| {
+						surface.accepts_input(point - geometry.loc())
+					})
+				},
+			);
+			if covered {
+				return None;
+			}
+
+			if let Some(part) = self.decoration_part_at(&window.surface, point)
+			{
+				return Some((window.surface.clone(), part));
+			}
+		}
+
+		None
+	}
+
+	/// Cursor shown over the decorations at `(x, y)`.
+	pub fn decoration_cursor(&self, x: f64, y: f64) -> Shape {
+		match self.decoration_under(x, y) {
+			Some((_, DecorationPart::Edge(edges))) => resize_cursor(edges),
+			_ => Shape::Default,
+		}
+	}
+
+	/// Handles a press of the left button on the decorations of `window`.
+	pub fn press_decoration(
+		&mut self,
+		window: &ObjectId,
+		part: DecorationPart,
+	) {
+		self.activate_window(window);
+
+		match part {
+			DecorationPart::TitleBar => {
+				self.start_grab(window, GrabKind::Move, Shape::Move);
+			}
+			DecorationPart::Edge(edges) => {
+				let kind = GrabKind::Resize {
+					edges,
+					pending: None,
+				};
+				self.start_grab(window, kind, resize_cursor(edges));
+			}
+			DecorationPart::Close => {
+				if let Some(XdgRole::Toplevel(toplevel)) =
+					self.xdg_shell.surfaces.get(window).map(|xdg| &xdg.role)
+				{
+					toplevel.handle.close();
+				}
+			}
+			DecorationPart::Maximize => {
+				let maximized = self
+					.acked_toplevel_state(window)
+					.is_some_and(|state| state.maximized);
+				self.set_maximized(window, !maximized);
+			}
+			DecorationPart::Minimize => self.minimize_window(window),
+		}
+	}
+
+	/// Quads drawing the decorations of the toplevel `id`, from bottom to
+	/// top.
+	pub fn decoration_elements(&self, id: &ObjectId) -> Vec<SceneElement> {
+		let (Some(state), Some(content)) =
+			(self.acked_toplevel_state(id), self.window_geometry(id))
+		else {
+			return Vec::new();
+		};
+		let insets = Insets::for_state(state);
+		if insets == Insets::default() {
+			return Vec::new();
+		}
+
+		let frame = insets.outset(content);
+		let corner_radius = if state.maximized { 0.0 } else { CORNER_RADIUS };
+		let (top, bottom) = if state.activated {
+			(ACTIVE_TOP, ACTIVE_BOTTOM)
+		} else {
+			(INACTIVE_TOP, INACTIVE_BOTTOM)
+		};
+
+		let mut elements = vec![
+			SceneElement {
+				fill: Fill::Gradient { top, bottom },
+				geometry: frame,
+				source: SceneElement::WHOLE_TEXTURE,
+				transform: TextureTransform::Normal,
+				opacity: 1.0,
+				corner_radius,
+				blur: true,
+			},
+			SceneElement {
+				fill: Fill::Gradient {
+					top: GLOSS_TOP,
+					bottom: GLOSS_BOTTOM,
+				},
+				geometry: Rectangle::new(
+					frame.x,
+					frame.y,
+					frame.width,
+					TITLE_BAR_HEIGHT / 2,
+				),
+				source: SceneElement::WHOLE_TEXTURE,
+				transform: TextureTransform::Normal,
+				opacity: 1.0,
+				corner_radius,
+				blur: false,
+			},
+		];
+
+		let hovered = self
+			.seat
+			.pointer
+			.location
+			.and_then(|(x, y)| self.decoration_under(x, y))
+			.filter(|(window, _)| window == id)
+			.map(|(_, part)| part);
+
+		for part in DecorationPart::BUTTONS {
+			let Some(geometry) = part.button_geometry(frame) else {
+				continue;
+			};
+			let [r, g, b] = part.button_color();
+
+			elements.push(SceneElement {
+				fill: Fill::Gradient {
+					top: glass(r, g, b, 1.0),
+					bottom: glass(r * 0.7, g * 0.7, b * 0.7, 1.0),
+				},
+				geometry,
+				source: SceneElement::WHOLE_TEXTURE,
+				transform: TextureTransform::Normal,
+				opacity: match (hovered == Some(part), state.activated) {
+					(true, _) => 1.0,
+					(false, true) => 0.75,
+					(false, false) => 0.4,
+				},
+				corner_radius: BUTTON_SIZE as f32 / 2.0,
+				blur: false,
+			});
+		}
+
+		elements
+	}
+}
@@ -0,0 +1,554 @@
+use std::{
+	io::Write as _,
+	os::fd::{AsFd as _, BorrowedFd, OwnedFd},
+	sync::Mutex,
+};
+
+use anyhow::Result;
+use rustix::event::{poll, PollFd, PollFlags};
+use vulkanalia::vk;
+use wayland_protocols::wp::linux_dmabuf::zv1::server::{
+	zwp_linux_buffer_params_v1::{self, Flags, ZwpLinuxBufferParamsV1},
+	zwp_linux_dmabuf_feedback_v1::{self, ZwpLinuxDmabufFeedbackV1},
+	zwp_linux_dmabuf_v1::{self, ZwpLinuxDmabufV1},
+};
+use wayland_server::{
+	backend::{ClientId, GlobalId},
+	protocol::wl_buffer::{self, WlBuffer},
+	Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+	WEnum,
+};
+
+use crate::{
+	compositor::BufferAssignment,
+	geometry::Size,
+	renderer::{Dmabuf, DmabufPlane, ExternalImageId, Renderer},
+	ServerState,
+};
+
+/// Version 4 adds feedback, which needs the DRM device of the renderer.
+const DMABUF_VERSION: u32 = 4;
+/// Without feedback, formats and modifiers are sent on bind.
+const DMABUF_VERSION_WITHOUT_DEVICE: u32 = 3;
+const MAX_PLANES: usize = 4;
+/// Modifier of buffers whose layout is only known to the driver.
+const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+	u32::from_le_bytes(*code)
+}
+
+/// DRM formats that map directly onto a Vulkan format, with whether their
+/// alpha channel must be ignored. Like wl_shm formats, they are
+/// little-endian.
+const FORMATS: &[(u32, vk::Format, bool)] = &[
+	(fourcc(b"AR24"), vk::Format::B8G8R8A8_UNORM, false),
+	(fourcc(b"XR24"), vk::Format::B8G8R8A8_UNORM, true),
+	(fourcc(b"AB24"), vk::Format::R8G8B8A8_UNORM, false),
+	(fourcc(b"XB24"), vk::Format::R8G8B8A8_UNORM, true),
+];
+
+/// State of the `zwp_linux_dmabuf_v1` global, through which clients share
+/// buffers in GPU memory. Only created if the renderer can import them, so
+/// clients fall back to wl_shm otherwise.
+pub struct LinuxDmabuf {
+	pub global: GlobalId,
+	/// Supported DRM formats with their modifiers, in the order of the
+	/// format table.
+	pub formats: Vec<(u32, u64)>,
+	/// Sealed memfd holding `formats` as the feedback format table.
+	pub format_table: OwnedFd,
+	/// `dev_t` of the DRM node of the renderer, needed for feedback.
+	pub main_device: Option<u64>,
+}
+
+impl LinuxDmabuf {
+	/// Creates the global if `renderer` can import some of [`FORMATS`].
+	pub fn new(
+		display: &DisplayHandle,
+		renderer: &Renderer,
+	) -> Result<Option<Self>> {
+		let Some(support) = &renderer.dmabuf else {
+			return Ok(None);
+		};
+
+		let formats = FORMATS
+			.iter()
+			.flat_map(|(fourcc, format, _)| {
+				let modifiers = unsafe { renderer.dmabuf_modifiers(*format) };
+				modifiers.into_iter().map(|modifier| (*fourcc, modifier))
+			})
+			.collect::<Vec<_>>();
+		if formats.is_empty() {
+			tracing::info!("No dmabuf format can be imported");
+			return Ok(None);
+		}
+
+		let version = match support.main_device {
+			Some(_) => DMABUF_VERSION,
+			None => DMABUF_VERSION_WITHOUT_DEVICE,
+		};
+
+		Ok(Some(Self {
+			global: display.create_global::<ServerState, ZwpLinuxDmabufV1, ()>(
+				version,
+				(),
+			),
+			format_table: create_format_table(&formats)?,
+			formats,
+			main_device: support.main_device,
+		}))
+	}
+
+	/// Sends the formats to `feedback`, all of them in a single tranche for
+	/// the main device.
+	fn send_feedback(&self, feedback: &ZwpLinuxDmabufFeedbackV1) {
+		let Some(device) = self.main_device else {
+			return;
+		};
+		let device = device.to_ne_bytes().to_vec();
+		let indices = (0..self.formats.len() as u16)
+			.flat_map(u16::to_ne_bytes)
+			.collect();
+
+		feedback.format_table(
+			self.format_table.as_fd(),
+			(self.formats.len() * FORMAT_TABLE_ENTRY_SIZE) as u32,
+		);
+		feedback.main_device(device.clone());
+		feedback.tranche_target_device(device);
+		feedback.tranche_formats(indices);
+		feedback
+			.tranche_flags(zwp_linux_dmabuf_feedback_v1::TrancheFlags::empty());
+		feedback.tranche_done();
+		feedback.done();
+	}
+}
+
+/// Size of an entry of the format table: the format, 4 bytes of padding and
+/// the modifier.
+const FORMAT_TABLE_ENTRY_SIZE: usize = 16;
+
+/// Writes `formats` into a sealed memfd that clients map read-only.
+fn create_format_table(formats: &[(u32, u64)]) -> Result<OwnedFd> {
+	use rustix::fs::{MemfdFlags, SealFlags};
+
+	let fd = rustix::fs::memfd_create(
+		"neora-dmabuf-format-table",
+		MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING,
+	)?;
+
+	let mut file = std::fs::File::from(fd);
+	for (format, modifier) in formats {
+		file.write_all(&format.to_ne_bytes())?;
+		file.write_all(&[0; 4])?;
+		file.write_all(&modifier.to_ne_bytes())?;
+	}
+
+	let fd = OwnedFd::from(file);
+	rustix::fs::fcntl_add_seals(
+		&fd,
+		SealFlags::SHRINK
+			| SealFlags::GROW
+			| SealFlags::WRITE
+			| SealFlags::SEAL,
+	)?;
+
+	Ok(fd)
+}
+
+/// User data of a `zwp_linux_buffer_params_v1`.
+#[derive(Default)]
+pub struct BufferParams {
+	/// Planes by index, with the modifier given for each.
+	planes: [Option<(DmabufPlane, u64)>; MAX_PLANES],
+	/// Set by the create requests, after which the object is useless.
+	used: bool,
+}
+
+/// User data of a `wl_buffer` created from a dmabuf.
+pub struct DmabufBuffer {
+	pub dmabuf: Dmabuf,
+	pub image: ExternalImageId,
+}
+
+impl DmabufBuffer {
+	pub fn size(&self) -> Size {
+		Size::new(self.dmabuf.width as i32, self.dmabuf.height as i32)
+	}
+
+	/// Whether the fences attached to the buffer by the client's GPU work
+	/// signalled, which Vulkan does not wait for on its own.
+	pub fn is_ready(&self) -> bool {
+		let mut fds = self
+			.dmabuf
+			.planes
+			.iter()
+			.map(|plane| PollFd::new(&plane.fd, PollFlags::IN))
+			.collect::<Vec<_>>();
+
+		// A dmabuf becomes readable once the writes to it have completed.
+		match poll(&mut fds, 0) {
+			Ok(_) => fds.iter().all(|fd| fd.revents().contains(PollFlags::IN)),
+			Err(err) => {
+				tracing::warn!("Failed to poll dmabuf fences: {}", err);
+				true
+			}
+		}
+	}
+}
+
+impl ServerState {
+	/// Descriptors of the dmabufs that queued commits wait for, which become
+	/// readable once the client finished drawing into them.
+	pub fn fence_fds(&self) -> Vec<BorrowedFd<'_>> {
+		self.compositor
+			.surfaces
+			.values()
+			.filter_map(|surface| match &surface.queued.front()?.buffer {
+				Some(BufferAssignment::NewBuffer(buffer)) => {
+					buffer.data::<DmabufBuffer>()
+				}
+				_ => None,
+			})
+			.flat_map(|data| data.dmabuf.planes.iter())
+			.map(|plane| plane.fd.as_fd())
+			.collect()
+	}
+
+	/// Creates the dmabuf global once there is a renderer to import with.
+	pub fn init_dmabuf(&mut self, display: &DisplayHandle) {
+		let Some(renderer) = &self.renderer else {
+			return;
+		};
+		if self.dmabuf.is_some() {
+			return;
+		}
+
+		self.dmabuf =
+			LinuxDmabuf::new(display, renderer).unwrap_or_else(|err| {
+				tracing::error!("Failed to create dmabuf global: {}", err);
+				None
+			});
+	}
+
+	/// Imports the dmabuf described by `params`, or returns why it cannot
+	/// be, with the error to post if it is a protocol error.
+	fn create_dmabuf_buffer(
+		&mut self,
+		params: &BufferParams,
+		width: i32,
+		height: i32,
+		format: u32,
+		flags: WEnum<Flags>,
+	) -> Result<DmabufBuffer, (Option<zwp_linux_buffer_params_v1::Error>, String)>
+	{
+		use zwp_linux_buffer_params_v1::Error;
+
+		let planes = params
+			.planes
+			.iter()
+			.map_while(Option::as_ref)
+			.collect::<Vec<_>>();
+		if planes.is_empty()
+			|| params.planes[planes.len()..].iter().any(Option::is_some)
+		{
+			return Err((Some(Error::Incomplete), "missing planes".into()));
+		}
+
+		let modifier = planes[0].1;
+		if planes.iter().any(|(_, m)| *m != modifier) {
+			return Err((
+				Some(Error::InvalidFormat),
+				"planes have different modifiers".into(),
+			));
+		}
+
+		let dmabuf = self.dmabuf.as_ref().unwrap();
+		let vk_format = FORMATS.iter().find(|(fourcc, ..)| *fourcc == format);
+		let Some((_, vk_format, opaque)) =
+			vk_format.filter(|_| dmabuf.formats.contains(&(format, modifier)))
+		else {
+			return Err((
+				Some(Error::InvalidFormat),
+				format!(
+					"unsupported format {:#010x} with modifier {:#x}",
+					format, modifier
+				),
+			));
+		};
+
+		if width <= 0 || height <= 0 {
+			return Err((
+				Some(Error::InvalidDimensions),
+				format!("invalid size {}x{}", width, height),
+			));
+		}
+
+		for (plane, _) in &planes {
+			let end = plane.offset as u64 + plane.stride as u64 * height as u64;
+			let size =
+				rustix::fs::seek(&plane.fd, rustix::fs::SeekFrom::End(0));
+			if size.is_ok_and(|size| end > size) {
+				return Err((
+					Some(Error::OutOfBounds),
+					format!(
+						"plane at offset {} with stride {} exceeds the dmabuf",
+						plane.offset, plane.stride
+					),
+				));
+			}
+		}
+
+		let flags = match flags {
+			WEnum::Value(flags) => flags,
+			WEnum::Unknown(flags) => Flags::from_bits_truncate(flags),
+		};
+		if flags.intersects(Flags::Interlaced | Flags::BottomFirst) {
+			return Err((None, "interlaced buffers are not supported".into()));
+		}
+
+		let planes = planes
+			.into_iter()
+			.map(|(plane, _)| {
+				Ok(DmabufPlane {
+					fd: plane.fd.try_clone()?,
+					offset: plane.offset,
+					stride: plane.stride,
+				})
+			})
+			.collect::<std::io::Result<_>>()
+			.map_err(|err| (None, err.to_string()))?;
+
+		let dmabuf = Dmabuf {
+			width: width as u32,
+			height: height as u32,
+			format: *vk_format,
+			opaque: *opaque,
+			modifier,
+			planes,
+			y_invert: flags.contains(Flags::YInvert),
+		};
+
+		let renderer = self.renderer.as_mut().unwrap();
+		match unsafe { renderer.import_dmabuf(&dmabuf) } {
+			Ok(image) => Ok(DmabufBuffer { dmabuf, image }),
+			Err(err) => Err((None, err.to_string())),
+		}
+	}
+}
+
+impl GlobalDispatch<ZwpLinuxDmabufV1, ()> for ServerState {
+	fn bind(
+		state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<ZwpLinuxDmabufV1>,
+		_global_data: &(),
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		let dmabuf = data_init.init(resource, ());
+
+		// Later versions get the formats through feedback instead, and
+		// earlier ones cannot be told about modifiers, so they only get the
+		// formats supported with the implicit one.
+		let formats = &state.dmabuf.as_ref().unwrap().formats;
+		match dmabuf.version() {
+			..=2 => {
+				for (format, modifier) in formats {
+					if *modifier == DRM_FORMAT_MOD_INVALID {
+						dmabuf.format(*format);
+					}
+				}
+			}
+			3 => {
+				for (format, modifier) in formats {
+					dmabuf.modifier(
+						*format,
+						(modifier >> 32) as u32,
+						*modifier as u32,
+					);
+				}
+			}
+			_ => {}
+		}
+	}
+}
+
+impl Dispatch<ZwpLinuxDmabufV1, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		_resource: &ZwpLinuxDmabufV1,
+		request: zwp_linux_dmabuf_v1::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			zwp_linux_dmabuf_v1::Request::Destroy => {}
+			zwp_linux_dmabuf_v1::Request::CreateParams { params_id } => {
+				data_init.init(params_id, Mutex::new(BufferParams::default()));
+			}
+			// Every surface gets the same feedback, as nothing is scanned
+			// out directly.
+			zwp_linux_dmabuf_v1::Request::GetDefaultFeedback { id }
+			| zwp_linux_dmabuf_v1::Request::GetSurfaceFeedback { id, .. } => {
+				let feedback = data_init.init(id, ());
+				state.dmabuf.as_ref().unwrap().send_feedback(&feedback);
+			}
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<ZwpLinuxBufferParamsV1, Mutex<BufferParams>> for ServerState {
+	fn request(
+		state: &mut Self,
+		client: &Client,
+		resource: &ZwpLinuxBufferParamsV1,
+		request: zwp_linux_buffer_params_v1::Request,
+		data: &Mutex<BufferParams>,
+		dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		use zwp_linux_buffer_params_v1::Error;
+
+		let mut params = data.lock().unwrap();
+		let create = matches!(
+			request,
+			zwp_linux_buffer_params_v1::Request::Create { .. }
+				| zwp_linux_buffer_params_v1::Request::CreateImmed { .. }
+		);
+		if params.used
+			&& !matches!(request, zwp_linux_buffer_params_v1::Request::Destroy)
+		{
+			resource.post_error(
+				Error::AlreadyUsed,
+				"the params were already used to create a buffer",
+			);
+			return;
+		}
+		params.used |= create;
+
+		match request {
+			zwp_linux_buffer_params_v1::Request::Destroy => {}
+			zwp_linux_buffer_params_v1::Request::Add {
+				fd,
+				plane_idx,
+				offset,
+				stride,
+				modifier_hi,
+				modifier_lo,
+			} => {
+				let Some(plane) = params.planes.get_mut(plane_idx as usize)
+				else {
+					resource.post_error(
+						Error::PlaneIdx,
+						format!("plane index {} is too large", plane_idx),
+					);
+					return;
+				};
+				if plane.is_some() {
+					resource.post_error(
+						Error::PlaneSet,
+						format!("plane {} was already set", plane_idx),
+					);
+					return;
+				}
+
+				let modifier = (modifier_hi as u64) << 32 | modifier_lo as u64;
+				*plane = Some((DmabufPlane { fd, offset, stride }, modifier));
+			}
+			zwp_linux_buffer_params_v1::Request::Create {
+				width,
+				height,
+				format,
+				flags,
+			} => match state
+				.create_dmabuf_buffer(&params, width, height, format, flags)
+			{
+				Ok(buffer) => {
+					match client.create_resource::<WlBuffer, _, ServerState>(
+						dhandle, 1, buffer,
+					) {
+						Ok(buffer) => resource.created(&buffer),
+						Err(_) => resource.failed(),
+					}
+				}
+				Err((Some(error), message)) => {
+					resource.post_error(error, message)
+				}
+				Err((None, message)) => {
+					tracing::debug!("Failed to import dmabuf: {}", message);
+					resource.failed();
+				}
+			},
+			zwp_linux_buffer_params_v1::Request::CreateImmed {
+				buffer_id,
+				width,
+				height,
+				format,
+				flags,
+			} => match state
+				.create_dmabuf_buffer(&params, width, height, format, flags)
+			{
+				Ok(buffer) => {
+					data_init.init(buffer_id, buffer);
+				}
+				// The buffer cannot be marked as failed, so the client is
+				// disconnected instead.
+				Err((error, message)) => resource.post_error(
+					error.unwrap_or(Error::InvalidWlBuffer),
+					message,
+				),
+			},
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<ZwpLinuxDmabufFeedbackV1, ()> for ServerState {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &ZwpLinuxDmabufFeedbackV1,
+		request: zwp_linux_dmabuf_feedback_v1::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			zwp_linux_dmabuf_feedback_v1::Request::Destroy => {}
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<WlBuffer, DmabufBuffer> for ServerState {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &WlBuffer,
+		request: wl_buffer::Request,
+		_data: &DmabufBuffer,
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wl_buffer::Request::Destroy => {}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		_resource: &WlBuffer,
+		data: &DmabufBuffer,
+	) {
+		if let Some(renderer) = state.renderer.as_mut() {
+			unsafe { renderer.destroy_external_image(data.image) };
+		}
+	}
+}
@@ -0,0 +1,100 @@
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Point {
+	pub x: i32,
+	pub y: i32,
+}
+
+impl Point {
+	pub fn new(x: i32, y: i32) -> Self {
+		Self { x, y }
+	}
+}
+
+impl std::ops::Add for Point {
+	type Output = Self;
+
+	fn add(self, other: Self) -> Self {
+		Self::new(self.x + other.x, self.y + other.y)
+	}
+}
+
+impl std::ops::Sub for Point {
+	type Output = Self;
+
+	fn sub(self, other: Self) -> Self {
+		Self::new(self.x - other.x, self.y - other.y)
+	}
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rectangle {
+	pub x: i32,
+	pub y: i32,
+	pub width: i32,
+	pub height: i32,
+}
+
+impl Rectangle {
+	pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+		Self {
+			x,
+			y,
+			width,
+			height,
+		}
+	}
+
+	pub fn from_loc_and_size(loc: Point, size: Size) -> Self {
+		Self::new(loc.x, loc.y, size.width, size.height)
+	}
+
+	pub fn loc(&self) -> Point {
+		Point::new(self.x, self.y)
+	}
+
+	pub fn size(&self) -> Size {
+		Size::new(self.width, self.height)
+	}
+
+	pub fn center(&self) -> Point {
+		Point::new(self.x + self.width / 2, self.y + self.height / 2)
+	}
+
+	pub fn contains(&self, point: Point) -> bool {
+		point.x >= self.x
+			&& point.y >= self.y
+			&& point.x < self.x + self.width
+			&& point.y < self.y + self.height
+	}
+
+	/// Whether the two rectangles share at least one pixel.
+	pub fn overlaps(&self, other: Rectangle) -> bool {
+		self.x < other.x + other.width
+			&& other.x < self.x + self.width
+			&& self.y < other.y + other.height
+			&& other.y < self.y + self.height
+	}
+
+	/// Scales the rectangle, rounding its edges so that adjacent rectangles
+	/// stay adjacent.
+	pub fn scale(&self, factor: f64) -> Rectangle {
+		let x = (self.x as f64 * factor).round() as i32;
+		let y = (self.y as f64 * factor).round() as i32;
+		let right = ((self.x + self.width) as f64 * factor).round() as i32;
+		let bottom = ((self.y + self.height) as f64 * factor).round() as i32;
+
+		Rectangle::new(x, y, right - x, bottom - y)
+	}
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Size {
+	pub width: i32,
+	pub height: i32,
+}
+
+impl Size {
+	pub fn new(width: i32, height: i32) -> Self {
+		Self { width, height }
+	}
+}
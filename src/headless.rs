@@ -0,0 +1,69 @@
+use std::{
+	io,
+	os::fd::AsFd as _,
+	time::{Duration, Instant},
+};
+
+use rustix::event::{poll, PollFd, PollFlags};
+use wayland_server::{Display, ListeningSocket};
+
+use crate::{config::Config, dispatch_wayland, ServerState};
+
+/// Runs the compositor without a window, dispatching clients in between
+/// frames rendered at the configured refresh rate. Only returns on errors.
+pub fn run(
+	display: &mut Display<ServerState>,
+	socket: &ListeningSocket,
+	state: &mut ServerState,
+	config: &Config,
+) -> io::Result<()> {
+	let interval = config.frame_interval();
+	let mut next_frame = Instant::now();
+
+	loop {
+		let timeout = next_frame.saturating_duration_since(Instant::now());
+
+		let readable = {
+			let sources = [socket.as_fd(), display.backend().poll_fd()];
+			let fences = state.fence_fds();
+			let mut poll_fds = sources
+				.iter()
+				.chain(&fences)
+				.map(|fd| PollFd::new(fd, PollFlags::IN))
+				.collect::<Vec<_>>();
+
+			match poll(&mut poll_fds, poll_timeout(timeout)) {
+				Ok(count) => count > 0,
+				Err(rustix::io::Errno::INTR) => false,
+				Err(err) => return Err(err.into()),
+			}
+		};
+
+		if readable {
+			dispatch_wayland(display, socket, state)?;
+		}
+
+		let now = Instant::now();
+		if now >= next_frame {
+			state.render().unwrap_or_else(|err| {
+				tracing::error!("Failed to render frame: {}", err);
+				std::process::exit(1);
+			});
+
+			// Skip the frames we are too late for instead of rendering them
+			// in a burst.
+			next_frame += interval;
+			if next_frame < now {
+				next_frame = now + interval;
+			}
+		}
+
+		display.flush_clients()?;
+	}
+}
+
+/// Converts a timeout into whole milliseconds for `poll`, rounding up so
+/// the loop does not wake up just before the deadline.
+fn poll_timeout(timeout: Duration) -> i32 {
+	timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+}
@@ -1,44 +1,250 @@
 #![deny(warnings)]
-use renderer::Renderer;
+use compositor::Compositor;
+use config::{Config, OutputConfig};
+use cursor_shape::CursorShape;
+use decoration::Decoration;
+use dmabuf::LinuxDmabuf;
+use geometry::{Point, Size};
+use output::{Output, XdgOutputManager};
+use renderer::{BlurSettings, Renderer};
+use seat::Seat;
+use shm::Shm;
+use space::Space;
+use std::os::fd::AsFd as _;
+use std::time::Instant;
 use std::{io::Result, sync::Arc};
-use vulkanalia::vk::DeviceV1_0 as _;
+use subcompositor::Subcompositor;
+use viewporter::Viewporter;
+use vulkanalia::vk::{self, DeviceV1_0 as _};
+use wayland_server::{Display, ListeningSocket};
+use wayland_source::WaylandSource;
 use winit::application::ApplicationHandler;
-use winit::event::WindowEvent;
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::event::{ElementState, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::platform::scancode::PhysicalKeyExtScancode as _;
 use winit::window::{Window, WindowId};
+use xdg_shell::XdgShell;
 
+pub mod compositor;
+pub mod config;
+pub mod cursor_shape;
+pub mod decoration;
+pub mod dmabuf;
+pub mod geometry;
+pub mod headless;
+pub mod output;
 pub mod renderer;
+pub mod scene;
+pub mod seat;
+pub mod shm;
+pub mod space;
+pub mod subcompositor;
+pub mod viewporter;
+pub mod wayland_source;
+pub mod xdg_shell;
 
-struct ServerState {
-	clients: Vec<wayland_server::Client>,
-	window: Option<Window>,
-	renderer: Option<Renderer>,
+/// Events sent to the winit event loop from outside of it.
+#[derive(Debug, Clone, Copy)]
+pub enum UserEvent {
+	/// The listening socket or a client connection has data to dispatch.
+	Wayland,
 }
 
-impl ApplicationHandler for ServerState {
+pub struct ServerState {
+	pub clients: Vec<wayland_server::Client>,
+	pub renderer: Option<Renderer>,
+	pub start_time: Instant,
+	pub compositor: Compositor,
+	pub subcompositor: Subcompositor,
+	pub shm: Shm,
+	pub xdg_shell: XdgShell,
+	pub space: Space,
+	pub seat: Seat,
+	pub cursor_shape: CursorShape,
+	pub decoration: Decoration,
+	pub viewporter: Viewporter,
+	/// One per host window, or per configured output when headless.
+	pub outputs: Vec<Output>,
+	pub xdg_output_manager: XdgOutputManager,
+	/// `None` until there is a renderer, and if it cannot import dmabufs.
+	pub dmabuf: Option<LinuxDmabuf>,
+	/// Last serial handed out by [`ServerState::next_serial`].
+	pub serial: u32,
+}
+
+impl ServerState {
+	/// Milliseconds since startup, as used for protocol timestamps.
+	fn now(&self) -> u32 {
+		self.start_time.elapsed().as_millis() as u32
+	}
+
+	/// Returns a new serial for events that clients may refer back to.
+	pub fn next_serial(&mut self) -> u32 {
+		self.serial = self.serial.wrapping_add(1);
+		self.serial
+	}
+
+	/// Draws a frame of the current scene on the output `index` and tells
+	/// clients shown there that it was shown.
+	pub fn render_output(&mut self, index: usize) -> anyhow::Result<()> {
+		self.prepare_cursor();
+		self.update_surface_outputs();
+		let scene = self.scene(index);
+
+		if let Some(renderer) = self.renderer.as_mut() {
+			unsafe { renderer.render_frame(index, &scene) }?;
+		}
+
+		let time = self.now();
+		self.compositor.send_frame_callbacks(index, time);
+
+		Ok(())
+	}
+
+	/// Draws a frame on every output, e.g. when headless.
+	pub fn render(&mut self) -> anyhow::Result<()> {
+		for index in 0..self.outputs.len() {
+			self.render_output(index)?;
+		}
+
+		Ok(())
+	}
+
+	/// Index of the output shown in the host window `id`.
+	fn window_output_index(&self, id: WindowId) -> Option<usize> {
+		self.outputs.iter().position(|output| {
+			output
+				.window
+				.as_ref()
+				.is_some_and(|window| window.id() == id)
+		})
+	}
+}
+
+/// Accepts new clients, applies the commits whose buffers became ready and
+/// dispatches the pending requests of all clients.
+pub fn dispatch_wayland(
+	display: &mut Display<ServerState>,
+	socket: &ListeningSocket,
+	state: &mut ServerState,
+) -> Result<()> {
+	while let Some(stream) = socket.accept()? {
+		let client = display
+			.handle()
+			.insert_client(stream, Arc::new(ClientState))?;
+		state.clients.push(client);
+	}
+
+	// Commits waiting for their buffers go first, to keep their order with
+	// the ones of the requests dispatched now.
+	state.unblock_all_commits();
+	display.dispatch_clients(state)?;
+	display.flush_clients()?;
+
+	Ok(())
+}
+
+struct App {
+	display: Display<ServerState>,
+	socket: ListeningSocket,
+	source: WaylandSource,
+	state: ServerState,
+	/// Applied to the renderer once the windows exist.
+	blur: BlurSettings,
+	/// Applied to the windows as they are created.
+	outputs: Vec<OutputConfig>,
+}
+
+impl ApplicationHandler<UserEvent> for App {
 	fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-		unsafe {
-			self.window = Some(
-				event_loop
-					.create_window(Window::default_attributes())
-					.unwrap_or_else(|err| {
-						tracing::error!("Failed to create window: {}", err);
-						std::process::exit(1);
-					}),
-			);
+		// The cursor is drawn into the outputs, so the host one would only
+		// get in the way, unless there is no theme to replace it with.
+		let hide_cursor = self.state.seat.cursor_theme.is_available();
+		if !hide_cursor {
+			tracing::warn!("No cursor theme found, using the host cursor");
+		}
+
+		for (output, config) in self.state.outputs.iter_mut().zip(&self.outputs)
+		{
+			let mut attributes = Window::default_attributes()
+				.with_title(format!("neora {}", output.name));
+			if let Some(size) = config.size {
+				// Without a scale override, the host one decides the mode.
+				attributes = match config.scale {
+					Some(scale) => {
+						attributes.with_inner_size(PhysicalSize::new(
+							(size.width as f64 * scale).round() as u32,
+							(size.height as f64 * scale).round() as u32,
+						))
+					}
+					None => attributes.with_inner_size(LogicalSize::new(
+						size.width as u32,
+						size.height as u32,
+					)),
+				};
+			}
 
-			let window = self.window.as_ref().unwrap();
+			let window =
+				event_loop.create_window(attributes).unwrap_or_else(|err| {
+					tracing::error!("Failed to create window: {}", err);
+					std::process::exit(1);
+				});
+			if hide_cursor {
+				window.set_cursor_visible(false);
+			}
+			output.window = Some(window);
+			output.scale_override = config.scale;
+		}
 
-			self.renderer = Some(Renderer::new(window).unwrap_or_else(|err| {
+		unsafe {
+			let windows: Vec<_> = self
+				.state
+				.outputs
+				.iter()
+				.filter_map(|output| output.window.as_ref())
+				.collect();
+			let mut renderer = Renderer::new(&windows).unwrap_or_else(|err| {
 				tracing::error!("Failed to create renderer: {}", err);
 				std::process::exit(1);
-			}));
+			});
+			renderer.blur.settings = self.blur;
+			self.state.renderer = Some(renderer);
+			for index in 0..self.state.outputs.len() {
+				self.state.configure_output_from_window(index);
+			}
+			self.state.init_dmabuf(&self.display.handle());
+		}
+	}
+
+	fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: UserEvent) {
+		match event {
+			UserEvent::Wayland => {
+				dispatch_wayland(
+					&mut self.display,
+					&self.socket,
+					&mut self.state,
+				)
+				.unwrap_or_else(|err| {
+					tracing::error!("Failed to dispatch clients: {}", err);
+				});
+				self.source.resume(&self.state.fence_fds());
+			}
 		}
 	}
 
+	fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+		// Rendering may have queued events (e.g. frame callbacks) without a
+		// client request waking us up, so flush them before going to sleep.
+		self.display.flush_clients().unwrap_or_else(|err| {
+			tracing::error!("Failed to flush clients: {}", err);
+		});
+	}
+
 	fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
 		unsafe {
-			self.renderer
+			self.state
+				.renderer
 				.as_mut()
 				.unwrap()
 				.device
@@ -50,30 +256,92 @@ impl ApplicationHandler for ServerState {
 	fn window_event(
 		&mut self,
 		event_loop: &ActiveEventLoop,
-		_id: WindowId,
+		id: WindowId,
 		event: WindowEvent,
 	) {
+		let Some(index) = self.state.window_output_index(id) else {
+			return;
+		};
+
 		match event {
 			WindowEvent::CloseRequested => {
 				event_loop.exit();
 			}
-			WindowEvent::RedrawRequested => {
-				unsafe {
-					// Draw.
-					self.renderer
-						.as_mut()
-						.unwrap()
-						.render_frame()
+			WindowEvent::Focused(focused) => {
+				self.state.set_host_focus(focused);
+			}
+			WindowEvent::ModifiersChanged(modifiers) => {
+				self.state.handle_host_modifiers(modifiers.state());
+			}
+			WindowEvent::KeyboardInput {
+				event,
+				is_synthetic,
+				..
+			} => {
+				// Clients implement key repeat themselves.
+				if event.repeat {
+					return;
+				}
+
+				if let Some(keycode) = event.physical_key.to_scancode() {
+					self.state.handle_key(
+						keycode,
+						event.state == ElementState::Pressed,
+						is_synthetic,
+					);
+				}
+			}
+			WindowEvent::CursorMoved { position, .. } => {
+				let output = &self.state.outputs[index];
+				let Point { x, y } = output.location;
+				let scale = output.scale_factor;
+				self.state.handle_pointer_motion(
+					x as f64 + position.x / scale,
+					y as f64 + position.y / scale,
+				);
+			}
+			WindowEvent::CursorLeft { .. } => {
+				self.state.handle_pointer_leave();
+			}
+			WindowEvent::MouseInput { state, button, .. } => {
+				self.state.handle_pointer_button(
+					button,
+					state == ElementState::Pressed,
+				);
+			}
+			WindowEvent::MouseWheel { delta, phase, .. } => {
+				self.state.handle_pointer_axis(delta, phase);
+			}
+			WindowEvent::ScaleFactorChanged { .. } => {
+				self.state.configure_output_from_window(index);
+			}
+			WindowEvent::Resized(size) => {
+				self.state.configure_output_from_window(index);
+
+				let extent = vk::Extent2D {
+					width: size.width,
+					height: size.height,
+				};
+
+				if let Some(renderer) = self.state.renderer.as_mut() {
+					unsafe { renderer.recreate_swapchain(index, extent) }
 						.unwrap_or_else(|err| {
 							tracing::error!(
-								"Failed to create renderer: {}",
+								"Failed to recreate swapchain: {}",
 								err
 							);
-							std::process::exit(1);
 						});
 				}
+			}
+			WindowEvent::RedrawRequested => {
+				self.state.render_output(index).unwrap_or_else(|err| {
+					tracing::error!("Failed to render frame: {}", err);
+					std::process::exit(1);
+				});
 
-				self.window.as_ref().unwrap().request_redraw();
+				if let Some(window) = &self.state.outputs[index].window {
+					window.request_redraw();
+				}
 			}
 			_ => (),
 		}
@@ -100,44 +368,159 @@ impl wayland_server::backend::ClientData for ClientState {
 	}
 }
 
+/// Prints the Vulkan devices for `--list-devices`, marking the one that
+/// would be used.
+fn list_devices() {
+	let (candidates, selected) = unsafe { renderer::list_devices() }
+		.unwrap_or_else(|err| {
+			tracing::error!("Failed to list devices: {}", err);
+			std::process::exit(1);
+		});
+
+	for (index, candidate) in candidates.iter().enumerate() {
+		let marker = if selected == Some(index) { '*' } else { ' ' };
+		match &candidate.unsuitable {
+			Some(reason) => {
+				println!("{} {} unsuitable: {}", marker, candidate, reason)
+			}
+			None => {
+				println!("{} {} score {}", marker, candidate, candidate.score())
+			}
+		}
+	}
+}
+
 fn main() -> Result<()> {
 	tracing_subscriber::fmt::init();
 
-	let socket =
-		wayland_server::ListeningSocket::bind_auto("wayland", 0..).unwrap();
+	let config = Config::from_args().unwrap_or_else(|err| {
+		eprintln!("{}", err);
+		std::process::exit(2);
+	});
+
+	if config.list_devices {
+		list_devices();
+		return Ok(());
+	}
+
+	let socket = match &config.socket {
+		Some(name) => ListeningSocket::bind(name),
+		None => ListeningSocket::bind_auto("wayland", 0..),
+	}
+	.unwrap_or_else(|err| {
+		tracing::error!("Failed to bind socket: {}", err);
+		std::process::exit(1);
+	});
 
 	tracing::info!("Listening on socket: {:?}", socket.socket_name().unwrap());
 
-	let event_loop = EventLoop::new().unwrap();
-	event_loop.set_control_flow(ControlFlow::Poll);
-	event_loop.set_control_flow(ControlFlow::Wait);
+	let mut display = Display::new().unwrap_or_else(|err| {
+		tracing::error!("Failed to create display: {}", err);
+		std::process::exit(1);
+	});
 
 	let mut state = ServerState {
 		clients: Vec::new(),
-		window: None,
 		renderer: None,
+		start_time: Instant::now(),
+		compositor: Compositor::new(&display.handle()),
+		subcompositor: Subcompositor::new(&display.handle()),
+		shm: Shm::new(&display.handle()),
+		xdg_shell: XdgShell::new(&display.handle()),
+		space: Space::new(config.focus_policy),
+		seat: Seat::new(&display.handle()).unwrap_or_else(|err| {
+			tracing::error!("Failed to create seat: {}", err);
+			std::process::exit(1);
+		}),
+		cursor_shape: CursorShape::new(&display.handle()),
+		decoration: Decoration::new(&display.handle()),
+		viewporter: Viewporter::new(&display.handle()),
+		outputs: (0..config.outputs.len())
+			.map(|index| {
+				let (name, description) = if config.headless {
+					("HEADLESS", "neora headless output")
+				} else {
+					("WINDOW", "neora window")
+				};
+				Output::new(
+					&display.handle(),
+					index,
+					&format!("{}-{}", name, index + 1),
+					&format!("{} {}", description, index + 1),
+				)
+			})
+			.collect(),
+		xdg_output_manager: XdgOutputManager::new(&display.handle()),
+		dmabuf: None,
+		serial: 0,
 	};
 
-	let mut display = wayland_server::Display::new().unwrap_or_else(|err| {
-		tracing::error!("Failed to create display: {}", err);
-		std::process::exit(1);
-	});
+	if config.headless {
+		let modes: Vec<_> = config
+			.outputs
+			.iter()
+			.map(|output| {
+				let size = output.size.unwrap_or(config.output_size);
+				let scale = output.scale.unwrap_or(1.0);
+				Size::new(
+					(size.width as f64 * scale).round() as i32,
+					(size.height as f64 * scale).round() as i32,
+				)
+			})
+			.collect();
+		let extents: Vec<_> = modes
+			.iter()
+			.map(|mode| vk::Extent2D {
+				width: mode.width as u32,
+				height: mode.height as u32,
+			})
+			.collect();
+		let mut renderer = unsafe { Renderer::new_headless(&extents) }
+			.unwrap_or_else(|err| {
+				tracing::error!("Failed to create renderer: {}", err);
+				std::process::exit(1);
+			});
+		renderer.blur.settings = config.blur;
+		state.renderer = Some(renderer);
+		for ((output, config_output), mode) in
+			state.outputs.iter_mut().zip(&config.outputs).zip(modes)
+		{
+			output.configure(
+				mode,
+				config_output.scale.unwrap_or(1.0),
+				config.refresh_rate as i32 * 1000,
+			);
+		}
+		state.arrange_outputs();
+		state.init_dmabuf(&display.handle());
+
+		return headless::run(&mut display, &socket, &mut state, &config);
+	}
+
+	let event_loop = EventLoop::with_user_event().build().unwrap();
+	event_loop.set_control_flow(ControlFlow::Wait);
+
+	let source = WaylandSource::spawn(
+		event_loop.create_proxy(),
+		vec![
+			socket.as_fd().try_clone_to_owned()?,
+			display.backend().poll_fd().try_clone_to_owned()?,
+		],
+	)?;
 
-	event_loop.run_app(&mut state).unwrap_or_else(|err| {
+	let mut app = App {
+		display,
+		socket,
+		source,
+		state,
+		blur: config.blur,
+		outputs: config.outputs.clone(),
+	};
+
+	event_loop.run_app(&mut app).unwrap_or_else(|err| {
 		tracing::error!("Failed to run application: {}", err);
 		std::process::exit(1);
 	});
 
-	loop {
-		if let Some(stream) = socket.accept().unwrap() {
-			let client = display
-				.handle()
-				.insert_client(stream, Arc::new(ClientState))
-				.unwrap();
-			state.clients.push(client);
-		}
-
-		display.dispatch_clients(&mut state)?;
-		display.flush_clients()?;
-	}
+	Ok(())
 }
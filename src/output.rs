@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+
+use wayland_protocols::xdg::xdg_output::zv1::server::{
+	zxdg_output_manager_v1::{self, ZxdgOutputManagerV1},
+	zxdg_output_v1::{self, ZxdgOutputV1},
+};
+use wayland_server::{
+	backend::{ClientId, GlobalId, ObjectId},
+	protocol::wl_output::{self, Subpixel, Transform, WlOutput},
+	Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+use winit::window::Window;
+
+use crate::{
+	geometry::{Point, Rectangle, Size},
+	xdg_shell::XdgRole,
+	ServerState,
+};
+
+const OUTPUT_VERSION: u32 = 4;
+const XDG_OUTPUT_MANAGER_VERSION: u32 = 3;
+
+/// Refresh rate advertised when the host does not report one, in mHz.
+const DEFAULT_REFRESH: i32 = 60_000;
+
+/// An area clients are shown in, advertised through `wl_output` and
+/// `zxdg_output_v1`. Each output has the render target of the same index.
+///
+/// The global coordinate space is in logical pixels, which the renderer
+/// scales by `scale_factor` to draw into the host window.
+pub struct Output {
+	pub global: GlobalId,
+	/// Host window the output is shown in, `None` when headless.
+	pub window: Option<Window>,
+	/// Connector-like name, which never changes once advertised.
+	pub name: String,
+	pub description: String,
+	/// Position in the global coordinate space.
+	pub location: Point,
+	/// Size in physical pixels.
+	pub mode: Size,
+	/// Refresh rate in mHz.
+	pub refresh: i32,
+	/// Physical pixels per logical pixel.
+	pub scale_factor: f64,
+	/// Scale factor from the configuration, replacing the one of the host
+	/// window.
+	pub scale_override: Option<f64>,
+	pub resources: Vec<WlOutput>,
+	pub xdg_outputs: Vec<ZxdgOutputV1>,
+}
+
+impl Output {
+	/// Creates the output at `index` in [`ServerState::outputs`].
+	pub fn new(
+		display: &DisplayHandle,
+		index: usize,
+		name: &str,
+		description: &str,
+	) -> Self {
+		Self {
+			global: display.create_global::<ServerState, WlOutput, usize>(
+				OUTPUT_VERSION,
+				index,
+			),
+			window: None,
+			name: name.to_owned(),
+			description: description.to_owned(),
+			location: Point::default(),
+			mode: Size::default(),
+			refresh: DEFAULT_REFRESH,
+			scale_factor: 1.0,
+			scale_override: None,
+			resources: Vec::new(),
+			xdg_outputs: Vec::new(),
+		}
+	}
+
+	/// Area covered in the global coordinate space.
+	pub fn geometry(&self) -> Rectangle {
+		Rectangle::from_loc_and_size(
+			self.location,
+			Size::new(
+				(self.mode.width as f64 / self.scale_factor).round() as i32,
+				(self.mode.height as f64 / self.scale_factor).round() as i32,
+			),
+		)
+	}
+
+	/// The integer scale clients should render at, rounded up so they stay
+	/// sharp with fractional scale factors.
+	pub fn scale(&self) -> i32 {
+		(self.scale_factor.ceil() as i32).max(1)
+	}
+
+	/// Updates the mode and scale, telling clients if they changed.
+	pub fn configure(&mut self, mode: Size, scale_factor: f64, refresh: i32) {
+		if (self.mode, self.scale_factor, self.refresh)
+			== (mode, scale_factor, refresh)
+		{
+			return;
+		}
+
+		self.mode = mode;
+		self.scale_factor = scale_factor;
+		self.refresh = refresh;
+
+		for resource in &self.resources {
+			self.send_configuration(resource);
+		}
+	}
+
+	/// Moves the output in the global coordinate space, telling clients if
+	/// it moved.
+	pub fn set_location(&mut self, location: Point) {
+		if self.location == location {
+			return;
+		}
+
+		self.location = location;
+		for resource in &self.resources {
+			self.send_configuration(resource);
+		}
+	}
+
+	/// Sends everything but the name, which only goes out once, ending with
+	/// `done`.
+	fn send_configuration(&self, resource: &WlOutput) {
+		resource.geometry(
+			self.location.x,
+			self.location.y,
+			// The physical size of a window is meaningless.
+			0,
+			0,
+			Subpixel::Unknown,
+			"neora".to_owned(),
+			self.description.clone(),
+			Transform::Normal,
+		);
+		resource.mode(
+			wl_output::Mode::Current | wl_output::Mode::Preferred,
+			self.mode.width,
+			self.mode.height,
+			self.refresh,
+		);
+		if resource.version() >= 2 {
+			resource.scale(self.scale());
+		}
+		if resource.version() >= 4 {
+			resource.description(self.description.clone());
+		}
+
+		for xdg_output in self.xdg_outputs_of(resource) {
+			self.send_xdg_configuration(xdg_output);
+		}
+
+		if resource.version() >= 2 {
+			resource.done();
+		}
+	}
+
+	fn xdg_outputs_of<'a>(
+		&'a self,
+		resource: &'a WlOutput,
+	) -> impl Iterator<Item = &'a ZxdgOutputV1> {
+		self.xdg_outputs.iter().filter(|xdg_output| {
+			xdg_output.data::<WlOutput>() == Some(resource)
+		})
+	}
+
+	/// Sends the logical geometry, which `wl_output.done` applies from
+	/// version 3 on.
+	fn send_xdg_configuration(&self, xdg_output: &ZxdgOutputV1) {
+		let geometry = self.geometry();
+		xdg_output.logical_position(geometry.x, geometry.y);
+		xdg_output.logical_size(geometry.width, geometry.height);
+
+		if xdg_output.version() < 3 {
+			xdg_output.done();
+		}
+	}
+
+	/// Output resources of the client owning `object`.
+	pub fn resources_for<'a>(
+		&'a self,
+		object: &'a ObjectId,
+	) -> impl Iterator<Item = &'a WlOutput> {
+		self.resources
+			.iter()
+			.filter(|output| output.id().same_client_as(object))
+	}
+}
+
+/// State of the `zxdg_output_manager_v1` global, describing outputs in the
+/// global coordinate space.
+pub struct XdgOutputManager {
+	pub global: GlobalId,
+}
+
+impl XdgOutputManager {
+	pub fn new(display: &DisplayHandle) -> Self {
+		Self {
+			global: display
+				.create_global::<ServerState, ZxdgOutputManagerV1, ()>(
+					XDG_OUTPUT_MANAGER_VERSION,
+					(),
+				),
+		}
+	}
+}
+
+impl ServerState {
+	/// Follows the size and scale factor of the host window of the output
+	/// `index`.
+	pub fn configure_output_from_window(&mut self, index: usize) {
+		let Some(output) = self.outputs.get_mut(index) else {
+			return;
+		};
+		let Some(window) = &output.window else {
+			return;
+		};
+
+		let size = window.inner_size();
+		let refresh = window
+			.current_monitor()
+			.and_then(|monitor| monitor.refresh_rate_millihertz())
+			.map_or(DEFAULT_REFRESH, |refresh| refresh as i32);
+		let scale_factor = output
+			.scale_override
+			.unwrap_or_else(|| window.scale_factor());
+
+		output.configure(
+			Size::new(size.width as i32, size.height as i32),
+			scale_factor,
+			refresh,
+		);
+		self.arrange_outputs();
+	}
+
+	/// Lays the outputs out from left to right in order, aligned at the top,
+	/// so their logical sizes decide where one ends and the next begins.
+	pub fn arrange_outputs(&mut self) {
+		let mut x = 0;
+		for output in &mut self.outputs {
+			output.set_location(Point::new(x, 0));
+			x += output.geometry().width;
+		}
+	}
+
+	/// Index of the output containing `point`.
+	pub fn output_at(&self, point: Point) -> Option<usize> {
+		self.outputs
+			.iter()
+			.position(|output| output.geometry().contains(point))
+	}
+
+	/// Index of the output the window of `surface` is on: the one it is made
+	/// fullscreen on, or else the one containing the center of its window
+	/// geometry. Windows that are not mapped yet open on the output under the
+	/// pointer.
+	pub fn window_output(&self, surface: &ObjectId) -> usize {
+		let window = self.window_of(surface);
+
+		let fullscreen_output = window.as_ref().and_then(|id| {
+			match &self.xdg_shell.surfaces.get(id)?.role {
+				XdgRole::Toplevel(toplevel) => toplevel.fullscreen_output,
+				_ => None,
+			}
+		});
+		if let Some(index) = fullscreen_output {
+			return index;
+		}
+
+		let pointer = self
+			.seat
+			.pointer
+			.location
+			.map(|(x, y)| Point::new(x.floor() as i32, y.floor() as i32));
+		window
+			.and_then(|id| self.window_geometry(&id))
+			.map(|geometry| geometry.center())
+			.or(pointer)
+			.and_then(|point| self.output_at(point))
+			.unwrap_or(0)
+	}
+
+	/// Area covered by the output the window of `surface` is on, in the
+	/// global coordinate space.
+	pub fn output_geometry(&self, surface: &ObjectId) -> Rectangle {
+		self.outputs[self.window_output(surface)].geometry()
+	}
+
+	/// Sends `enter` and `leave` to surfaces as they start and stop
+	/// overlapping outputs, along with the preferred buffer scale of the
+	/// densest one.
+	pub fn update_surface_outputs(&mut self) {
+		let mut overlapping = HashMap::new();
+		for window in self.space.visible() {
+			for (id, rectangle) in self.window_surfaces(&window.surface) {
+				let outputs: Vec<_> = (0..self.outputs.len())
+					.filter(|&index| {
+						rectangle.overlaps(self.outputs[index].geometry())
+					})
+					.collect();
+				overlapping.insert(id, outputs);
+			}
+		}
+
+		for (id, surface) in &mut self.compositor.surfaces {
+			let entered = overlapping.remove(id).unwrap_or_default();
+
+			for &index in &surface.outputs {
+				if entered.contains(&index) {
+					continue;
+				}
+				for output in self.outputs[index].resources_for(id) {
+					surface.handle.leave(output);
+				}
+			}
+			for &index in &entered {
+				if surface.outputs.contains(&index) {
+					continue;
+				}
+				for output in self.outputs[index].resources_for(id) {
+					surface.handle.enter(output);
+				}
+			}
+			surface.outputs = entered;
+
+			let Some(scale) = surface
+				.outputs
+				.iter()
+				.map(|&index| self.outputs[index].scale())
+				.max()
+			else {
+				continue;
+			};
+			if surface.handle.version() >= 6
+				&& surface.preferred_buffer_scale != Some(scale)
+			{
+				surface.handle.preferred_buffer_scale(scale);
+				surface.preferred_buffer_scale = Some(scale);
+			}
+		}
+	}
+}
+
+impl GlobalDispatch<WlOutput, usize> for ServerState {
+	fn bind(
+		state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<WlOutput>,
+		global_data: &usize,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		let index = *global_data;
+		let resource = data_init.init(resource, index);
+		let output = &mut state.outputs[index];
+
+		if resource.version() >= 4 {
+			resource.name(output.name.clone());
+		}
+		output.send_configuration(&resource);
+
+		// Surfaces of the client may already be on the output.
+		for surface in state.compositor.surfaces.values() {
+			if surface.outputs.contains(&index)
+				&& surface.handle.id().same_client_as(&resource.id())
+			{
+				surface.handle.enter(&resource);
+			}
+		}
+
+		output.resources.push(resource);
+	}
+}
+
+impl Dispatch<WlOutput, usize> for ServerState {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &WlOutput,
+		request: wl_output::Request,
+		_data: &usize,
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wl_output::Request::Release => {}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		resource: &WlOutput,
+		data: &usize,
+	) {
+		if let Some(output) = state.outputs.get_mut(*data) {
+			output.resources.retain(|output| output != resource);
+		}
+	}
+}
+
+impl GlobalDispatch<ZxdgOutputManagerV1, ()> for ServerState {
+	fn bind(
+		_state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<ZxdgOutputManagerV1>,
+		_global_data: &(),
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		data_init.init(resource, ());
+	}
+}
+
+impl Dispatch<ZxdgOutputManagerV1, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		_resource: &ZxdgOutputManagerV1,
+		request: zxdg_output_manager_v1::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			zxdg_output_manager_v1::Request::Destroy => {}
+			zxdg_output_manager_v1::Request::GetXdgOutput { id, output } => {
+				let xdg_output = data_init.init(id, output.clone());
+				let Some(output_state) = output
+					.data::<usize>()
+					.and_then(|&index| state.outputs.get_mut(index))
+				else {
+					return;
+				};
+
+				// Deprecated from version 3 on, in favor of `wl_output`.
+				if xdg_output.version() >= 2 {
+					xdg_output.name(output_state.name.clone());
+					xdg_output.description(output_state.description.clone());
+				}
+				output_state.send_xdg_configuration(&xdg_output);
+				if xdg_output.version() >= 3 && output.version() >= 2 {
+					output.done();
+				}
+
+				output_state.xdg_outputs.push(xdg_output);
+			}
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<ZxdgOutputV1, WlOutput> for ServerState {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &ZxdgOutputV1,
+		request: zxdg_output_v1::Request,
+		_data: &WlOutput,
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			zxdg_output_v1::Request::Destroy => {}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		resource: &ZxdgOutputV1,
+		data: &WlOutput,
+	) {
+		if let Some(output) = data
+			.data::<usize>()
+			.and_then(|&index| state.outputs.get_mut(index))
+		{
+			output.xdg_outputs.retain(|output| output != resource);
+		}
+	}
+}
@@ -0,0 +1,72 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+	vec4 rect;
+	vec2 outputSize;
+	float opacity;
+	float cornerRadius;
+	// Premultiplied colors the quad is multiplied with, from top to bottom.
+	vec4 colorTop;
+	vec4 colorBottom;
+	// Part of the texture to sample, in normalized texture coordinates.
+	vec4 source;
+	// Transform of the texture contents, numbered like wl_output.transform.
+	uint transform;
+} pc;
+
+layout(set = 0, binding = 0) uniform texture2D surfaceTexture;
+layout(set = 0, binding = 1) uniform sampler surfaceSampler;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+// Fraction of the pixel covered by the quad once its corners are rounded,
+// from the signed distance to the rounded rectangle.
+float coverage() {
+	vec2 halfSize = pc.rect.zw * 0.5;
+	float radius = min(pc.cornerRadius, min(halfSize.x, halfSize.y));
+	vec2 p = abs(fragTexCoord * pc.rect.zw - halfSize) - (halfSize - radius);
+	float distance =
+		length(max(p, 0.0)) + min(max(p.x, p.y), 0.0) - radius;
+
+	return clamp(0.5 - distance, 0.0, 1.0);
+}
+
+// Maps normalized coordinates of the untransformed contents to texture
+// coordinates.
+vec2 transformed(vec2 p) {
+	switch (pc.transform) {
+	case 1u: return vec2(p.y, 1.0 - p.x);
+	case 2u: return 1.0 - p;
+	case 3u: return vec2(1.0 - p.y, p.x);
+	case 4u: return vec2(1.0 - p.x, p.y);
+	case 5u: return p.yx;
+	case 6u: return vec2(p.x, 1.0 - p.y);
+	case 7u: return 1.0 - p.yx;
+	default: return p;
+	}
+}
+
+void main() {
+	vec4 color = mix(pc.colorTop, pc.colorBottom, fragTexCoord.y);
+
+	// The odd transforms swap the axes of the texture.
+	vec2 size =
+		vec2(textureSize(sampler2D(surfaceTexture, surfaceSampler), 0));
+	if (pc.transform % 2u == 1u) {
+		size = size.yx;
+	}
+
+	// Keep the filter from reaching texels outside of the source rectangle.
+	vec2 halfTexel = min(0.5 / size, pc.source.zw * 0.5);
+	vec2 uv = transformed(clamp(
+		pc.source.xy + fragTexCoord * pc.source.zw,
+		pc.source.xy + halfTexel,
+		pc.source.xy + pc.source.zw - halfTexel
+	));
+
+	// Client buffers use premultiplied alpha.
+	outColor = texture(sampler2D(surfaceTexture, surfaceSampler), uv)
+		* color * pc.opacity * coverage();
+}
@@ -0,0 +1,28 @@
+#version 450
+
+layout(push_constant) uniform PushConstants {
+	// Position and size of the quad in output pixels.
+	vec4 rect;
+	vec2 outputSize;
+	// The fragment shaders drawn with this one declare their own members
+	// from here on.
+} pc;
+
+layout(location = 0) out vec2 fragTexCoord;
+
+vec2 corners[6] = vec2[](
+	vec2(0.0, 0.0),
+	vec2(1.0, 0.0),
+	vec2(0.0, 1.0),
+	vec2(0.0, 1.0),
+	vec2(1.0, 0.0),
+	vec2(1.0, 1.0)
+);
+
+void main() {
+	vec2 corner = corners[gl_VertexIndex];
+	vec2 position = pc.rect.xy + corner * pc.rect.zw;
+
+	gl_Position = vec4(position / pc.outputSize * 2.0 - 1.0, 0.0, 1.0);
+	fragTexCoord = corner;
+}
@@ -1,394 +1,357 @@
-use std::collections::HashSet;
+use std::{
+	collections::{HashMap, HashSet},
+	mem::size_of,
+};
 
 use anyhow::{Context as _, Result};
-use raw_window_handle::{HasDisplayHandle as _, HasWindowHandle as _};
+use raw_window_handle::{
+	HasDisplayHandle as _, HasWindowHandle as _, RawDisplayHandle,
+};
 use vulkanalia::{
 	loader::{LibloadingLoader, LIBRARY},
 	vk::{
-		self, DeviceV1_0 as _, Handle as _, HasBuilder, InstanceV1_0 as _,
-		KhrSurfaceExtension, KhrSwapchainExtension, PolygonMode,
+		self, DeviceV1_0 as _, EntryV1_0 as _, ExtDebugUtilsExtension as _,
+		Handle as _, HasBuilder, InstanceV1_0 as _, KhrSurfaceExtension,
+		KhrSwapchainExtension, PolygonMode,
 	},
 	Device, Entry, Instance,
 };
 
+use crate::geometry::Rectangle;
+
+mod blur;
+mod capture;
+mod debug;
+mod dmabuf;
+#[cfg(all(debug_assertions, feature = "hot-reload"))]
+mod hot_reload;
+mod offscreen;
+mod physical_device;
+#[cfg(all(debug_assertions, feature = "hot-reload"))]
+mod shader_compiler;
+mod swapchain;
+mod target;
+#[cfg(test)]
+mod tests;
+mod texture;
+
+use physical_device::pick_physical_device;
+
+pub use blur::{Blur, BlurSettings};
+pub use capture::FrameCapture;
+pub use dmabuf::{
+	Dmabuf, DmabufPlane, DmabufSupport, ExternalImage, ExternalImageId,
+};
+pub use physical_device::{list_devices, DeviceCandidate};
+pub use target::Target;
+pub use texture::{ImageData, Texture, TextureId};
+
+use dmabuf::dmabuf_extensions;
+
 const MAX_FRAMES_IN_FLIGHT: usize = 2;
+/// Format of the target images when headless. Like the formats preferred
+/// for windows, it stores client pixels unchanged.
+const OFFSCREEN_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+/// Upper bound of live textures, each owning one descriptor set.
+const MAX_TEXTURES: u32 = 4096;
 const VALIDATION_LAYER: vk::ExtensionName =
 	vk::ExtensionName::from_bytes(b"VK_LAYER_KHRONOS_validation");
 
 pub struct Renderer {
 	pub instance: Instance,
+	/// Null unless validation is enabled.
+	pub debug_messenger: vk::DebugUtilsMessengerEXT,
 	pub physical_device: vk::PhysicalDevice,
 	pub device: Device,
 	pub graphics_queue: vk::Queue,
 	pub present_queue: vk::Queue,
 	pub queue_family_indices: QueueFamilyIndices,
-	pub surface: vk::SurfaceKHR,
-	pub swapchain: vk::SwapchainKHR,
-	pub swapchain_images: Vec<vk::Image>,
+	/// Format of the images of every target.
 	pub swapchain_format: vk::Format,
-	pub swapchain_extent: vk::Extent2D,
-	pub swapchain_image_views: Vec<vk::ImageView>,
 	pub vertex_shader_module: vk::ShaderModule,
 	pub fragment_shader_module: vk::ShaderModule,
+	pub sampler: vk::Sampler,
+	pub descriptor_set_layout: vk::DescriptorSetLayout,
+	pub descriptor_pool: vk::DescriptorPool,
 	pub pipeline_layout: vk::PipelineLayout,
 	pub render_pass: vk::RenderPass,
 	pub pipeline: vk::Pipeline,
-	pub framebuffers: Vec<vk::Framebuffer>,
+	pub blur: Blur,
 	pub command_pool: vk::CommandPool,
-	pub command_buffers: Vec<vk::CommandBuffer>,
-	pub image_available_semaphores: Vec<vk::Semaphore>,
-	pub render_finished_semaphores: Vec<vk::Semaphore>,
-	pub frame: usize,
-	pub in_flight_fences: Vec<vk::Fence>,
-	pub images_in_flight: Vec<vk::Fence>,
+	/// One per output, in the order of the windows or extents the renderer
+	/// was created with.
+	pub targets: Vec<Target>,
+	pub textures: HashMap<TextureId, Texture>,
+	pub next_texture_id: TextureId,
+	/// Sampled by [`Fill::Gradient`] elements, which have no texture of
+	/// their own.
+	pub white_texture: TextureId,
+	/// Imported client dmabufs, see [`Renderer::import_dmabuf`].
+	pub external_images: HashMap<ExternalImageId, ExternalImage>,
+	pub next_external_image_id: ExternalImageId,
+	/// `None` if dmabufs cannot be imported.
+	pub dmabuf: Option<DmabufSupport>,
+	pub staging_buffer: vk::Buffer,
+	pub staging_memory: vk::DeviceMemory,
+	pub staging_size: vk::DeviceSize,
+	pub upload_command_buffer: vk::CommandBuffer,
+	pub upload_fence: vk::Fence,
+	#[cfg(all(debug_assertions, feature = "hot-reload"))]
+	pub shader_watcher: hot_reload::ShaderWatcher,
 }
 
 impl Renderer {
+	/// Creates a renderer presenting to each of `windows`, which all need
+	/// the same display server.
+	///
 	/// # Safety
-	pub unsafe fn new(window: &winit::window::Window) -> Result<Self> {
-		let application_info = vk::ApplicationInfo::builder()
-			.application_name(b"macOS but it FUCKING SUCKS\0")
-			.application_version(vk::make_version(1, 0, 0))
-			.engine_name(b"No Engine\0")
-			.engine_version(vk::make_version(1, 0, 0))
-			.api_version(vk::make_version(1, 0, 0));
-
-		let extensions = vec![
-			vk::KHR_SURFACE_EXTENSION.name.as_ptr(),
-			vk::KHR_WAYLAND_SURFACE_EXTENSION.name.as_ptr(),
-			vk::KHR_DISPLAY_EXTENSION.name.as_ptr(),
-		];
-		let layers = vec![VALIDATION_LAYER.as_ptr()];
-
-		let flags = vk::InstanceCreateFlags::empty();
-
-		let info = vk::InstanceCreateInfo::builder()
-			.application_info(&application_info)
-			.enabled_layer_names(&layers)
-			.enabled_extension_names(&extensions)
-			.flags(flags);
-
-		let loader = LibloadingLoader::new(LIBRARY)?;
-		let entry = Entry::new(loader).map_err(|e| {
-			anyhow::anyhow!("Failed to load vulkan library: {}", e.to_string())
-		})?;
-		let instance = entry.create_instance(&info, None)?;
-
-		let mut physical_device = None;
-
-		for device in instance.enumerate_physical_devices()? {
-			let properties = instance.get_physical_device_properties(device);
-			tracing::debug!("Physical device: {}", properties.device_name);
-
-			physical_device = Some(device);
-		}
-
-		let physical_device =
-			physical_device.context("No physical devices found.")?;
+	pub unsafe fn new(windows: &[&winit::window::Window]) -> Result<Self> {
+		let Some(first) = windows.first() else {
+			anyhow::bail!("At least one window is needed.");
+		};
+		let display_handle = first.display_handle()?;
 
-		let mut queue_family_indices =
-			QueueFamilyIndices::get(&instance, physical_device)?;
+		let extensions = [
+			vk::KHR_SURFACE_EXTENSION.name,
+			surface_extension(display_handle.as_raw())?,
+		];
+		let (instance, debug_messenger) = create_instance(&extensions)?;
 
-		let window_handle = window.window_handle()?;
-		let display_handle = window.display_handle()?;
+		let surfaces = windows
+			.iter()
+			.map(|window| {
+				vulkanalia::window::create_surface(
+					&instance,
+					&window.display_handle()?,
+					&window.window_handle()?,
+				)
+				.context("Failed to create surface.")
+			})
+			.collect::<Result<Vec<_>>>()?;
 
-		let surface = vulkanalia::window::create_surface(
-			&instance,
-			&display_handle,
-			&window_handle,
-		)
-		.context("Failed to create surface.")?;
+		// The device is picked for the first window, the others only have to
+		// be presentable from the same queue with the same format.
+		let extensions = [vk::KHR_SWAPCHAIN_EXTENSION.name];
+		let (physical_device, queue_family_indices) =
+			pick_physical_device(&instance, surfaces[0], &extensions)?;
 
 		let support =
-			SwapchainSupport::get(&instance, surface, physical_device)?;
-		if support.formats.is_empty() || support.present_modes.is_empty() {
-			return Err(anyhow::anyhow!("Insufficient swapchain support."));
-		}
-
-		let properties = instance
-			.get_physical_device_queue_family_properties(physical_device);
+			SwapchainSupport::get(&instance, surfaces[0], physical_device)?;
+		let surface_format = get_swapchain_surface_format(&support.formats);
 
-		let mut present = None;
-		for (index, _properties) in properties.iter().enumerate() {
-			if instance.get_physical_device_surface_support_khr(
+		for &surface in &surfaces[1..] {
+			let present = instance.get_physical_device_surface_support_khr(
 				physical_device,
-				index as u32,
+				queue_family_indices.present.unwrap(),
 				surface,
-			)? {
-				present = Some(index as u32);
-				break;
+			)?;
+			let support =
+				SwapchainSupport::get(&instance, surface, physical_device)?;
+			if !present
+				|| get_swapchain_surface_format(&support.formats)
+					!= surface_format
+			{
+				anyhow::bail!(
+					"The windows cannot be presented to from one device."
+				);
 			}
 		}
 
-		queue_family_indices.present = present;
+		let optional_extensions =
+			dmabuf_extensions(&instance, physical_device)?;
+		let device = create_device(
+			&instance,
+			physical_device,
+			queue_family_indices,
+			&[&extensions[..], &optional_extensions].concat(),
+		)?;
+
+		let mut renderer = Self::from_device(
+			instance,
+			debug_messenger,
+			physical_device,
+			device,
+			queue_family_indices,
+			surface_format.format,
+			vk::ImageLayout::PRESENT_SRC_KHR,
+		)?;
+		renderer.init_dmabuf(&optional_extensions);
 
-		if queue_family_indices.present.is_none() {
-			anyhow::bail!("Missing required queue families.")
+		for (index, (window, surface)) in
+			windows.iter().zip(surfaces).enumerate()
+		{
+			let target = renderer.create_target(surface)?;
+			renderer.targets.push(target);
+
+			let size = window.inner_size();
+			renderer.recreate_swapchain(
+				index,
+				vk::Extent2D {
+					width: size.width,
+					height: size.height,
+				},
+			)?;
 		}
 
-		let mut unique_indices = HashSet::new();
-		unique_indices.insert(queue_family_indices.graphics);
-		unique_indices.insert(queue_family_indices.present.unwrap());
+		Ok(renderer)
+	}
 
-		let extensions = vec![vk::KHR_SWAPCHAIN_EXTENSION.name.as_ptr()];
+	/// Creates a renderer drawing into offscreen images, one target of each
+	/// of `extents`, which needs neither a window nor presentation support
+	/// from the driver.
+	///
+	/// # Safety
+	pub unsafe fn new_headless(extents: &[vk::Extent2D]) -> Result<Self> {
+		let (instance, debug_messenger) = create_instance(&[])?;
 
-		let queue_priorities = &[1.0];
-		let queue_infos = unique_indices
-			.iter()
-			.map(|i| {
-				vk::DeviceQueueCreateInfo::builder()
-					.queue_family_index(*i)
-					.queue_priorities(queue_priorities)
-			})
-			.collect::<Vec<_>>();
-
-		let features = vk::PhysicalDeviceFeatures::builder();
-		let info = vk::DeviceCreateInfo::builder()
-			.queue_create_infos(&queue_infos)
-			.enabled_layer_names(&layers)
-			.enabled_extension_names(&extensions)
-			.enabled_features(&features);
-		let device = instance.create_device(physical_device, &info, None)?;
-		let graphics_queue =
-			device.get_device_queue(queue_family_indices.graphics, 0);
-		let present_queue =
-			device.get_device_queue(queue_family_indices.present.unwrap(), 0);
+		// Without a surface, the graphics queue family also "presents".
+		let (physical_device, queue_family_indices) =
+			pick_physical_device(&instance, vk::SurfaceKHR::null(), &[])?;
 
-		let support =
-			SwapchainSupport::get(&instance, surface, physical_device)?;
+		let optional_extensions =
+			dmabuf_extensions(&instance, physical_device)?;
+		let device = create_device(
+			&instance,
+			physical_device,
+			queue_family_indices,
+			&optional_extensions,
+		)?;
 
-		let surface_format = get_swapchain_surface_format(&support.formats);
-		let present_mode = get_swapchain_present_mode(&support.present_modes);
-		let swapchain_extent =
-			get_swapchain_extent(window, support.capabilities);
-		let mut image_count = support.capabilities.min_image_count + 1;
-		if support.capabilities.max_image_count != 0
-			&& image_count > support.capabilities.max_image_count
-		{
-			image_count = support.capabilities.max_image_count;
+		let mut renderer = Self::from_device(
+			instance,
+			debug_messenger,
+			physical_device,
+			device,
+			queue_family_indices,
+			OFFSCREEN_FORMAT,
+			vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+		)?;
+		renderer.init_dmabuf(&optional_extensions);
+
+		for (index, &extent) in extents.iter().enumerate() {
+			let target = renderer.create_target(vk::SurfaceKHR::null())?;
+			renderer.targets.push(target);
+			renderer.with_target(index, |renderer, target| {
+				renderer.create_offscreen_images(target, extent)
+			})?;
 		}
 
-		let mut indices = vec![];
-		let image_sharing_mode = if queue_family_indices.graphics
-			!= queue_family_indices.present.unwrap()
-		{
-			indices.push(queue_family_indices.graphics);
-			indices.push(queue_family_indices.present.unwrap());
-			vk::SharingMode::CONCURRENT
-		} else {
-			vk::SharingMode::EXCLUSIVE
-		};
+		Ok(renderer)
+	}
 
-		let info = vk::SwapchainCreateInfoKHR::builder()
-			.surface(surface)
-			.min_image_count(image_count)
-			.image_format(surface_format.format)
-			.image_color_space(surface_format.color_space)
-			.image_extent(swapchain_extent)
-			.image_array_layers(1)
-			.image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-			.image_sharing_mode(image_sharing_mode)
-			.queue_family_indices(&indices)
-			.pre_transform(support.capabilities.current_transform)
-			.composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-			.present_mode(present_mode)
-			.clipped(true)
-			.old_swapchain(vk::SwapchainKHR::null());
-		let swapchain = device.create_swapchain_khr(&info, None)?;
-
-		let swapchain_images = device.get_swapchain_images_khr(swapchain)?;
-
-		let swapchain_image_views = swapchain_images
-			.iter()
-			.map(|i| {
-				let components = vk::ComponentMapping::builder()
-					.r(vk::ComponentSwizzle::IDENTITY)
-					.g(vk::ComponentSwizzle::IDENTITY)
-					.b(vk::ComponentSwizzle::IDENTITY)
-					.a(vk::ComponentSwizzle::IDENTITY);
-
-				let subresource_range = vk::ImageSubresourceRange::builder()
-					.aspect_mask(vk::ImageAspectFlags::COLOR)
-					.base_mip_level(0)
-					.level_count(1)
-					.base_array_layer(0)
-					.layer_count(1);
-
-				let info = vk::ImageViewCreateInfo::builder()
-					.image(*i)
-					.view_type(vk::ImageViewType::_2D)
-					.format(surface_format.format)
-					.components(components)
-					.subresource_range(subresource_range);
-
-				device.create_image_view(&info, None)
-			})
-			.collect::<Result<Vec<_>, _>>()?;
+	/// Creates everything that does not depend on where frames end up,
+	/// rendering into images of `format` left in `final_layout`. The targets
+	/// are created by the caller.
+	unsafe fn from_device(
+		instance: Instance,
+		debug_messenger: vk::DebugUtilsMessengerEXT,
+		physical_device: vk::PhysicalDevice,
+		device: Device,
+		queue_family_indices: QueueFamilyIndices,
+		format: vk::Format,
+		final_layout: vk::ImageLayout,
+	) -> Result<Self> {
+		let graphics_queue =
+			device.get_device_queue(queue_family_indices.graphics, 0);
+		let present_queue =
+			device.get_device_queue(queue_family_indices.present.unwrap(), 0);
 
-		let vert = include_bytes!("./shader.vertex.spv");
-		let frag = include_bytes!("./shader.fragment.spv");
+		let vert = include_bytes!(concat!(env!("OUT_DIR"), "/quad.vertex.spv"));
+		let frag =
+			include_bytes!(concat!(env!("OUT_DIR"), "/quad.fragment.spv"));
 
 		let vertex_shader_module = create_shader_module(&device, &vert[..])?;
 		let fragment_shader_module = create_shader_module(&device, &frag[..])?;
 
-		let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
-			.stage(vk::ShaderStageFlags::VERTEX)
-			.module(vertex_shader_module)
-			.name(b"main\0");
-
-		let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
-			.stage(vk::ShaderStageFlags::FRAGMENT)
-			.module(fragment_shader_module)
-			.name(b"main\0");
-
-		let vertex_input_state =
-			vk::PipelineVertexInputStateCreateInfo::builder();
-
-		let input_assembly_state =
-			vk::PipelineInputAssemblyStateCreateInfo::builder()
-				.topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-				.primitive_restart_enable(false);
-
-		let viewport = vk::Viewport::builder()
-			.x(0.0)
-			.y(0.0)
-			.width(swapchain_extent.width as f32)
-			.height(swapchain_extent.height as f32)
-			.min_depth(0.0)
-			.max_depth(1.0);
-
-		let scissor = vk::Rect2D::builder()
-			.offset(vk::Offset2D { x: 0, y: 0 })
-			.extent(swapchain_extent);
-
-		let viewports = &[viewport];
-		let scissors = &[scissor];
-		let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
-			.viewports(viewports)
-			.scissors(scissors);
-
-		let rasterization_state =
-			vk::PipelineRasterizationStateCreateInfo::builder()
-				.depth_clamp_enable(false)
-				.rasterizer_discard_enable(false)
-				.polygon_mode(PolygonMode::FILL)
-				.line_width(1.0)
-				.cull_mode(vk::CullModeFlags::BACK)
-				.front_face(vk::FrontFace::CLOCKWISE)
-				.depth_bias_enable(false);
-
-		let multisample_state =
-			vk::PipelineMultisampleStateCreateInfo::builder()
-				.sample_shading_enable(false)
-				.rasterization_samples(vk::SampleCountFlags::_1);
-
-		let attachment = vk::PipelineColorBlendAttachmentState::builder()
-			.color_write_mask(vk::ColorComponentFlags::all())
-			.blend_enable(true)
-			.src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
-			.dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
-			.color_blend_op(vk::BlendOp::ADD)
-			.src_alpha_blend_factor(vk::BlendFactor::ONE)
-			.dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-			.alpha_blend_op(vk::BlendOp::ADD);
-
-		let attachments = &[attachment];
-		let color_blend_state =
-			vk::PipelineColorBlendStateCreateInfo::builder()
-				.logic_op_enable(false)
-				.logic_op(vk::LogicOp::COPY)
-				.attachments(attachments)
-				.blend_constants([0.0, 0.0, 0.0, 0.0]);
-
-		let dynamic_states =
-			&[vk::DynamicState::VIEWPORT, vk::DynamicState::LINE_WIDTH];
-
-		let _dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
-			.dynamic_states(dynamic_states);
-
-		let layout_info = vk::PipelineLayoutCreateInfo::builder();
+		let info = vk::SamplerCreateInfo::builder()
+			.mag_filter(vk::Filter::LINEAR)
+			.min_filter(vk::Filter::LINEAR)
+			.address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+			.address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+			.address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+			.mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+			.max_lod(0.0);
+
+		let sampler = device.create_sampler(&info, None)?;
+
+		let immutable_samplers = &[sampler];
+		let bindings = &[
+			vk::DescriptorSetLayoutBinding::builder()
+				.binding(0)
+				.descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+				.descriptor_count(1)
+				.stage_flags(vk::ShaderStageFlags::FRAGMENT),
+			vk::DescriptorSetLayoutBinding::builder()
+				.binding(1)
+				.descriptor_type(vk::DescriptorType::SAMPLER)
+				.descriptor_count(1)
+				.stage_flags(vk::ShaderStageFlags::FRAGMENT)
+				.immutable_samplers(immutable_samplers),
+		];
+		let info =
+			vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+
+		let descriptor_set_layout =
+			device.create_descriptor_set_layout(&info, None)?;
+
+		let pool_sizes = &[
+			vk::DescriptorPoolSize::builder()
+				.type_(vk::DescriptorType::SAMPLED_IMAGE)
+				.descriptor_count(MAX_TEXTURES),
+			vk::DescriptorPoolSize::builder()
+				.type_(vk::DescriptorType::SAMPLER)
+				.descriptor_count(MAX_TEXTURES),
+		];
+		let info = vk::DescriptorPoolCreateInfo::builder()
+			.flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+			.pool_sizes(pool_sizes)
+			.max_sets(MAX_TEXTURES);
+
+		let descriptor_pool = device.create_descriptor_pool(&info, None)?;
+
+		let push_constant_range = vk::PushConstantRange::builder()
+			.stage_flags(
+				vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+			)
+			.offset(0)
+			.size(size_of::<QuadPushConstants>() as u32);
+
+		let set_layouts = &[descriptor_set_layout];
+		let push_constant_ranges = &[push_constant_range];
+		let layout_info = vk::PipelineLayoutCreateInfo::builder()
+			.set_layouts(set_layouts)
+			.push_constant_ranges(push_constant_ranges);
 
 		let pipeline_layout =
 			device.create_pipeline_layout(&layout_info, None)?;
 
-		// Render pass
-		let color_attachment = vk::AttachmentDescription::builder()
-			.format(surface_format.format)
-			.samples(vk::SampleCountFlags::_1)
-			.load_op(vk::AttachmentLoadOp::CLEAR)
-			.store_op(vk::AttachmentStoreOp::STORE)
-			.stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-			.stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-			.initial_layout(vk::ImageLayout::UNDEFINED)
-			.final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
-
-		let color_attachment_ref = vk::AttachmentReference::builder()
-			.attachment(0)
-			.layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
-
-		let color_attachments = &[color_attachment_ref];
-		let subpass = vk::SubpassDescription::builder()
-			.pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-			.color_attachments(color_attachments);
-
-		let dependency = vk::SubpassDependency::builder()
-			.src_subpass(vk::SUBPASS_EXTERNAL)
-			.dst_subpass(0)
-			.src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-			.src_access_mask(vk::AccessFlags::empty())
-			.dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-			.dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
-
-		let attachments = &[color_attachment];
-		let subpasses = &[subpass];
-		let dependencies = &[dependency];
-		let info = vk::RenderPassCreateInfo::builder()
-			.attachments(attachments)
-			.subpasses(subpasses)
-			.dependencies(dependencies);
-
-		let render_pass = device.create_render_pass(&info, None)?;
-
-		let stages = &[vert_stage, frag_stage];
-		let info = vk::GraphicsPipelineCreateInfo::builder()
-			.stages(stages)
-			.vertex_input_state(&vertex_input_state)
-			.input_assembly_state(&input_assembly_state)
-			.viewport_state(&viewport_state)
-			.rasterization_state(&rasterization_state)
-			.multisample_state(&multisample_state)
-			.color_blend_state(&color_blend_state)
-			.layout(pipeline_layout)
-			.render_pass(render_pass)
-			.base_pipeline_handle(vk::Pipeline::null())
-			.base_pipeline_index(-1)
-			.subpass(0);
-
-		let pipeline = device
-			.create_graphics_pipelines(
-				vk::PipelineCache::null(),
-				&[info],
-				None,
-			)?
-			.0[0];
+		let render_pass = create_render_pass(
+			&device,
+			format,
+			vk::AttachmentLoadOp::CLEAR,
+			vk::ImageLayout::UNDEFINED,
+			final_layout,
+		)?;
 
-		let framebuffers = swapchain_image_views
-			.iter()
-			.map(|i| {
-				let attachments = &[*i];
-				let create_info = vk::FramebufferCreateInfo::builder()
-					.render_pass(render_pass)
-					.attachments(attachments)
-					.width(swapchain_extent.width)
-					.height(swapchain_extent.height)
-					.layers(1);
-
-				device.create_framebuffer(&create_info, None)
-			})
-			.collect::<Result<Vec<_>, _>>()?;
+		let pipeline = create_pipeline(
+			&device,
+			render_pass,
+			pipeline_layout,
+			vertex_shader_module,
+			fragment_shader_module,
+			true,
+		)?;
+
+		let blur = Blur::new(
+			&device,
+			format,
+			final_layout,
+			pipeline_layout,
+			vertex_shader_module,
+		)?;
 
 		let info = vk::CommandPoolCreateInfo::builder()
-			.flags(vk::CommandPoolCreateFlags::empty())
+			.flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
 			.queue_family_index(queue_family_indices.graphics);
 
 		let command_pool = device.create_command_pool(&info, None)?;
@@ -396,126 +359,328 @@ impl Renderer {
 		let allocate_info = vk::CommandBufferAllocateInfo::builder()
 			.command_pool(command_pool)
 			.level(vk::CommandBufferLevel::PRIMARY)
-			.command_buffer_count(framebuffers.len() as u32);
+			.command_buffer_count(1);
 
-		let command_buffers =
-			device.allocate_command_buffers(&allocate_info)?;
+		let upload_command_buffer =
+			device.allocate_command_buffers(&allocate_info)?[0];
+		let upload_fence =
+			device.create_fence(&vk::FenceCreateInfo::builder(), None)?;
 
-		for (i, command_buffer) in command_buffers.iter().enumerate() {
-			let info = vk::CommandBufferBeginInfo::builder();
+		let mut renderer = Self {
+			instance,
+			debug_messenger,
+			device,
+			physical_device,
+			graphics_queue,
+			present_queue,
+			queue_family_indices,
+			swapchain_format: format,
+			fragment_shader_module,
+			vertex_shader_module,
+			sampler,
+			descriptor_set_layout,
+			descriptor_pool,
+			pipeline_layout,
+			render_pass,
+			pipeline,
+			blur,
+			command_pool,
+			targets: Vec::new(),
+			textures: HashMap::new(),
+			next_texture_id: 0,
+			white_texture: 0,
+			external_images: HashMap::new(),
+			next_external_image_id: 0,
+			dmabuf: None,
+			staging_buffer: vk::Buffer::null(),
+			staging_memory: vk::DeviceMemory::null(),
+			staging_size: 0,
+			upload_command_buffer,
+			upload_fence,
+			#[cfg(all(debug_assertions, feature = "hot-reload"))]
+			shader_watcher: Default::default(),
+		};
 
-			device.begin_command_buffer(*command_buffer, &info)?;
+		renderer.set_object_name(renderer.pipeline, "quad pipeline");
+		renderer.set_object_name(renderer.render_pass, "render pass");
+		renderer.set_object_name(
+			renderer.blur.backdrop_pipeline,
+			"backdrop pipeline",
+		);
+
+		renderer.white_texture = renderer.upload_texture(
+			None,
+			ImageData {
+				data: &[255; 4],
+				width: 1,
+				height: 1,
+				stride: 4,
+				format: vk::Format::R8G8B8A8_UNORM,
+				opaque: false,
+			},
+		)?;
+		renderer.set_texture_name(renderer.white_texture, "white texture");
 
-			let render_area = vk::Rect2D::builder()
-				.offset(vk::Offset2D::default())
-				.extent(swapchain_extent);
+		Ok(renderer)
+	}
 
-			let color_clear_value = vk::ClearValue {
-				color: vk::ClearColorValue {
-					float32: [0.0, 0.0, 0.0, 1.0],
-				},
+	/// Records the commands drawing `scene` into the framebuffer of the
+	/// image `image_index` of `target`.
+	unsafe fn record_command_buffer(
+		&self,
+		target: &Target,
+		image_index: usize,
+		scene: &[SceneElement],
+	) -> Result<()> {
+		let command_buffer = target.command_buffers[image_index];
+
+		self.device.reset_command_buffer(
+			command_buffer,
+			vk::CommandBufferResetFlags::empty(),
+		)?;
+
+		let info = vk::CommandBufferBeginInfo::builder()
+			.flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+		self.device.begin_command_buffer(command_buffer, &info)?;
+
+		// Glass elements split the scene: what is drawn before one of them
+		// is blurred and drawn back behind it.
+		let mut segments = Vec::new();
+		let mut start = 0;
+		if self.blur.enabled(target) {
+			for (index, element) in scene.iter().enumerate() {
+				if element.blur {
+					segments.push(&scene[start..index]);
+					start = index;
+				}
+			}
+		}
+		segments.push(&scene[start..]);
+
+		let last = segments.len() - 1;
+		for (index, segment) in segments.into_iter().enumerate() {
+			let render_pass = match (index == 0, index == last) {
+				(true, true) => self.render_pass,
+				(true, false) => self.blur.clear_to_blit_render_pass,
+				(false, false) => self.blur.load_to_blit_render_pass,
+				(false, true) => self.blur.load_to_final_render_pass,
 			};
 
-			let clear_values = &[color_clear_value];
-			let info = vk::RenderPassBeginInfo::builder()
-				.render_pass(render_pass)
-				.framebuffer(framebuffers[i])
-				.render_area(render_area)
-				.clear_values(clear_values);
-
-			device.cmd_begin_render_pass(
-				*command_buffer,
-				&info,
-				vk::SubpassContents::INLINE,
-			);
-			device.cmd_bind_pipeline(
-				*command_buffer,
-				vk::PipelineBindPoint::GRAPHICS,
-				pipeline,
+			if index > 0 {
+				self.record_blur(command_buffer, target, image_index);
+			}
+
+			self.begin_target_render_pass(
+				command_buffer,
+				target,
+				image_index,
+				render_pass,
 			);
-			device.cmd_draw(*command_buffer, 3, 1, 0, 0);
-			device.cmd_end_render_pass(*command_buffer);
 
-			device.end_command_buffer(*command_buffer)?;
+			if index > 0 {
+				self.record_backdrop(command_buffer, target, &segment[0]);
+			}
+			self.record_elements(command_buffer, target, segment);
+
+			self.device.cmd_end_render_pass(command_buffer);
 		}
 
-		let semaphore_info = vk::SemaphoreCreateInfo::builder();
-		let fence_info = vk::FenceCreateInfo::builder()
-			.flags(vk::FenceCreateFlags::SIGNALED);
+		self.device.end_command_buffer(command_buffer)?;
 
-		let mut image_available_semaphores = Vec::new();
-		let mut render_finished_semaphores = Vec::new();
-		let mut in_flight_fences = Vec::new();
+		Ok(())
+	}
 
-		for _ in 0..MAX_FRAMES_IN_FLIGHT {
-			image_available_semaphores
-				.push(device.create_semaphore(&semaphore_info, None)?);
-			render_finished_semaphores
-				.push(device.create_semaphore(&semaphore_info, None)?);
-			in_flight_fences.push(device.create_fence(&fence_info, None)?);
-		}
+	/// Begins `render_pass` into the image `image_index` of `target`,
+	/// clearing it if the render pass does, and sets the viewport to the
+	/// whole of it.
+	unsafe fn begin_target_render_pass(
+		&self,
+		command_buffer: vk::CommandBuffer,
+		target: &Target,
+		image_index: usize,
+		render_pass: vk::RenderPass,
+	) {
+		let render_area = vk::Rect2D::builder()
+			.offset(vk::Offset2D::default())
+			.extent(target.swapchain_extent);
+
+		let color_clear_value = vk::ClearValue {
+			color: vk::ClearColorValue {
+				float32: [0.0, 0.0, 0.0, 1.0],
+			},
+		};
 
-		let images_in_flight =
-			swapchain_images.iter().map(|_| vk::Fence::null()).collect();
+		let clear_values = &[color_clear_value];
+		let info = vk::RenderPassBeginInfo::builder()
+			.render_pass(render_pass)
+			.framebuffer(target.framebuffers[image_index])
+			.render_area(render_area)
+			.clear_values(clear_values);
 
-		Ok(Self {
-			instance,
-			device,
-			physical_device,
-			graphics_queue,
-			present_queue,
-			queue_family_indices,
-			surface,
-			swapchain,
-			swapchain_images,
-			swapchain_image_views,
-			swapchain_format: surface_format.format,
-			swapchain_extent,
-			fragment_shader_module,
-			vertex_shader_module,
-			pipeline_layout,
-			render_pass,
-			pipeline,
-			framebuffers,
-			command_pool,
-			command_buffers,
-			image_available_semaphores,
-			render_finished_semaphores,
-			in_flight_fences,
-			images_in_flight,
-			frame: 0,
-		})
+		self.device.cmd_begin_render_pass(
+			command_buffer,
+			&info,
+			vk::SubpassContents::INLINE,
+		);
+
+		let viewport = vk::Viewport::builder()
+			.x(0.0)
+			.y(0.0)
+			.width(target.swapchain_extent.width as f32)
+			.height(target.swapchain_extent.height as f32)
+			.min_depth(0.0)
+			.max_depth(1.0);
+		self.device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+		self.device
+			.cmd_set_scissor(command_buffer, 0, &[render_area]);
 	}
 
+	/// Records the draws of `elements` into `target` with the quad pipeline.
+	unsafe fn record_elements(
+		&self,
+		command_buffer: vk::CommandBuffer,
+		target: &Target,
+		elements: &[SceneElement],
+	) {
+		self.device.cmd_bind_pipeline(
+			command_buffer,
+			vk::PipelineBindPoint::GRAPHICS,
+			self.pipeline,
+		);
+
+		for element in elements {
+			let (texture, colors) = match element.fill {
+				Fill::Texture(texture) => (texture, [[1.0; 4]; 2]),
+				Fill::Gradient { top, bottom } => {
+					(self.white_texture, [top, bottom])
+				}
+			};
+			let Some(texture) = self.textures.get(&texture) else {
+				continue;
+			};
+
+			self.device.cmd_bind_descriptor_sets(
+				command_buffer,
+				vk::PipelineBindPoint::GRAPHICS,
+				self.pipeline_layout,
+				0,
+				&[texture.descriptor_set],
+				&[],
+			);
+
+			let push_constants = QuadPushConstants {
+				rect: [
+					element.geometry.x as f32,
+					element.geometry.y as f32,
+					element.geometry.width as f32,
+					element.geometry.height as f32,
+				],
+				output_size: [
+					target.swapchain_extent.width as f32,
+					target.swapchain_extent.height as f32,
+				],
+				opacity: element.opacity,
+				corner_radius: element.corner_radius,
+				color_top: colors[0],
+				color_bottom: colors[1],
+				source: element.source,
+				transform: element.transform as u32,
+			};
+			self.device.cmd_push_constants(
+				command_buffer,
+				self.pipeline_layout,
+				vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+				0,
+				std::slice::from_raw_parts(
+					&push_constants as *const _ as *const u8,
+					size_of::<QuadPushConstants>(),
+				),
+			);
+
+			self.device.cmd_draw(command_buffer, 6, 1, 0, 0);
+		}
+	}
+
+	/// Draws `scene` into the target `target` and presents it, recreating
+	/// the swapchain first if it no longer matches the surface. Frames are
+	/// skipped while the window has a zero size.
+	///
 	/// # Safety
-	pub unsafe fn render_frame(&mut self) -> Result<()> {
-		let in_flight_fence = self.in_flight_fences[self.frame];
+	pub unsafe fn render_frame(
+		&mut self,
+		target: usize,
+		scene: &[SceneElement],
+	) -> Result<()> {
+		#[cfg(all(debug_assertions, feature = "hot-reload"))]
+		self.reload_shaders();
+
+		self.with_target(target, |renderer, target| {
+			if target.surface.is_null() {
+				renderer.render_offscreen(target, scene)
+			} else {
+				renderer.present_frame(target, scene)
+			}
+		})
+	}
+
+	/// Draws `scene` into the next swapchain image of `target` and presents
+	/// it.
+	unsafe fn present_frame(
+		&self,
+		target: &mut Target,
+		scene: &[SceneElement],
+	) -> Result<()> {
+		if target.swapchain_dirty {
+			self.recreate_target_swapchain(target, target.window_extent)?;
+		}
+		if target.swapchain.is_null() {
+			return Ok(());
+		}
+
+		let in_flight_fence = target.in_flight_fences[target.frame];
 
 		self.device
 			.wait_for_fences(&[in_flight_fence], true, u64::MAX)?;
 
-		let image_index = self
-			.device
-			.acquire_next_image_khr(
-				self.swapchain,
-				u64::MAX,
-				self.image_available_semaphores[self.frame],
-				vk::Fence::null(),
-			)?
-			.0 as usize;
-
-		let image_in_flight = self.images_in_flight[image_index];
+		let result = self.device.acquire_next_image_khr(
+			target.swapchain,
+			u64::MAX,
+			target.image_available_semaphores[target.frame],
+			vk::Fence::null(),
+		);
+
+		let image_index = match result {
+			Ok((image_index, code)) => {
+				// A suboptimal image can still be presented, so finish the
+				// frame and recreate afterwards.
+				if code == vk::SuccessCode::SUBOPTIMAL_KHR {
+					target.swapchain_dirty = true;
+				}
+				image_index as usize
+			}
+			Err(vk::ErrorCode::OUT_OF_DATE_KHR) => {
+				return self
+					.recreate_target_swapchain(target, target.window_extent);
+			}
+			Err(err) => return Err(err.into()),
+		};
+
+		let image_in_flight = target.images_in_flight[image_index];
 		if !image_in_flight.is_null() {
 			self.device
 				.wait_for_fences(&[image_in_flight], true, u64::MAX)?;
 		}
 
-		self.images_in_flight[image_index] = in_flight_fence;
+		target.images_in_flight[image_index] = in_flight_fence;
 
-		let wait_semaphores = &[self.image_available_semaphores[self.frame]];
+		self.record_command_buffer(target, image_index, scene)?;
+
+		let wait_semaphores =
+			&[target.image_available_semaphores[target.frame]];
 		let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-		let command_buffers = &[self.command_buffers[image_index]];
-		let signal_semaphores = &[self.render_finished_semaphores[self.frame]];
+		let command_buffers = &[target.command_buffers[image_index]];
+		let signal_semaphores =
+			&[target.render_finished_semaphores[target.frame]];
 		let submit_info = vk::SubmitInfo::builder()
 			.wait_semaphores(wait_semaphores)
 			.wait_dst_stage_mask(wait_stages)
@@ -530,17 +695,26 @@ impl Renderer {
 			in_flight_fence,
 		)?;
 
-		let swapchains = &[self.swapchain];
+		let swapchains = &[target.swapchain];
 		let image_indices = &[image_index as u32];
 		let present_info = vk::PresentInfoKHR::builder()
 			.wait_semaphores(signal_semaphores)
 			.swapchains(swapchains)
 			.image_indices(image_indices);
 
-		self.device
-			.queue_present_khr(self.present_queue, &present_info)?;
+		match self
+			.device
+			.queue_present_khr(self.present_queue, &present_info)
+		{
+			Ok(vk::SuccessCode::SUBOPTIMAL_KHR)
+			| Err(vk::ErrorCode::OUT_OF_DATE_KHR) => {
+				target.swapchain_dirty = true;
+			}
+			Ok(_) => {}
+			Err(err) => return Err(err.into()),
+		}
 
-		self.frame = (self.frame + 1) % MAX_FRAMES_IN_FLIGHT;
+		target.frame = (target.frame + 1) % MAX_FRAMES_IN_FLIGHT;
 
 		Ok(())
 	}
@@ -551,35 +725,325 @@ impl Drop for Renderer {
 		unsafe {
 			self.device.device_wait_idle().unwrap();
 
-			self.in_flight_fences
-				.iter()
-				.for_each(|f| self.device.destroy_fence(*f, None));
-			self.render_finished_semaphores
-				.iter()
-				.for_each(|s| self.device.destroy_semaphore(*s, None));
-			self.image_available_semaphores
-				.iter()
-				.for_each(|s| self.device.destroy_semaphore(*s, None));
+			self.textures
+				.values()
+				.for_each(|t| self.destroy_texture_resources(t));
+			self.external_images
+				.values()
+				.for_each(|i| self.destroy_external_image_resources(i));
+			self.device.destroy_buffer(self.staging_buffer, None);
+			self.device.free_memory(self.staging_memory, None);
+			self.device.destroy_fence(self.upload_fence, None);
+			let mut targets = std::mem::take(&mut self.targets);
+			targets.iter_mut().for_each(|t| self.destroy_target(t));
 			self.device.destroy_command_pool(self.command_pool, None);
-			self.framebuffers
-				.iter()
-				.for_each(|f| self.device.destroy_framebuffer(*f, None));
 			self.device.destroy_pipeline(self.pipeline, None);
+			self.blur.destroy(&self.device);
+			self.device
+				.destroy_shader_module(self.vertex_shader_module, None);
+			self.device
+				.destroy_shader_module(self.fragment_shader_module, None);
 			self.device
 				.destroy_pipeline_layout(self.pipeline_layout, None);
+			self.device
+				.destroy_descriptor_pool(self.descriptor_pool, None);
+			self.device.destroy_descriptor_set_layout(
+				self.descriptor_set_layout,
+				None,
+			);
+			self.device.destroy_sampler(self.sampler, None);
 			self.device.destroy_render_pass(self.render_pass, None);
-			self.swapchain_image_views
-				.iter()
-				.for_each(|v| self.device.destroy_image_view(*v, None));
-			self.device.destroy_swapchain_khr(self.swapchain, None);
 			self.device.destroy_device(None);
-			self.instance.destroy_surface_khr(self.surface, None);
+			if !self.debug_messenger.is_null() {
+				self.instance.destroy_debug_utils_messenger_ext(
+					self.debug_messenger,
+					None,
+				);
+			}
 
 			self.instance.destroy_instance(None);
 		}
 	}
 }
 
+/// # Safety
+unsafe fn create_instance(
+	extensions: &[vk::ExtensionName],
+) -> Result<(Instance, vk::DebugUtilsMessengerEXT)> {
+	let application_info = vk::ApplicationInfo::builder()
+		.application_name(b"macOS but it FUCKING SUCKS\0")
+		.application_version(vk::make_version(1, 0, 0))
+		.engine_name(b"No Engine\0")
+		.engine_version(vk::make_version(1, 0, 0))
+		.api_version(vk::make_version(1, 1, 0));
+
+	let loader = LibloadingLoader::new(LIBRARY)?;
+	let entry = Entry::new(loader).map_err(|e| {
+		anyhow::anyhow!("Failed to load vulkan library: {}", e.to_string())
+	})?;
+
+	let mut layers = Vec::new();
+	let mut extensions = extensions.to_vec();
+
+	if debug::validation_requested() {
+		let available = entry.enumerate_instance_layer_properties()?;
+		if available.iter().any(|l| l.layer_name == VALIDATION_LAYER) {
+			layers.push(VALIDATION_LAYER.as_ptr());
+		} else {
+			tracing::warn!("Validation requested, but the layer is missing");
+		}
+	}
+
+	// The messenger is only useful to report validation errors.
+	let debug_utils = !layers.is_empty()
+		&& entry
+			.enumerate_instance_extension_properties(None)?
+			.iter()
+			.any(|e| e.extension_name == vk::EXT_DEBUG_UTILS_EXTENSION.name);
+	if debug_utils {
+		extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION.name);
+	}
+
+	let extensions = extensions.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+	let flags = vk::InstanceCreateFlags::empty();
+
+	let mut messenger_info = debug::messenger_info();
+	let mut info = vk::InstanceCreateInfo::builder()
+		.application_info(&application_info)
+		.enabled_layer_names(&layers)
+		.enabled_extension_names(&extensions)
+		.flags(flags);
+	if debug_utils {
+		info = info.push_next(&mut messenger_info);
+	}
+
+	let instance = entry.create_instance(&info, None)?;
+
+	let messenger = if debug_utils {
+		instance.create_debug_utils_messenger_ext(&messenger_info, None)?
+	} else {
+		vk::DebugUtilsMessengerEXT::null()
+	};
+
+	Ok((instance, messenger))
+}
+
+/// The instance extension creating surfaces on the host display server.
+fn surface_extension(display: RawDisplayHandle) -> Result<vk::ExtensionName> {
+	match display {
+		RawDisplayHandle::Wayland(_) => {
+			Ok(vk::KHR_WAYLAND_SURFACE_EXTENSION.name)
+		}
+		RawDisplayHandle::Xlib(_) => Ok(vk::KHR_XLIB_SURFACE_EXTENSION.name),
+		RawDisplayHandle::Xcb(_) => Ok(vk::KHR_XCB_SURFACE_EXTENSION.name),
+		display => anyhow::bail!("Unsupported display server: {:?}.", display),
+	}
+}
+
+/// # Safety
+unsafe fn create_device(
+	instance: &Instance,
+	physical_device: vk::PhysicalDevice,
+	queue_family_indices: QueueFamilyIndices,
+	extensions: &[vk::ExtensionName],
+) -> Result<Device> {
+	let mut unique_indices = HashSet::new();
+	unique_indices.insert(queue_family_indices.graphics);
+	unique_indices.insert(queue_family_indices.present.unwrap());
+
+	let queue_priorities = &[1.0];
+	let queue_infos = unique_indices
+		.iter()
+		.map(|i| {
+			vk::DeviceQueueCreateInfo::builder()
+				.queue_family_index(*i)
+				.queue_priorities(queue_priorities)
+		})
+		.collect::<Vec<_>>();
+
+	let extensions = extensions.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+
+	// Device layers are deprecated, the instance ones apply to devices too.
+	let features = vk::PhysicalDeviceFeatures::builder();
+	let info = vk::DeviceCreateInfo::builder()
+		.queue_create_infos(&queue_infos)
+		.enabled_extension_names(&extensions)
+		.enabled_features(&features);
+
+	Ok(instance.create_device(physical_device, &info, None)?)
+}
+
+/// Creates a pipeline drawing quads, blending them over the target with
+/// premultiplied alpha if `blend` is set or replacing it otherwise.
+///
+/// # Safety
+unsafe fn create_pipeline(
+	device: &Device,
+	render_pass: vk::RenderPass,
+	pipeline_layout: vk::PipelineLayout,
+	vertex_shader_module: vk::ShaderModule,
+	fragment_shader_module: vk::ShaderModule,
+	blend: bool,
+) -> Result<vk::Pipeline> {
+	let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+		.stage(vk::ShaderStageFlags::VERTEX)
+		.module(vertex_shader_module)
+		.name(b"main\0");
+
+	let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+		.stage(vk::ShaderStageFlags::FRAGMENT)
+		.module(fragment_shader_module)
+		.name(b"main\0");
+
+	let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+
+	let input_assembly_state =
+		vk::PipelineInputAssemblyStateCreateInfo::builder()
+			.topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+			.primitive_restart_enable(false);
+
+	// Viewport and scissor are dynamic, only their count is fixed here.
+	let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+		.viewport_count(1)
+		.scissor_count(1);
+
+	let rasterization_state =
+		vk::PipelineRasterizationStateCreateInfo::builder()
+			.depth_clamp_enable(false)
+			.rasterizer_discard_enable(false)
+			.polygon_mode(PolygonMode::FILL)
+			.line_width(1.0)
+			.cull_mode(vk::CullModeFlags::NONE)
+			.front_face(vk::FrontFace::CLOCKWISE)
+			.depth_bias_enable(false);
+
+	let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+		.sample_shading_enable(false)
+		.rasterization_samples(vk::SampleCountFlags::_1);
+
+	let attachment = vk::PipelineColorBlendAttachmentState::builder()
+		.color_write_mask(vk::ColorComponentFlags::all())
+		.blend_enable(blend)
+		.src_color_blend_factor(vk::BlendFactor::ONE)
+		.dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+		.color_blend_op(vk::BlendOp::ADD)
+		.src_alpha_blend_factor(vk::BlendFactor::ONE)
+		.dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+		.alpha_blend_op(vk::BlendOp::ADD);
+
+	let attachments = &[attachment];
+	let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+		.logic_op_enable(false)
+		.logic_op(vk::LogicOp::COPY)
+		.attachments(attachments)
+		.blend_constants([0.0, 0.0, 0.0, 0.0]);
+
+	let dynamic_states =
+		&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+
+	let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder()
+		.dynamic_states(dynamic_states);
+
+	let stages = &[vert_stage, frag_stage];
+	let info = vk::GraphicsPipelineCreateInfo::builder()
+		.stages(stages)
+		.vertex_input_state(&vertex_input_state)
+		.input_assembly_state(&input_assembly_state)
+		.viewport_state(&viewport_state)
+		.rasterization_state(&rasterization_state)
+		.multisample_state(&multisample_state)
+		.color_blend_state(&color_blend_state)
+		.dynamic_state(&dynamic_state)
+		.layout(pipeline_layout)
+		.render_pass(render_pass)
+		.base_pipeline_handle(vk::Pipeline::null())
+		.base_pipeline_index(-1)
+		.subpass(0);
+
+	Ok(device
+		.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)?
+		.0[0])
+}
+
+/// Creates a render pass drawing into a single color attachment of
+/// `format`, taking it from `initial_layout` to `final_layout`.
+///
+/// The attachment may have been written, blitted or sampled just before,
+/// and may be blitted or sampled right after.
+///
+/// # Safety
+unsafe fn create_render_pass(
+	device: &Device,
+	format: vk::Format,
+	load_op: vk::AttachmentLoadOp,
+	initial_layout: vk::ImageLayout,
+	final_layout: vk::ImageLayout,
+) -> Result<vk::RenderPass> {
+	let color_attachment = vk::AttachmentDescription::builder()
+		.format(format)
+		.samples(vk::SampleCountFlags::_1)
+		.load_op(load_op)
+		.store_op(vk::AttachmentStoreOp::STORE)
+		.stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+		.stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+		.initial_layout(initial_layout)
+		.final_layout(final_layout);
+
+	let color_attachment_ref = vk::AttachmentReference::builder()
+		.attachment(0)
+		.layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+	let color_attachments = &[color_attachment_ref];
+	let subpass = vk::SubpassDescription::builder()
+		.pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+		.color_attachments(color_attachments);
+
+	let dependencies = &[
+		vk::SubpassDependency::builder()
+			.src_subpass(vk::SUBPASS_EXTERNAL)
+			.dst_subpass(0)
+			.src_stage_mask(
+				vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+					| vk::PipelineStageFlags::FRAGMENT_SHADER
+					| vk::PipelineStageFlags::TRANSFER,
+			)
+			.src_access_mask(
+				vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+					| vk::AccessFlags::TRANSFER_WRITE,
+			)
+			.dst_stage_mask(
+				vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+					| vk::PipelineStageFlags::FRAGMENT_SHADER,
+			)
+			.dst_access_mask(
+				vk::AccessFlags::COLOR_ATTACHMENT_READ
+					| vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+					| vk::AccessFlags::SHADER_READ,
+			),
+		vk::SubpassDependency::builder()
+			.src_subpass(0)
+			.dst_subpass(vk::SUBPASS_EXTERNAL)
+			.src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+			.src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+			.dst_stage_mask(
+				vk::PipelineStageFlags::FRAGMENT_SHADER
+					| vk::PipelineStageFlags::TRANSFER,
+			)
+			.dst_access_mask(
+				vk::AccessFlags::SHADER_READ | vk::AccessFlags::TRANSFER_READ,
+			),
+	];
+
+	let attachments = &[color_attachment];
+	let subpasses = &[subpass];
+	let info = vk::RenderPassCreateInfo::builder()
+		.attachments(attachments)
+		.subpasses(subpasses)
+		.dependencies(dependencies);
+
+	Ok(device.create_render_pass(&info, None)?)
+}
+
 /// # Safety
 pub unsafe fn create_shader_module(
 	device: &Device,
@@ -595,34 +1059,93 @@ pub unsafe fn create_shader_module(
 	Ok(device.create_shader_module(&info, None)?)
 }
 
+/// # Safety
+pub unsafe fn get_memory_type_index(
+	instance: &Instance,
+	physical_device: vk::PhysicalDevice,
+	properties: vk::MemoryPropertyFlags,
+	requirements: vk::MemoryRequirements,
+) -> Result<u32> {
+	let memory =
+		instance.get_physical_device_memory_properties(physical_device);
+
+	(0..memory.memory_type_count)
+		.find(|i| {
+			let suitable = (requirements.memory_type_bits & (1 << i)) != 0;
+			let memory_type = memory.memory_types[*i as usize];
+			suitable && memory_type.property_flags.contains(properties)
+		})
+		.context("Failed to find suitable memory type.")
+}
+
+/// What a [`SceneElement`] is filled with.
 #[derive(Copy, Clone, Debug)]
-pub struct QueueFamilyIndices {
-	pub graphics: u32,
-	pub present: Option<u32>,
+pub enum Fill {
+	Texture(TextureId),
+	/// Premultiplied colors, blended from the top edge to the bottom one.
+	Gradient {
+		top: [f32; 4],
+		bottom: [f32; 4],
+	},
 }
 
-impl QueueFamilyIndices {
-	unsafe fn get(
-		instance: &Instance,
-		physical_device: vk::PhysicalDevice,
-	) -> Result<Self> {
-		let properties = instance
-			.get_physical_device_queue_family_properties(physical_device);
+/// A quad to composite, in output pixel coordinates.
+#[derive(Copy, Clone, Debug)]
+pub struct SceneElement {
+	pub fill: Fill,
+	pub geometry: Rectangle,
+	/// Part of the texture stretched over `geometry`, as x, y, width and
+	/// height in normalized coordinates of the texture once `transform` is
+	/// undone.
+	pub source: [f32; 4],
+	pub transform: TextureTransform,
+	pub opacity: f32,
+	/// Radius of the rounded corners in pixels, zero for square ones.
+	pub corner_radius: f32,
+	/// Whether what is below the element is blurred where it is drawn.
+	pub blur: bool,
+}
 
-		let graphics = properties
-			.iter()
-			.position(|p| p.queue_flags.contains(vk::QueueFlags::GRAPHICS))
-			.map(|i| i as u32);
+impl SceneElement {
+	/// Samples the whole texture.
+	pub const WHOLE_TEXTURE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+}
 
-		if let Some(graphics) = graphics {
-			Ok(Self {
-				graphics,
-				present: None,
-			})
-		} else {
-			Err(anyhow::anyhow!("Missing required queue families."))
-		}
-	}
+/// How the contents of a texture were rotated counter-clockwise and flipped
+/// around the vertical axis, which is undone when drawing it. Numbered like
+/// `wl_output.transform`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TextureTransform {
+	#[default]
+	Normal = 0,
+	Rotated90 = 1,
+	Rotated180 = 2,
+	Rotated270 = 3,
+	Flipped = 4,
+	Flipped90 = 5,
+	Flipped180 = 6,
+	Flipped270 = 7,
+}
+
+/// Matches the `PushConstants` block of `quad.fragment.glsl`, which starts
+/// with the one of `quad.vertex.glsl`.
+#[repr(C)]
+struct QuadPushConstants {
+	rect: [f32; 4],
+	output_size: [f32; 2],
+	opacity: f32,
+	corner_radius: f32,
+	color_top: [f32; 4],
+	color_bottom: [f32; 4],
+	source: [f32; 4],
+	transform: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct QueueFamilyIndices {
+	pub graphics: u32,
+	pub present: Option<u32>,
 }
 
 #[derive(Clone, Debug)]
@@ -658,15 +1181,18 @@ impl SwapchainSupport {
 	}
 }
 
+/// Picks a UNORM format, so that client pixels, which are already sRGB
+/// encoded, reach the window unchanged just like the offscreen images.
 pub fn get_swapchain_surface_format(
 	formats: &[vk::SurfaceFormatKHR],
 ) -> vk::SurfaceFormatKHR {
-	formats
+	[vk::Format::B8G8R8A8_UNORM, vk::Format::R8G8B8A8_UNORM]
 		.iter()
-		.cloned()
-		.find(|f| {
-			f.format == vk::Format::B8G8R8A8_SRGB
-				&& f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+		.find_map(|&format| {
+			formats.iter().cloned().find(|f| {
+				f.format == format
+					&& f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+			})
 		})
 		.unwrap_or_else(|| formats[0])
 }
@@ -682,18 +1208,18 @@ pub fn get_swapchain_present_mode(
 }
 
 pub fn get_swapchain_extent(
-	window: &winit::window::Window,
+	window_extent: vk::Extent2D,
 	capabilities: vk::SurfaceCapabilitiesKHR,
 ) -> vk::Extent2D {
 	if capabilities.current_extent.width != u32::MAX {
 		capabilities.current_extent
 	} else {
 		vk::Extent2D::builder()
-			.width(window.inner_size().width.clamp(
+			.width(window_extent.width.clamp(
 				capabilities.min_image_extent.width,
 				capabilities.max_image_extent.width,
 			))
-			.height(window.inner_size().height.clamp(
+			.height(window_extent.height.clamp(
 				capabilities.min_image_extent.height,
 				capabilities.max_image_extent.height,
 			))
@@ -0,0 +1,618 @@
+//! Frosted glass: the part of the frame already drawn below a glass element
+//! is blurred and drawn back behind it.
+//!
+//! The target image is blitted into the first of a chain of images, each
+//! half the size of the previous one, then blurred with the dual Kawase
+//! filter: downsampling passes walk down the chain and upsampling passes
+//! back up to the first image, which the backdrop pipeline samples.
+
+use anyhow::Result;
+use vulkanalia::{
+	vk::{self, DeviceV1_0 as _, HasBuilder as _, InstanceV1_0 as _},
+	Device,
+};
+
+use super::{
+	create_pipeline, create_render_pass, create_shader_module,
+	get_memory_type_index, Renderer, SceneElement, Target,
+};
+
+/// Upper bound of the downsampling passes, and of the images in the chain
+/// besides the first.
+const MAX_BLUR_PASSES: usize = 5;
+
+/// How the backdrop of glass elements is blurred.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BlurSettings {
+	/// Approximate radius in pixels, zero to disable blurring.
+	pub radius: f32,
+	/// Saturation of the blurred backdrop, 1.0 to keep its colors.
+	pub saturation: f32,
+}
+
+impl Default for BlurSettings {
+	fn default() -> Self {
+		Self {
+			radius: 16.0,
+			saturation: 1.3,
+		}
+	}
+}
+
+impl BlurSettings {
+	/// Number of downsampling passes and distance of their samples in
+	/// texels for the radius. Each pass doubles the reach of the filter.
+	fn passes(&self) -> (usize, f32) {
+		let passes = (self.radius.max(1.0).log2().round() as usize)
+			.saturating_sub(1)
+			.clamp(1, MAX_BLUR_PASSES);
+		let offset = self.radius / (1 << (passes + 1)) as f32;
+
+		(passes, offset)
+	}
+}
+
+/// An image of the blur chain.
+pub struct BlurLevel {
+	pub image: vk::Image,
+	pub memory: vk::DeviceMemory,
+	pub view: vk::ImageView,
+	pub framebuffer: vk::Framebuffer,
+	/// Binds `view` for the pass reading from this level.
+	pub descriptor_set: vk::DescriptorSet,
+	pub extent: vk::Extent2D,
+}
+
+/// Pipelines and render passes of the blur, shared by the targets which
+/// each own their chain of images.
+pub struct Blur {
+	pub settings: BlurSettings,
+	pub down_shader_module: vk::ShaderModule,
+	pub up_shader_module: vk::ShaderModule,
+	pub backdrop_shader_module: vk::ShaderModule,
+	/// Renders into the images of the chain, which it leaves ready to be
+	/// sampled.
+	pub level_render_pass: vk::RenderPass,
+	pub down_pipeline: vk::Pipeline,
+	pub up_pipeline: vk::Pipeline,
+	/// Draws the first image of the chain into the target image.
+	pub backdrop_pipeline: vk::Pipeline,
+	/// Render passes into the target image for the parts of the frame
+	/// around blurs: clearing it and leaving it ready to be blitted from,
+	/// resuming and leaving it ready to be blitted from again, or resuming
+	/// and leaving it as [`Renderer::render_pass`] does.
+	pub clear_to_blit_render_pass: vk::RenderPass,
+	pub load_to_blit_render_pass: vk::RenderPass,
+	pub load_to_final_render_pass: vk::RenderPass,
+}
+
+/// Matches the `PushConstants` blocks of `blur_down.fragment.glsl` and
+/// `blur_up.fragment.glsl`.
+#[repr(C)]
+struct BlurPushConstants {
+	rect: [f32; 4],
+	output_size: [f32; 2],
+	offset: f32,
+}
+
+/// Matches the `PushConstants` block of `backdrop.fragment.glsl`.
+#[repr(C)]
+struct BackdropPushConstants {
+	rect: [f32; 4],
+	output_size: [f32; 2],
+	opacity: f32,
+	corner_radius: f32,
+	saturation: f32,
+}
+
+impl Blur {
+	/// # Safety
+	pub(super) unsafe fn new(
+		device: &Device,
+		format: vk::Format,
+		final_layout: vk::ImageLayout,
+		pipeline_layout: vk::PipelineLayout,
+		vertex_shader_module: vk::ShaderModule,
+	) -> Result<Self> {
+		let down =
+			include_bytes!(concat!(env!("OUT_DIR"), "/blur_down.fragment.spv"));
+		let up =
+			include_bytes!(concat!(env!("OUT_DIR"), "/blur_up.fragment.spv"));
+		let backdrop =
+			include_bytes!(concat!(env!("OUT_DIR"), "/backdrop.fragment.spv"));
+
+		let down_shader_module = create_shader_module(device, &down[..])?;
+		let up_shader_module = create_shader_module(device, &up[..])?;
+		let backdrop_shader_module =
+			create_shader_module(device, &backdrop[..])?;
+
+		let level_render_pass = create_render_pass(
+			device,
+			format,
+			vk::AttachmentLoadOp::DONT_CARE,
+			vk::ImageLayout::UNDEFINED,
+			vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+		)?;
+		let clear_to_blit_render_pass = create_render_pass(
+			device,
+			format,
+			vk::AttachmentLoadOp::CLEAR,
+			vk::ImageLayout::UNDEFINED,
+			vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+		)?;
+		let load_to_blit_render_pass = create_render_pass(
+			device,
+			format,
+			vk::AttachmentLoadOp::LOAD,
+			vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+			vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+		)?;
+		let load_to_final_render_pass = create_render_pass(
+			device,
+			format,
+			vk::AttachmentLoadOp::LOAD,
+			vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+			final_layout,
+		)?;
+
+		let [down_pipeline, up_pipeline, backdrop_pipeline] =
+			Self::create_pipelines(
+				device,
+				pipeline_layout,
+				level_render_pass,
+				clear_to_blit_render_pass,
+				vertex_shader_module,
+				[down_shader_module, up_shader_module, backdrop_shader_module],
+			)?;
+
+		Ok(Self {
+			settings: BlurSettings::default(),
+			down_shader_module,
+			up_shader_module,
+			backdrop_shader_module,
+			level_render_pass,
+			down_pipeline,
+			up_pipeline,
+			backdrop_pipeline,
+			clear_to_blit_render_pass,
+			load_to_blit_render_pass,
+			load_to_final_render_pass,
+		})
+	}
+
+	/// Creates the down, up and backdrop pipelines, drawing with the
+	/// fragment shaders of `fragment_shader_modules` in that order.
+	///
+	/// # Safety
+	pub(super) unsafe fn create_pipelines(
+		device: &Device,
+		pipeline_layout: vk::PipelineLayout,
+		level_render_pass: vk::RenderPass,
+		backdrop_render_pass: vk::RenderPass,
+		vertex_shader_module: vk::ShaderModule,
+		fragment_shader_modules: [vk::ShaderModule; 3],
+	) -> Result<[vk::Pipeline; 3]> {
+		let [down, up, backdrop] = fragment_shader_modules;
+		// The passes of the chain overwrite every pixel, so they do not
+		// blend.
+		let passes = [
+			(level_render_pass, down, false),
+			(level_render_pass, up, false),
+			(backdrop_render_pass, backdrop, true),
+		];
+
+		let mut pipelines = Vec::new();
+		for (render_pass, fragment_shader_module, blend) in passes {
+			let pipeline = create_pipeline(
+				device,
+				render_pass,
+				pipeline_layout,
+				vertex_shader_module,
+				fragment_shader_module,
+				blend,
+			);
+			match pipeline {
+				Ok(pipeline) => pipelines.push(pipeline),
+				Err(err) => {
+					for pipeline in pipelines {
+						device.destroy_pipeline(pipeline, None);
+					}
+					return Err(err);
+				}
+			}
+		}
+
+		Ok([pipelines[0], pipelines[1], pipelines[2]])
+	}
+
+	/// Whether glass elements drawn into `target` get a blurred backdrop.
+	pub fn enabled(&self, target: &Target) -> bool {
+		self.settings.radius > 0.0 && !target.blur_levels.is_empty()
+	}
+
+	/// # Safety
+	pub(super) unsafe fn destroy(&mut self, device: &Device) {
+		device.destroy_pipeline(self.down_pipeline, None);
+		device.destroy_pipeline(self.up_pipeline, None);
+		device.destroy_pipeline(self.backdrop_pipeline, None);
+		device.destroy_shader_module(self.down_shader_module, None);
+		device.destroy_shader_module(self.up_shader_module, None);
+		device.destroy_shader_module(self.backdrop_shader_module, None);
+		device.destroy_render_pass(self.level_render_pass, None);
+		device.destroy_render_pass(self.clear_to_blit_render_pass, None);
+		device.destroy_render_pass(self.load_to_blit_render_pass, None);
+		device.destroy_render_pass(self.load_to_final_render_pass, None);
+	}
+}
+
+impl Renderer {
+	/// Creates the blur chain for the images of `target`, if their format
+	/// allows blitting and sampling.
+	///
+	/// # Safety
+	pub(super) unsafe fn create_blur_levels(
+		&self,
+		target: &mut Target,
+	) -> Result<()> {
+		let required = vk::FormatFeatureFlags::BLIT_SRC
+			| vk::FormatFeatureFlags::BLIT_DST
+			| vk::FormatFeatureFlags::COLOR_ATTACHMENT
+			| vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+		let features = self
+			.instance
+			.get_physical_device_format_properties(
+				self.physical_device,
+				self.swapchain_format,
+			)
+			.optimal_tiling_features;
+		if !features.contains(required) {
+			tracing::warn!(
+				"{:?} cannot be blitted, glass will not be blurred",
+				self.swapchain_format
+			);
+			return Ok(());
+		}
+
+		let mut extent = target.swapchain_extent;
+		for index in 0..=MAX_BLUR_PASSES {
+			extent = vk::Extent2D {
+				width: (extent.width / 2).max(1),
+				height: (extent.height / 2).max(1),
+			};
+
+			let level = self.create_blur_level(extent)?;
+			self.set_object_name(level.image, &format!("blur level {}", index));
+			target.blur_levels.push(level);
+		}
+
+		Ok(())
+	}
+
+	unsafe fn create_blur_level(
+		&self,
+		extent: vk::Extent2D,
+	) -> Result<BlurLevel> {
+		let info = vk::ImageCreateInfo::builder()
+			.image_type(vk::ImageType::_2D)
+			.extent(vk::Extent3D {
+				width: extent.width,
+				height: extent.height,
+				depth: 1,
+			})
+			.mip_levels(1)
+			.array_layers(1)
+			.format(self.swapchain_format)
+			.tiling(vk::ImageTiling::OPTIMAL)
+			.initial_layout(vk::ImageLayout::UNDEFINED)
+			.usage(
+				vk::ImageUsageFlags::COLOR_ATTACHMENT
+					| vk::ImageUsageFlags::SAMPLED
+					| vk::ImageUsageFlags::TRANSFER_DST,
+			)
+			.sharing_mode(vk::SharingMode::EXCLUSIVE)
+			.samples(vk::SampleCountFlags::_1);
+
+		let image = self.device.create_image(&info, None)?;
+
+		let requirements = self.device.get_image_memory_requirements(image);
+		let info = vk::MemoryAllocateInfo::builder()
+			.allocation_size(requirements.size)
+			.memory_type_index(get_memory_type_index(
+				&self.instance,
+				self.physical_device,
+				vk::MemoryPropertyFlags::DEVICE_LOCAL,
+				requirements,
+			)?);
+
+		let memory = self.device.allocate_memory(&info, None)?;
+		self.device.bind_image_memory(image, memory, 0)?;
+
+		let subresource_range = vk::ImageSubresourceRange::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.base_mip_level(0)
+			.level_count(1)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		let info = vk::ImageViewCreateInfo::builder()
+			.image(image)
+			.view_type(vk::ImageViewType::_2D)
+			.format(self.swapchain_format)
+			.subresource_range(subresource_range);
+
+		let view = self.device.create_image_view(&info, None)?;
+
+		let attachments = &[view];
+		let info = vk::FramebufferCreateInfo::builder()
+			.render_pass(self.blur.level_render_pass)
+			.attachments(attachments)
+			.width(extent.width)
+			.height(extent.height)
+			.layers(1);
+
+		let framebuffer = self.device.create_framebuffer(&info, None)?;
+
+		let set_layouts = &[self.descriptor_set_layout];
+		let info = vk::DescriptorSetAllocateInfo::builder()
+			.descriptor_pool(self.descriptor_pool)
+			.set_layouts(set_layouts);
+
+		let descriptor_set = self.device.allocate_descriptor_sets(&info)?[0];
+
+		let image_info = vk::DescriptorImageInfo::builder()
+			.image_view(view)
+			.image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+		let image_infos = &[image_info];
+		let write = vk::WriteDescriptorSet::builder()
+			.dst_set(descriptor_set)
+			.dst_binding(0)
+			.dst_array_element(0)
+			.descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+			.image_info(image_infos);
+
+		self.device
+			.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+
+		Ok(BlurLevel {
+			image,
+			memory,
+			view,
+			framebuffer,
+			descriptor_set,
+			extent,
+		})
+	}
+
+	/// # Safety
+	pub(super) unsafe fn destroy_blur_levels(&self, target: &mut Target) {
+		for level in target.blur_levels.drain(..) {
+			let _ = self.device.free_descriptor_sets(
+				self.descriptor_pool,
+				&[level.descriptor_set],
+			);
+			self.device.destroy_framebuffer(level.framebuffer, None);
+			self.device.destroy_image_view(level.view, None);
+			self.device.destroy_image(level.image, None);
+			self.device.free_memory(level.memory, None);
+		}
+	}
+
+	/// Blurs the image `image_index` of `target`, which the last render
+	/// pass left ready to be blitted from, into the first image of its
+	/// chain.
+	///
+	/// # Safety
+	pub(super) unsafe fn record_blur(
+		&self,
+		command_buffer: vk::CommandBuffer,
+		target: &Target,
+		image_index: usize,
+	) {
+		let levels = &target.blur_levels;
+		let (passes, offset) = self.blur.settings.passes();
+		let passes = passes.min(levels.len() - 1);
+
+		// The previous blur of this or another frame may still be rendering
+		// into or sampling the first image.
+		self.cmd_transition_image(
+			command_buffer,
+			levels[0].image,
+			(
+				vk::ImageLayout::UNDEFINED,
+				vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+					| vk::PipelineStageFlags::FRAGMENT_SHADER,
+				vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+			),
+			(
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::AccessFlags::TRANSFER_WRITE,
+			),
+		);
+
+		let subresource = vk::ImageSubresourceLayers::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.mip_level(0)
+			.base_array_layer(0)
+			.layer_count(1);
+		let corner = |extent: vk::Extent2D| vk::Offset3D {
+			x: extent.width as i32,
+			y: extent.height as i32,
+			z: 1,
+		};
+		let region = vk::ImageBlit::builder()
+			.src_subresource(subresource)
+			.src_offsets([
+				vk::Offset3D::default(),
+				corner(target.swapchain_extent),
+			])
+			.dst_subresource(subresource)
+			.dst_offsets([vk::Offset3D::default(), corner(levels[0].extent)]);
+
+		self.device.cmd_blit_image(
+			command_buffer,
+			target.swapchain_images[image_index],
+			vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+			levels[0].image,
+			vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+			&[region],
+			vk::Filter::LINEAR,
+		);
+
+		self.cmd_transition_image(
+			command_buffer,
+			levels[0].image,
+			(
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::AccessFlags::TRANSFER_WRITE,
+			),
+			(
+				vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+				vk::PipelineStageFlags::FRAGMENT_SHADER,
+				vk::AccessFlags::SHADER_READ,
+			),
+		);
+
+		for index in 0..passes {
+			self.record_blur_pass(
+				command_buffer,
+				self.blur.down_pipeline,
+				&levels[index],
+				&levels[index + 1],
+				offset,
+			);
+		}
+		for index in (0..passes).rev() {
+			self.record_blur_pass(
+				command_buffer,
+				self.blur.up_pipeline,
+				&levels[index + 1],
+				&levels[index],
+				offset,
+			);
+		}
+	}
+
+	/// Renders `source` into the whole of `target` with `pipeline`.
+	unsafe fn record_blur_pass(
+		&self,
+		command_buffer: vk::CommandBuffer,
+		pipeline: vk::Pipeline,
+		source: &BlurLevel,
+		target: &BlurLevel,
+		offset: f32,
+	) {
+		let render_area = vk::Rect2D::builder()
+			.offset(vk::Offset2D::default())
+			.extent(target.extent);
+
+		let info = vk::RenderPassBeginInfo::builder()
+			.render_pass(self.blur.level_render_pass)
+			.framebuffer(target.framebuffer)
+			.render_area(render_area);
+
+		self.device.cmd_begin_render_pass(
+			command_buffer,
+			&info,
+			vk::SubpassContents::INLINE,
+		);
+		self.device.cmd_bind_pipeline(
+			command_buffer,
+			vk::PipelineBindPoint::GRAPHICS,
+			pipeline,
+		);
+
+		let (width, height) =
+			(target.extent.width as f32, target.extent.height as f32);
+		let viewport = vk::Viewport::builder()
+			.width(width)
+			.height(height)
+			.max_depth(1.0);
+		self.device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+		self.device
+			.cmd_set_scissor(command_buffer, 0, &[render_area]);
+
+		self.device.cmd_bind_descriptor_sets(
+			command_buffer,
+			vk::PipelineBindPoint::GRAPHICS,
+			self.pipeline_layout,
+			0,
+			&[source.descriptor_set],
+			&[],
+		);
+
+		let push_constants = BlurPushConstants {
+			rect: [0.0, 0.0, width, height],
+			output_size: [width, height],
+			offset,
+		};
+		self.device.cmd_push_constants(
+			command_buffer,
+			self.pipeline_layout,
+			vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+			0,
+			std::slice::from_raw_parts(
+				&push_constants as *const _ as *const u8,
+				size_of::<BlurPushConstants>(),
+			),
+		);
+
+		self.device.cmd_draw(command_buffer, 6, 1, 0, 0);
+		self.device.cmd_end_render_pass(command_buffer);
+	}
+
+	/// Draws the result of [`Renderer::record_blur`] behind `element`, in
+	/// a render pass into an image of `target`.
+	///
+	/// # Safety
+	pub(super) unsafe fn record_backdrop(
+		&self,
+		command_buffer: vk::CommandBuffer,
+		target: &Target,
+		element: &SceneElement,
+	) {
+		self.device.cmd_bind_pipeline(
+			command_buffer,
+			vk::PipelineBindPoint::GRAPHICS,
+			self.blur.backdrop_pipeline,
+		);
+		self.device.cmd_bind_descriptor_sets(
+			command_buffer,
+			vk::PipelineBindPoint::GRAPHICS,
+			self.pipeline_layout,
+			0,
+			&[target.blur_levels[0].descriptor_set],
+			&[],
+		);
+
+		let push_constants = BackdropPushConstants {
+			rect: [
+				element.geometry.x as f32,
+				element.geometry.y as f32,
+				element.geometry.width as f32,
+				element.geometry.height as f32,
+			],
+			output_size: [
+				target.swapchain_extent.width as f32,
+				target.swapchain_extent.height as f32,
+			],
+			opacity: element.opacity,
+			corner_radius: element.corner_radius,
+			saturation: self.blur.settings.saturation,
+		};
+		self.device.cmd_push_constants(
+			command_buffer,
+			self.pipeline_layout,
+			vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+			0,
+			std::slice::from_raw_parts(
+				&push_constants as *const _ as *const u8,
+				size_of::<BackdropPushConstants>(),
+			),
+		);
+
+		self.device.cmd_draw(command_buffer, 6, 1, 0, 0);
+	}
+}
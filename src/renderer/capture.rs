@@ -0,0 +1,177 @@
+use anyhow::Result;
+use vulkanalia::vk::{self, DeviceV1_0 as _, Handle as _, HasBuilder as _};
+
+use super::{Renderer, Target};
+
+/// Pixels of a rendered frame, read back from the GPU.
+#[derive(Clone, Debug)]
+pub struct FrameCapture {
+	pub width: u32,
+	pub height: u32,
+	/// Format of the image the frame was rendered into. The pixels are
+	/// converted to RGBA, but keep its encoding, e.g. sRGB.
+	pub format: vk::Format,
+	/// Tightly packed rows of 8-bit RGBA pixels.
+	pub data: Vec<u8>,
+}
+
+impl FrameCapture {
+	/// The RGBA value of the pixel at `(x, y)`.
+	pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+		let offset = ((y * self.width + x) * 4) as usize;
+		self.data[offset..offset + 4].try_into().unwrap()
+	}
+}
+
+impl Renderer {
+	/// Waits for the last frame rendered into the target `target` and copies
+	/// it into host memory through the staging buffer.
+	///
+	/// Only headless renderers keep their frames around, presented swapchain
+	/// images belong to the host compositor.
+	///
+	/// # Safety
+	pub unsafe fn capture_frame(
+		&mut self,
+		target: usize,
+	) -> Result<FrameCapture> {
+		self.with_target(target, |renderer, target| {
+			renderer.capture_target_frame(target)
+		})
+	}
+
+	unsafe fn capture_target_frame(
+		&mut self,
+		target: &Target,
+	) -> Result<FrameCapture> {
+		if !target.surface.is_null() {
+			anyhow::bail!("Frames can only be captured when headless.");
+		}
+		let Some(image_index) = target.last_image else {
+			anyhow::bail!("No frame was rendered yet.");
+		};
+
+		let extent = target.swapchain_extent;
+		let size = extent.width as vk::DeviceSize
+			* extent.height as vk::DeviceSize
+			* 4;
+		self.reserve_staging_buffer(size)?;
+
+		// The frame may still be in flight.
+		let frame = image_index % target.in_flight_fences.len();
+		self.device.wait_for_fences(
+			&[target.in_flight_fences[frame]],
+			true,
+			u64::MAX,
+		)?;
+
+		let image = target.swapchain_images[image_index];
+		let command_buffer = self.upload_command_buffer;
+
+		let info = vk::CommandBufferBeginInfo::builder()
+			.flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+		self.device.begin_command_buffer(command_buffer, &info)?;
+
+		// The render pass already left the image in the layout for copies,
+		// only its writes have to become visible.
+		self.cmd_transition_image(
+			command_buffer,
+			image,
+			(
+				vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+				vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+				vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+			),
+			(
+				vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::AccessFlags::TRANSFER_READ,
+			),
+		);
+
+		let subresource = vk::ImageSubresourceLayers::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.mip_level(0)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		let region = vk::BufferImageCopy::builder()
+			.buffer_offset(0)
+			.buffer_row_length(0)
+			.buffer_image_height(0)
+			.image_subresource(subresource)
+			.image_offset(vk::Offset3D::default())
+			.image_extent(vk::Extent3D {
+				width: extent.width,
+				height: extent.height,
+				depth: 1,
+			});
+
+		self.device.cmd_copy_image_to_buffer(
+			command_buffer,
+			image,
+			vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+			self.staging_buffer,
+			&[region],
+		);
+
+		let barrier = vk::BufferMemoryBarrier::builder()
+			.src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+			.dst_access_mask(vk::AccessFlags::HOST_READ)
+			.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.buffer(self.staging_buffer)
+			.offset(0)
+			.size(size);
+
+		self.device.cmd_pipeline_barrier(
+			command_buffer,
+			vk::PipelineStageFlags::TRANSFER,
+			vk::PipelineStageFlags::HOST,
+			vk::DependencyFlags::empty(),
+			&[] as &[vk::MemoryBarrier],
+			&[barrier],
+			&[] as &[vk::ImageMemoryBarrier],
+		);
+
+		self.device.end_command_buffer(command_buffer)?;
+
+		let command_buffers = &[command_buffer];
+		let submit_info =
+			vk::SubmitInfo::builder().command_buffers(command_buffers);
+
+		self.device.queue_submit(
+			self.graphics_queue,
+			&[submit_info],
+			self.upload_fence,
+		)?;
+		self.device
+			.wait_for_fences(&[self.upload_fence], true, u64::MAX)?;
+		self.device.reset_fences(&[self.upload_fence])?;
+
+		let memory = self.device.map_memory(
+			self.staging_memory,
+			0,
+			size,
+			vk::MemoryMapFlags::empty(),
+		)?;
+		let mut data =
+			std::slice::from_raw_parts(memory.cast::<u8>(), size as usize)
+				.to_vec();
+		self.device.unmap_memory(self.staging_memory);
+
+		if matches!(
+			self.swapchain_format,
+			vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB
+		) {
+			data.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+		}
+
+		Ok(FrameCapture {
+			width: extent.width,
+			height: extent.height,
+			format: self.swapchain_format,
+			data,
+		})
+	}
+}
@@ -0,0 +1,114 @@
+use std::ffi::{c_void, CStr, CString};
+
+use vulkanalia::vk::{
+	self, DeviceV1_0 as _, ExtDebugUtilsExtension as _, Handle as _,
+	HasBuilder as _,
+};
+
+use super::{Renderer, TextureId};
+
+/// Set to `1` or `0` to force the validation layer on or off. It defaults to
+/// on in debug builds.
+const VALIDATION_OVERRIDE: &str = "NEORA_VK_VALIDATION";
+
+/// Whether the validation layer should be enabled if it is installed.
+pub(super) fn validation_requested() -> bool {
+	match std::env::var(VALIDATION_OVERRIDE).as_deref() {
+		Ok("1") => true,
+		Ok("0") => false,
+		_ => cfg!(debug_assertions),
+	}
+}
+
+/// Settings of the messenger, also used for the messages about creating
+/// and destroying the instance itself.
+pub(super) fn messenger_info(
+) -> vk::DebugUtilsMessengerCreateInfoEXTBuilder<'static> {
+	vk::DebugUtilsMessengerCreateInfoEXT::builder()
+		.message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::all())
+		.message_type(
+			vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+				| vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+				| vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+		)
+		.user_callback(Some(debug_callback))
+}
+
+/// Forwards Vulkan messages to `tracing`, along with the names of the
+/// objects they are about.
+extern "system" fn debug_callback(
+	severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+	type_: vk::DebugUtilsMessageTypeFlagsEXT,
+	data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+	_: *mut c_void,
+) -> vk::Bool32 {
+	let data = unsafe { *data };
+	let message = unsafe { CStr::from_ptr(data.message) }.to_string_lossy();
+
+	let objects = (0..data.object_count as usize)
+		.map(|i| unsafe { *data.objects.add(i) })
+		.filter(|object| !object.object_name.is_null())
+		.map(|object| {
+			unsafe { CStr::from_ptr(object.object_name) }
+				.to_string_lossy()
+				.into_owned()
+		})
+		.collect::<Vec<_>>();
+	let objects = if objects.is_empty() {
+		String::new()
+	} else {
+		format!(" (objects: {})", objects.join(", "))
+	};
+
+	if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+		tracing::error!("({:?}) {}{}", type_, message, objects);
+	} else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING)
+	{
+		tracing::warn!("({:?}) {}{}", type_, message, objects);
+	} else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+		tracing::debug!("({:?}) {}{}", type_, message, objects);
+	} else {
+		tracing::trace!("({:?}) {}{}", type_, message, objects);
+	}
+
+	vk::FALSE
+}
+
+impl Renderer {
+	/// Names `handle` in validation messages and debugging tools. Does
+	/// nothing without a debug messenger.
+	///
+	/// # Safety
+	pub unsafe fn set_object_name<T: vk::Handle<Repr = u64>>(
+		&self,
+		handle: T,
+		name: &str,
+	) {
+		if self.debug_messenger.is_null() {
+			return;
+		}
+
+		let name = CString::new(name).unwrap_or_default();
+		let info = vk::DebugUtilsObjectNameInfoEXT::builder()
+			.object_type(T::TYPE)
+			.object_handle(handle.as_raw())
+			.object_name(name.as_bytes_with_nul());
+
+		self.instance
+			.set_debug_utils_object_name_ext(self.device.handle(), &info)
+			.unwrap_or_else(|err| {
+				tracing::warn!("Failed to name {:?}: {}", handle, err)
+			});
+	}
+
+	/// Names the image of the texture `id`, see
+	/// [`Renderer::set_object_name`].
+	///
+	/// # Safety
+	pub unsafe fn set_texture_name(&self, id: TextureId, name: &str) {
+		if let Some(texture) = self.textures.get(&id) {
+			self.set_object_name(texture.image, name);
+			self.set_object_name(texture.view, name);
+		}
+	}
+}
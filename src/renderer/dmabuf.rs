@@ -0,0 +1,516 @@
+//! Importing client dmabufs, which are copied into textures on the GPU like
+//! shm buffers are through the staging buffer.
+
+use std::os::fd::{AsRawFd as _, IntoRawFd as _, OwnedFd};
+
+use anyhow::{Context as _, Result};
+use vulkanalia::{
+	vk::{
+		self, DeviceV1_0 as _, HasBuilder as _, InstanceV1_0 as _,
+		InstanceV1_1 as _, KhrExternalMemoryFdExtension as _,
+	},
+	Instance,
+};
+
+use super::{Renderer, TextureId};
+
+/// Device extensions needed to import dmabufs, on top of Vulkan 1.1.
+const REQUIRED_EXTENSIONS: &[vk::ExtensionName] = &[
+	vk::KHR_EXTERNAL_MEMORY_FD_EXTENSION.name,
+	vk::EXT_EXTERNAL_MEMORY_DMA_BUF_EXTENSION.name,
+	vk::EXT_IMAGE_DRM_FORMAT_MODIFIER_EXTENSION.name,
+	vk::KHR_IMAGE_FORMAT_LIST_EXTENSION.name,
+	vk::EXT_QUEUE_FAMILY_FOREIGN_EXTENSION.name,
+];
+
+/// Identifies the DRM device of the physical device, for dmabuf feedback.
+const DRM_EXTENSION: vk::ExtensionName =
+	vk::EXT_PHYSICAL_DEVICE_DRM_EXTENSION.name;
+
+pub type ExternalImageId = u64;
+
+/// A plane of a dmabuf, as added to `zwp_linux_buffer_params_v1`.
+pub struct DmabufPlane {
+	pub fd: OwnedFd,
+	pub offset: u32,
+	pub stride: u32,
+}
+
+/// A client buffer in GPU memory.
+pub struct Dmabuf {
+	pub width: u32,
+	pub height: u32,
+	pub format: vk::Format,
+	/// Whether the alpha channel is undefined and must be treated as 1.0.
+	pub opaque: bool,
+	pub modifier: u64,
+	pub planes: Vec<DmabufPlane>,
+	/// Whether the first row is the bottom one.
+	pub y_invert: bool,
+}
+
+/// An imported dmabuf, copied from into textures.
+pub struct ExternalImage {
+	pub image: vk::Image,
+	pub memory: vk::DeviceMemory,
+	pub format: vk::Format,
+	pub extent: vk::Extent2D,
+	pub opaque: bool,
+	pub y_invert: bool,
+}
+
+/// What the device supports for importing dmabufs.
+pub struct DmabufSupport {
+	/// `dev_t` of the DRM node of the device, if the driver exposes it.
+	pub main_device: Option<u64>,
+}
+
+/// The extensions to enable for importing dmabufs on `physical_device`, or
+/// none if it cannot.
+///
+/// # Safety
+pub unsafe fn dmabuf_extensions(
+	instance: &Instance,
+	physical_device: vk::PhysicalDevice,
+) -> Result<Vec<vk::ExtensionName>> {
+	let available = instance
+		.enumerate_device_extension_properties(physical_device, None)?;
+	let has = |name: &vk::ExtensionName| {
+		available.iter().any(|a| a.extension_name == *name)
+	};
+
+	if !REQUIRED_EXTENSIONS.iter().all(has) {
+		tracing::info!("Dmabufs cannot be imported, clients will use shm");
+		return Ok(Vec::new());
+	}
+
+	let mut extensions = REQUIRED_EXTENSIONS.to_vec();
+	if has(&DRM_EXTENSION) {
+		extensions.push(DRM_EXTENSION);
+	}
+
+	Ok(extensions)
+}
+
+impl Renderer {
+	/// Enables dmabuf import if `extensions` are the ones returned by
+	/// [`dmabuf_extensions`] and the device was created with them.
+	///
+	/// # Safety
+	pub(super) unsafe fn init_dmabuf(
+		&mut self,
+		extensions: &[vk::ExtensionName],
+	) {
+		if extensions.is_empty() {
+			return;
+		}
+
+		let main_device = extensions.contains(&DRM_EXTENSION).then(|| {
+			let mut drm = vk::PhysicalDeviceDrmPropertiesEXT::default();
+			let mut properties =
+				vk::PhysicalDeviceProperties2::builder().push_next(&mut drm);
+			self.instance.get_physical_device_properties2(
+				self.physical_device,
+				&mut properties,
+			);
+
+			// Clients allocate from the render node when there is one.
+			match (drm.has_render, drm.has_primary) {
+				(vk::TRUE, _) => Some((drm.render_major, drm.render_minor)),
+				(_, vk::TRUE) => Some((drm.primary_major, drm.primary_minor)),
+				_ => None,
+			}
+		});
+		let main_device = main_device.flatten().map(|(major, minor)| {
+			rustix::fs::makedev(major as u32, minor as u32)
+		});
+
+		self.dmabuf = Some(DmabufSupport { main_device });
+	}
+
+	/// Modifiers of `format` that dmabufs can be imported with, empty if
+	/// dmabufs are not supported.
+	///
+	/// # Safety
+	pub unsafe fn dmabuf_modifiers(&self, format: vk::Format) -> Vec<u64> {
+		if self.dmabuf.is_none() {
+			return Vec::new();
+		}
+
+		let mut list = vk::DrmFormatModifierPropertiesListEXT::default();
+		let mut properties =
+			vk::FormatProperties2::builder().push_next(&mut list);
+		self.instance.get_physical_device_format_properties2(
+			self.physical_device,
+			format,
+			&mut properties,
+		);
+
+		let mut modifiers = vec![
+			vk::DrmFormatModifierPropertiesEXT::default();
+			list.drm_format_modifier_count as usize
+		];
+		let mut list = vk::DrmFormatModifierPropertiesListEXT::builder()
+			.drm_format_modifier_properties(&mut modifiers);
+		let mut properties =
+			vk::FormatProperties2::builder().push_next(&mut list);
+		self.instance.get_physical_device_format_properties2(
+			self.physical_device,
+			format,
+			&mut properties,
+		);
+
+		// Planes other than the first hold compression metadata, which is
+		// not supported.
+		modifiers
+			.iter()
+			.filter(|m| {
+				m.drm_format_modifier_plane_count == 1
+					&& m.drm_format_modifier_tiling_features.contains(
+						vk::FormatFeatureFlags::BLIT_SRC
+							| vk::FormatFeatureFlags::TRANSFER_SRC,
+					)
+			})
+			.map(|m| m.drm_format_modifier)
+			.filter(|modifier| self.can_import(format, *modifier))
+			.collect()
+	}
+
+	/// Whether images of `format` and `modifier` can be imported from
+	/// dmabufs and copied from.
+	unsafe fn can_import(&self, format: vk::Format, modifier: u64) -> bool {
+		let mut modifier_info =
+			vk::PhysicalDeviceImageDrmFormatModifierInfoEXT::builder()
+				.drm_format_modifier(modifier)
+				.sharing_mode(vk::SharingMode::EXCLUSIVE);
+		let mut external_info =
+			vk::PhysicalDeviceExternalImageFormatInfo::builder()
+				.handle_type(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT);
+		let info = vk::PhysicalDeviceImageFormatInfo2::builder()
+			.format(format)
+			.type_(vk::ImageType::_2D)
+			.tiling(vk::ImageTiling::DRM_FORMAT_MODIFIER_EXT)
+			.usage(vk::ImageUsageFlags::TRANSFER_SRC)
+			.push_next(&mut modifier_info)
+			.push_next(&mut external_info);
+
+		let mut external = vk::ExternalImageFormatProperties::default();
+		let mut properties =
+			vk::ImageFormatProperties2::builder().push_next(&mut external);
+
+		self.instance
+			.get_physical_device_image_format_properties2(
+				self.physical_device,
+				&info,
+				&mut properties,
+			)
+			.is_ok() && external
+			.external_memory_properties
+			.external_memory_features
+			.contains(vk::ExternalMemoryFeatureFlags::IMPORTABLE)
+	}
+
+	/// Imports `dmabuf`, which keeps its file descriptors for
+	/// [`Renderer::copy_external_image`].
+	///
+	/// # Safety
+	pub unsafe fn import_dmabuf(
+		&mut self,
+		dmabuf: &Dmabuf,
+	) -> Result<ExternalImageId> {
+		anyhow::ensure!(self.dmabuf.is_some(), "Dmabufs are not supported.");
+		let [plane] = &dmabuf.planes[..] else {
+			anyhow::bail!("Dmabufs with several planes are not supported.");
+		};
+
+		let plane_layouts = &[vk::SubresourceLayout {
+			offset: plane.offset as u64,
+			size: 0,
+			row_pitch: plane.stride as u64,
+			array_pitch: 0,
+			depth_pitch: 0,
+		}];
+		let mut modifier_info =
+			vk::ImageDrmFormatModifierExplicitCreateInfoEXT::builder()
+				.drm_format_modifier(dmabuf.modifier)
+				.plane_layouts(plane_layouts);
+		let mut external_info = vk::ExternalMemoryImageCreateInfo::builder()
+			.handle_types(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT);
+
+		let extent = vk::Extent2D {
+			width: dmabuf.width,
+			height: dmabuf.height,
+		};
+		let info = vk::ImageCreateInfo::builder()
+			.image_type(vk::ImageType::_2D)
+			.extent(vk::Extent3D {
+				width: extent.width,
+				height: extent.height,
+				depth: 1,
+			})
+			.mip_levels(1)
+			.array_layers(1)
+			.format(dmabuf.format)
+			.tiling(vk::ImageTiling::DRM_FORMAT_MODIFIER_EXT)
+			.initial_layout(vk::ImageLayout::UNDEFINED)
+			.usage(vk::ImageUsageFlags::TRANSFER_SRC)
+			.sharing_mode(vk::SharingMode::EXCLUSIVE)
+			.samples(vk::SampleCountFlags::_1)
+			.push_next(&mut modifier_info)
+			.push_next(&mut external_info);
+
+		let image = self.device.create_image(&info, None)?;
+
+		match self.bind_dmabuf_memory(image, &plane.fd) {
+			Ok(memory) => {
+				let id = self.next_external_image_id;
+				self.next_external_image_id += 1;
+				self.external_images.insert(
+					id,
+					ExternalImage {
+						image,
+						memory,
+						format: dmabuf.format,
+						extent,
+						opaque: dmabuf.opaque,
+						y_invert: dmabuf.y_invert,
+					},
+				);
+
+				Ok(id)
+			}
+			Err(err) => {
+				self.device.destroy_image(image, None);
+				Err(err)
+			}
+		}
+	}
+
+	/// Imports the memory of `fd` and binds it to `image`.
+	unsafe fn bind_dmabuf_memory(
+		&self,
+		image: vk::Image,
+		fd: &OwnedFd,
+	) -> Result<vk::DeviceMemory> {
+		let mut fd_properties = vk::MemoryFdPropertiesKHR::default();
+		self.device.get_memory_fd_properties_khr(
+			vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT,
+			fd.as_raw_fd(),
+			&mut fd_properties,
+		)?;
+
+		let requirements = self.device.get_image_memory_requirements(image);
+		let memory_type_bits =
+			requirements.memory_type_bits & fd_properties.memory_type_bits;
+		let memory_type_index = (0..u32::BITS)
+			.find(|i| memory_type_bits & (1 << i) != 0)
+			.context("No memory type can import the dmabuf.")?;
+
+		// Vulkan owns the descriptor once the import succeeds.
+		let fd = fd.try_clone()?;
+		let mut import_info = vk::ImportMemoryFdInfoKHR::builder()
+			.handle_type(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT)
+			.fd(fd.as_raw_fd());
+		let mut dedicated_info =
+			vk::MemoryDedicatedAllocateInfo::builder().image(image);
+		let info = vk::MemoryAllocateInfo::builder()
+			.allocation_size(requirements.size)
+			.memory_type_index(memory_type_index)
+			.push_next(&mut import_info)
+			.push_next(&mut dedicated_info);
+
+		let memory = self.device.allocate_memory(&info, None)?;
+		let _ = fd.into_raw_fd();
+
+		if let Err(err) = self.device.bind_image_memory(image, memory, 0) {
+			self.device.free_memory(memory, None);
+			return Err(err.into());
+		}
+
+		Ok(memory)
+	}
+
+	/// Copies the external image `image` into the texture `id`, or into a
+	/// new texture if `id` is `None` or does not match it. Returns once the
+	/// copy has completed on the GPU, after which the client may reuse its
+	/// buffer.
+	///
+	/// # Safety
+	pub unsafe fn copy_external_image(
+		&mut self,
+		id: Option<TextureId>,
+		image: ExternalImageId,
+	) -> Result<TextureId> {
+		let external = self
+			.external_images
+			.get(&image)
+			.context("Unknown external image.")?;
+		let (source, extent, y_invert) =
+			(external.image, external.extent, external.y_invert);
+
+		let id =
+			self.reserve_texture(id, external.format, extent, external.opaque)?;
+		let target = self.textures[&id].image;
+		let command_buffer = self.upload_command_buffer;
+
+		let info = vk::CommandBufferBeginInfo::builder()
+			.flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+		self.device.begin_command_buffer(command_buffer, &info)?;
+
+		let subresource_range = vk::ImageSubresourceRange::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.base_mip_level(0)
+			.level_count(1)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		// The client renders into the buffer from outside of Vulkan, so its
+		// image is acquired from the foreign queue family for the copy and
+		// released back after.
+		let acquire = vk::ImageMemoryBarrier::builder()
+			.old_layout(vk::ImageLayout::GENERAL)
+			.new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+			.src_queue_family_index(vk::QUEUE_FAMILY_FOREIGN_EXT)
+			.dst_queue_family_index(self.queue_family_indices.graphics)
+			.image(source)
+			.subresource_range(subresource_range)
+			.src_access_mask(vk::AccessFlags::empty())
+			.dst_access_mask(vk::AccessFlags::TRANSFER_READ);
+
+		self.device.cmd_pipeline_barrier(
+			command_buffer,
+			vk::PipelineStageFlags::TOP_OF_PIPE,
+			vk::PipelineStageFlags::TRANSFER,
+			vk::DependencyFlags::empty(),
+			&[] as &[vk::MemoryBarrier],
+			&[] as &[vk::BufferMemoryBarrier],
+			&[acquire],
+		);
+
+		// Previous frames may still sample the texture, see
+		// `upload_texture`.
+		self.cmd_transition_image(
+			command_buffer,
+			target,
+			(
+				vk::ImageLayout::UNDEFINED,
+				vk::PipelineStageFlags::FRAGMENT_SHADER,
+				vk::AccessFlags::empty(),
+			),
+			(
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::AccessFlags::TRANSFER_WRITE,
+			),
+		);
+
+		let subresource = vk::ImageSubresourceLayers::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.mip_level(0)
+			.base_array_layer(0)
+			.layer_count(1);
+		let (top, bottom) = (0, extent.height as i32);
+		let (top, bottom) = if y_invert {
+			(bottom, top)
+		} else {
+			(top, bottom)
+		};
+		let region = vk::ImageBlit::builder()
+			.src_subresource(subresource)
+			.src_offsets([
+				vk::Offset3D { x: 0, y: top, z: 0 },
+				vk::Offset3D {
+					x: extent.width as i32,
+					y: bottom,
+					z: 1,
+				},
+			])
+			.dst_subresource(subresource)
+			.dst_offsets([
+				vk::Offset3D::default(),
+				vk::Offset3D {
+					x: extent.width as i32,
+					y: extent.height as i32,
+					z: 1,
+				},
+			]);
+
+		self.device.cmd_blit_image(
+			command_buffer,
+			source,
+			vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+			target,
+			vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+			&[region],
+			vk::Filter::NEAREST,
+		);
+
+		let release = vk::ImageMemoryBarrier::builder()
+			.old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+			.new_layout(vk::ImageLayout::GENERAL)
+			.src_queue_family_index(self.queue_family_indices.graphics)
+			.dst_queue_family_index(vk::QUEUE_FAMILY_FOREIGN_EXT)
+			.image(source)
+			.subresource_range(subresource_range)
+			.src_access_mask(vk::AccessFlags::TRANSFER_READ)
+			.dst_access_mask(vk::AccessFlags::empty());
+
+		self.device.cmd_pipeline_barrier(
+			command_buffer,
+			vk::PipelineStageFlags::TRANSFER,
+			vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+			vk::DependencyFlags::empty(),
+			&[] as &[vk::MemoryBarrier],
+			&[] as &[vk::BufferMemoryBarrier],
+			&[release],
+		);
+
+		self.cmd_transition_image(
+			command_buffer,
+			target,
+			(
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::AccessFlags::TRANSFER_WRITE,
+			),
+			(
+				vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+				vk::PipelineStageFlags::FRAGMENT_SHADER,
+				vk::AccessFlags::SHADER_READ,
+			),
+		);
+
+		self.device.end_command_buffer(command_buffer)?;
+
+		let command_buffers = &[command_buffer];
+		let submit_info =
+			vk::SubmitInfo::builder().command_buffers(command_buffers);
+
+		self.device.queue_submit(
+			self.graphics_queue,
+			&[submit_info],
+			self.upload_fence,
+		)?;
+		self.device
+			.wait_for_fences(&[self.upload_fence], true, u64::MAX)?;
+		self.device.reset_fences(&[self.upload_fence])?;
+
+		Ok(id)
+	}
+
+	/// # Safety
+	pub unsafe fn destroy_external_image(&mut self, id: ExternalImageId) {
+		// Copies complete before they return, so nothing uses the image.
+		if let Some(image) = self.external_images.remove(&id) {
+			self.destroy_external_image_resources(&image);
+		}
+	}
+
+	pub(super) unsafe fn destroy_external_image_resources(
+		&self,
+		image: &ExternalImage,
+	) {
+		self.device.destroy_image(image.image, None);
+		self.device.free_memory(image.memory, None);
+	}
+}
@@ -0,0 +1,217 @@
+use std::{
+	path::{Path, PathBuf},
+	time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Result;
+use naga::ShaderStage;
+use vulkanalia::vk::{self, DeviceV1_0 as _};
+
+use super::{
+	blur::Blur, create_pipeline, create_shader_module, shader_compiler,
+	Renderer,
+};
+
+/// How often the sources are checked for changes.
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches the GLSL sources of every pipeline in the source tree, so debug
+/// builds can pick up shader changes without restarting.
+pub struct ShaderWatcher {
+	pub src: PathBuf,
+	/// Modification times of the sources of
+	/// [`shader_compiler::PIPELINES`] when they were last compiled.
+	pub modified: Option<Vec<SystemTime>>,
+	pub last_check: Instant,
+}
+
+impl Default for ShaderWatcher {
+	fn default() -> Self {
+		let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+
+		Self {
+			modified: modification_times(&src),
+			src,
+			last_check: Instant::now(),
+		}
+	}
+}
+
+impl ShaderWatcher {
+	/// Whether the sources changed since they were last compiled. Missing
+	/// sources, e.g. when running away from the source tree, never do.
+	fn poll(&mut self) -> bool {
+		if self.last_check.elapsed() < CHECK_INTERVAL {
+			return false;
+		}
+		self.last_check = Instant::now();
+
+		let modified = modification_times(&self.src);
+		if modified.is_none() || modified == self.modified {
+			return false;
+		}
+
+		self.modified = modified;
+		true
+	}
+
+	/// Reads and compiles the shader `name` of `stage`.
+	fn compile(
+		&self,
+		name: &str,
+		stage: ShaderStage,
+	) -> Result<shader_compiler::CompiledShader> {
+		let path = source_path(&self.src, name, stage);
+		let source = std::fs::read_to_string(&path)?;
+		shader_compiler::compile(&source, stage)
+			.map_err(|err| anyhow::anyhow!("{}:\n{}", path.display(), err))
+	}
+}
+
+fn source_path(src: &Path, name: &str, stage: ShaderStage) -> PathBuf {
+	let stage = match stage {
+		ShaderStage::Vertex => "vertex",
+		ShaderStage::Fragment => "fragment",
+		ShaderStage::Compute => "compute",
+	};
+
+	src.join(format!("{}.{}.glsl", name, stage))
+}
+
+fn modification_times(src: &Path) -> Option<Vec<SystemTime>> {
+	let modified =
+		|path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+	shader_compiler::PIPELINES
+		.iter()
+		.flat_map(|(fragment, vertex)| {
+			[
+				source_path(src, vertex, ShaderStage::Vertex),
+				source_path(src, fragment, ShaderStage::Fragment),
+			]
+		})
+		.map(|path| modified(&path))
+		.collect()
+}
+
+impl Renderer {
+	/// Rebuilds the pipelines if their shader sources changed. Failures are
+	/// logged and leave the last working pipelines in place.
+	///
+	/// # Safety
+	pub(super) unsafe fn reload_shaders(&mut self) {
+		if !self.shader_watcher.poll() {
+			return;
+		}
+
+		tracing::info!("Reloading shaders");
+		self.rebuild_pipelines().unwrap_or_else(|err| {
+			tracing::error!("Failed to reload shaders: {:#}", err);
+		});
+	}
+
+	/// Rebuilds the quad pipeline and those of the blur, which all draw with
+	/// the quad vertex shader.
+	unsafe fn rebuild_pipelines(&mut self) -> Result<()> {
+		let watcher = &self.shader_watcher;
+		let vertex = watcher.compile("quad", ShaderStage::Vertex)?;
+		let fragments = ["quad", "blur_down", "blur_up", "backdrop"]
+			.into_iter()
+			.map(|name| {
+				let fragment = watcher.compile(name, ShaderStage::Fragment)?;
+				shader_compiler::check_interface(
+					&vertex.module,
+					&fragment.module,
+				)
+				.map_err(|err| anyhow::anyhow!("{}: {}", name, err))?;
+				Ok(fragment)
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		let mut modules = Vec::new();
+		for shader in std::iter::once(&vertex).chain(&fragments) {
+			match create_shader_module(&self.device, &shader.to_bytes()) {
+				Ok(module) => modules.push(module),
+				Err(err) => {
+					self.destroy_shader_modules(&modules);
+					return Err(err);
+				}
+			}
+		}
+		let [vertex_module, quad, down, up, backdrop] = modules[..] else {
+			unreachable!();
+		};
+
+		let pipeline = create_pipeline(
+			&self.device,
+			self.render_pass,
+			self.pipeline_layout,
+			vertex_module,
+			quad,
+			true,
+		);
+		let pipeline = match pipeline {
+			Ok(pipeline) => pipeline,
+			Err(err) => {
+				self.destroy_shader_modules(&modules);
+				return Err(err);
+			}
+		};
+		let blur_pipelines = Blur::create_pipelines(
+			&self.device,
+			self.pipeline_layout,
+			self.blur.level_render_pass,
+			self.blur.clear_to_blit_render_pass,
+			vertex_module,
+			[down, up, backdrop],
+		);
+		let [down_pipeline, up_pipeline, backdrop_pipeline] =
+			match blur_pipelines {
+				Ok(pipelines) => pipelines,
+				Err(err) => {
+					self.device.destroy_pipeline(pipeline, None);
+					self.destroy_shader_modules(&modules);
+					return Err(err);
+				}
+			};
+
+		// Frames in flight may still use the old pipelines.
+		self.device.device_wait_idle()?;
+		for pipeline in [
+			self.pipeline,
+			self.blur.down_pipeline,
+			self.blur.up_pipeline,
+			self.blur.backdrop_pipeline,
+		] {
+			self.device.destroy_pipeline(pipeline, None);
+		}
+		self.destroy_shader_modules(&[
+			self.vertex_shader_module,
+			self.fragment_shader_module,
+			self.blur.down_shader_module,
+			self.blur.up_shader_module,
+			self.blur.backdrop_shader_module,
+		]);
+
+		self.pipeline = pipeline;
+		self.vertex_shader_module = vertex_module;
+		self.fragment_shader_module = quad;
+		let blur = &mut self.blur;
+		blur.down_pipeline = down_pipeline;
+		blur.up_pipeline = up_pipeline;
+		blur.backdrop_pipeline = backdrop_pipeline;
+		blur.down_shader_module = down;
+		blur.up_shader_module = up;
+		blur.backdrop_shader_module = backdrop;
+		self.set_object_name(self.pipeline, "quad pipeline");
+		self.set_object_name(self.blur.backdrop_pipeline, "backdrop pipeline");
+
+		Ok(())
+	}
+
+	unsafe fn destroy_shader_modules(&self, modules: &[vk::ShaderModule]) {
+		for module in modules {
+			self.device.destroy_shader_module(*module, None);
+		}
+	}
+}
@@ -0,0 +1,121 @@
+use anyhow::Result;
+use vulkanalia::vk::{self, DeviceV1_0 as _, HasBuilder as _};
+
+use super::{
+	get_memory_type_index, Renderer, SceneElement, Target, MAX_FRAMES_IN_FLIGHT,
+};
+
+impl Renderer {
+	/// Creates the images frames of a headless `target` are rendered into,
+	/// one per frame in flight so a frame can be read back while the next is
+	/// drawn.
+	///
+	/// # Safety
+	pub(super) unsafe fn create_offscreen_images(
+		&self,
+		target: &mut Target,
+		extent: vk::Extent2D,
+	) -> Result<()> {
+		target.swapchain_extent = extent;
+		target.window_extent = extent;
+
+		for _ in 0..MAX_FRAMES_IN_FLIGHT {
+			let info = vk::ImageCreateInfo::builder()
+				.image_type(vk::ImageType::_2D)
+				.extent(vk::Extent3D {
+					width: extent.width,
+					height: extent.height,
+					depth: 1,
+				})
+				.mip_levels(1)
+				.array_layers(1)
+				.format(self.swapchain_format)
+				.tiling(vk::ImageTiling::OPTIMAL)
+				.initial_layout(vk::ImageLayout::UNDEFINED)
+				.usage(
+					vk::ImageUsageFlags::COLOR_ATTACHMENT
+						| vk::ImageUsageFlags::TRANSFER_SRC,
+				)
+				.sharing_mode(vk::SharingMode::EXCLUSIVE)
+				.samples(vk::SampleCountFlags::_1);
+
+			let image = self.device.create_image(&info, None)?;
+			self.set_object_name(
+				image,
+				&format!("offscreen image {}", target.swapchain_images.len()),
+			);
+			target.swapchain_images.push(image);
+
+			let requirements = self.device.get_image_memory_requirements(image);
+			let info = vk::MemoryAllocateInfo::builder()
+				.allocation_size(requirements.size)
+				.memory_type_index(get_memory_type_index(
+					&self.instance,
+					self.physical_device,
+					vk::MemoryPropertyFlags::DEVICE_LOCAL,
+					requirements,
+				)?);
+
+			let memory = self.device.allocate_memory(&info, None)?;
+			target.offscreen_memory.push(memory);
+			self.device.bind_image_memory(image, memory, 0)?;
+		}
+
+		self.create_image_resources(target)?;
+		self.create_blur_levels(target)
+	}
+
+	/// Destroys the images created by
+	/// [`Renderer::create_offscreen_images`], if any.
+	pub(super) unsafe fn destroy_offscreen_images(&self, target: &mut Target) {
+		if target.offscreen_memory.is_empty() {
+			return;
+		}
+
+		self.destroy_swapchain_resources(target);
+		target
+			.swapchain_images
+			.drain(..)
+			.for_each(|i| self.device.destroy_image(i, None));
+		target
+			.offscreen_memory
+			.drain(..)
+			.for_each(|m| self.device.free_memory(m, None));
+		target.last_image = None;
+	}
+
+	/// Draws `scene` into the offscreen image of the current frame of
+	/// `target`.
+	pub(super) unsafe fn render_offscreen(
+		&self,
+		target: &mut Target,
+		scene: &[SceneElement],
+	) -> Result<()> {
+		let in_flight_fence = target.in_flight_fences[target.frame];
+
+		self.device
+			.wait_for_fences(&[in_flight_fence], true, u64::MAX)?;
+
+		// Each frame in flight has its own image, so there is nothing to
+		// acquire.
+		let image_index = target.frame;
+		self.record_command_buffer(target, image_index, scene)?;
+
+		let command_buffers = &[target.command_buffers[image_index]];
+		let submit_info =
+			vk::SubmitInfo::builder().command_buffers(command_buffers);
+
+		self.device.reset_fences(&[in_flight_fence])?;
+
+		self.device.queue_submit(
+			self.graphics_queue,
+			&[submit_info],
+			in_flight_fence,
+		)?;
+
+		target.last_image = Some(image_index);
+		target.frame = (target.frame + 1) % MAX_FRAMES_IN_FLIGHT;
+
+		Ok(())
+	}
+}
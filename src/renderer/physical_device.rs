@@ -0,0 +1,304 @@
+use std::fmt;
+
+use anyhow::Result;
+use vulkanalia::{
+	vk::{
+		self, ExtDebugUtilsExtension as _, Handle as _, HasBuilder as _,
+		InstanceV1_0 as _, InstanceV1_1 as _, KhrSurfaceExtension as _,
+	},
+	Instance,
+};
+
+use super::{create_instance, QueueFamilyIndices, SwapchainSupport};
+
+/// Picks a device by (part of) its name or by its UUID, overriding the
+/// scores.
+const DEVICE_OVERRIDE: &str = "NEORA_VK_DEVICE";
+
+/// A physical device and whether it can be used.
+#[derive(Clone, Debug)]
+pub struct DeviceCandidate {
+	pub physical_device: vk::PhysicalDevice,
+	pub name: String,
+	pub uuid: [u8; vk::UUID_SIZE],
+	pub device_type: vk::PhysicalDeviceType,
+	/// `None` if the device lacks a required queue family.
+	pub queue_family_indices: Option<QueueFamilyIndices>,
+	/// Why the device cannot be used, if it cannot.
+	pub unsuitable: Option<String>,
+}
+
+impl DeviceCandidate {
+	/// Preference among suitable devices, the higher the better.
+	pub fn score(&self) -> u32 {
+		match self.device_type {
+			vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+			vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+			vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+			vk::PhysicalDeviceType::CPU => 1,
+			_ => 0,
+		}
+	}
+
+	/// The UUID in its usual hyphenated form.
+	pub fn uuid_string(&self) -> String {
+		let hex = self
+			.uuid
+			.iter()
+			.map(|b| format!("{:02x}", b))
+			.collect::<String>();
+
+		format!(
+			"{}-{}-{}-{}-{}",
+			&hex[0..8],
+			&hex[8..12],
+			&hex[12..16],
+			&hex[16..20],
+			&hex[20..32]
+		)
+	}
+
+	/// Whether `query` is this device's UUID (with or without hyphens) or
+	/// part of its name, ignoring case.
+	fn matches(&self, query: &str) -> bool {
+		let query = query.to_lowercase();
+
+		query.replace('-', "") == self.uuid_string().replace('-', "")
+			|| self.name.to_lowercase().contains(&query)
+	}
+}
+
+impl fmt::Display for DeviceCandidate {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let device_type = match self.device_type {
+			vk::PhysicalDeviceType::DISCRETE_GPU => "discrete GPU",
+			vk::PhysicalDeviceType::INTEGRATED_GPU => "integrated GPU",
+			vk::PhysicalDeviceType::VIRTUAL_GPU => "virtual GPU",
+			vk::PhysicalDeviceType::CPU => "CPU",
+			_ => "other",
+		};
+
+		write!(f, "{} ({}, {})", self.name, device_type, self.uuid_string())
+	}
+}
+
+/// Describes every physical device, checking for the queue families, the
+/// device `extensions` and, unless it is null, support for `surface`.
+///
+/// # Safety
+pub unsafe fn enumerate_candidates(
+	instance: &Instance,
+	surface: vk::SurfaceKHR,
+	extensions: &[vk::ExtensionName],
+) -> Result<Vec<DeviceCandidate>> {
+	instance
+		.enumerate_physical_devices()?
+		.into_iter()
+		.map(|physical_device| {
+			let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+			let mut properties = vk::PhysicalDeviceProperties2::builder()
+				.push_next(&mut id_properties);
+			instance.get_physical_device_properties2(
+				physical_device,
+				&mut properties,
+			);
+			let properties = properties.properties;
+
+			let queue_family_indices =
+				QueueFamilyIndices::get(instance, physical_device, surface)?;
+			let unsuitable = check_suitability(
+				instance,
+				physical_device,
+				surface,
+				extensions,
+				queue_family_indices,
+			)?;
+
+			Ok(DeviceCandidate {
+				physical_device,
+				name: properties.device_name.to_string(),
+				uuid: id_properties.device_uuid.0,
+				device_type: properties.device_type,
+				queue_family_indices,
+				unsuitable,
+			})
+		})
+		.collect()
+}
+
+unsafe fn check_suitability(
+	instance: &Instance,
+	physical_device: vk::PhysicalDevice,
+	surface: vk::SurfaceKHR,
+	extensions: &[vk::ExtensionName],
+	queue_family_indices: Option<QueueFamilyIndices>,
+) -> Result<Option<String>> {
+	if queue_family_indices.is_none() {
+		return Ok(Some("missing required queue families".into()));
+	}
+
+	let available = instance
+		.enumerate_device_extension_properties(physical_device, None)?;
+	let missing = extensions
+		.iter()
+		.filter(|e| !available.iter().any(|a| a.extension_name == **e))
+		.map(|e| e.to_string())
+		.collect::<Vec<_>>();
+	if !missing.is_empty() {
+		return Ok(Some(format!("missing {}", missing.join(", "))));
+	}
+
+	if !surface.is_null() {
+		let support =
+			SwapchainSupport::get(instance, surface, physical_device)?;
+		if support.formats.is_empty() || support.present_modes.is_empty() {
+			return Ok(Some("insufficient swapchain support".into()));
+		}
+	}
+
+	Ok(None)
+}
+
+/// Picks the device named by `NEORA_VK_DEVICE`, or else the suitable one
+/// with the highest score.
+pub fn select(candidates: &[DeviceCandidate]) -> Result<&DeviceCandidate> {
+	let query = std::env::var(DEVICE_OVERRIDE).ok();
+	if let Some(query) = query.filter(|query| !query.is_empty()) {
+		let candidate = candidates
+			.iter()
+			.find(|c| c.matches(&query))
+			.ok_or_else(|| {
+				anyhow::anyhow!(
+					"No physical device matches {}={}.",
+					DEVICE_OVERRIDE,
+					query
+				)
+			})?;
+
+		if let Some(reason) = &candidate.unsuitable {
+			anyhow::bail!(
+				"Physical device {} cannot be used: {}.",
+				candidate,
+				reason
+			);
+		}
+
+		return Ok(candidate);
+	}
+
+	// `max_by_key` returns the last maximum, keep the first instead.
+	candidates
+		.iter()
+		.filter(|c| c.unsuitable.is_none())
+		.rev()
+		.max_by_key(|c| c.score())
+		.ok_or_else(|| anyhow::anyhow!("No suitable physical device found."))
+}
+
+/// Selects the device to render with, see [`select`].
+///
+/// # Safety
+pub unsafe fn pick_physical_device(
+	instance: &Instance,
+	surface: vk::SurfaceKHR,
+	extensions: &[vk::ExtensionName],
+) -> Result<(vk::PhysicalDevice, QueueFamilyIndices)> {
+	let candidates = enumerate_candidates(instance, surface, extensions)?;
+	for candidate in &candidates {
+		match &candidate.unsuitable {
+			Some(reason) => tracing::debug!(
+				"Physical device {} is unsuitable: {}",
+				candidate,
+				reason
+			),
+			None => tracing::debug!(
+				"Physical device {} has score {}",
+				candidate,
+				candidate.score()
+			),
+		}
+	}
+
+	let candidate = select(&candidates)?;
+	tracing::info!("Using physical device {}", candidate);
+
+	Ok((
+		candidate.physical_device,
+		candidate.queue_family_indices.unwrap(),
+	))
+}
+
+/// Describes the physical devices for `--list-devices`, checking them as
+/// for headless rendering since there is no surface yet. Returns them with
+/// the index of the one that would be selected.
+///
+/// # Safety
+pub unsafe fn list_devices() -> Result<(Vec<DeviceCandidate>, Option<usize>)> {
+	let (instance, debug_messenger) = create_instance(&[])?;
+	let candidates =
+		enumerate_candidates(&instance, vk::SurfaceKHR::null(), &[]);
+	if !debug_messenger.is_null() {
+		instance.destroy_debug_utils_messenger_ext(debug_messenger, None);
+	}
+	instance.destroy_instance(None);
+
+	let candidates = candidates?;
+	let selected = select(&candidates).ok().map(|selected| {
+		candidates
+			.iter()
+			.position(|c| c.physical_device == selected.physical_device)
+			.unwrap()
+	});
+
+	Ok((candidates, selected))
+}
+
+impl QueueFamilyIndices {
+	/// Finds a graphics queue family, and one presenting to `surface`, or
+	/// returns `None`. When `surface` is null, the graphics family presents.
+	///
+	/// # Safety
+	pub unsafe fn get(
+		instance: &Instance,
+		physical_device: vk::PhysicalDevice,
+		surface: vk::SurfaceKHR,
+	) -> Result<Option<Self>> {
+		let properties = instance
+			.get_physical_device_queue_family_properties(physical_device);
+
+		let Some(graphics) = properties
+			.iter()
+			.position(|p| p.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+			.map(|i| i as u32)
+		else {
+			return Ok(None);
+		};
+
+		if surface.is_null() {
+			return Ok(Some(Self {
+				graphics,
+				present: Some(graphics),
+			}));
+		}
+
+		// Prefer presenting from the graphics family, so images need not be
+		// shared between queues.
+		let mut present = None;
+		for index in std::iter::once(graphics).chain(0..properties.len() as u32)
+		{
+			if instance.get_physical_device_surface_support_khr(
+				physical_device,
+				index,
+				surface,
+			)? {
+				present = Some(index);
+				break;
+			}
+		}
+
+		Ok(present.map(|present| Self {
+			graphics,
+			present: Some(present),
+		}))
+	}
+}
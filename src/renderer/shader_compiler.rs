@@ -0,0 +1,154 @@
+//! GLSL to SPIR-V compilation, shared by the build script and shader hot
+//! reloading of debug builds with the `hot-reload` feature.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use naga::{
+	back::spv,
+	front::glsl,
+	valid::{Capabilities, ValidationFlags, Validator},
+	AddressSpace, Binding, Module, ShaderStage, TypeInner,
+};
+
+/// Names of the fragment shaders and of the vertex shader their pipeline
+/// draws them with, which must agree on their interface.
+pub const PIPELINES: &[(&str, &str)] = &[
+	("quad", "quad"),
+	("blur_down", "quad"),
+	("blur_up", "quad"),
+	("backdrop", "quad"),
+];
+
+/// A validated shader and its SPIR-V.
+pub struct CompiledShader {
+	pub module: Module,
+	pub spirv: Vec<u32>,
+}
+
+impl CompiledShader {
+	/// The SPIR-V words as little-endian bytes.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		self.spirv
+			.iter()
+			.flat_map(|word| word.to_le_bytes())
+			.collect()
+	}
+}
+
+/// Parses and validates `source`, then translates it into SPIR-V. Errors
+/// carry the diagnostics with source excerpts.
+pub fn compile(source: &str, stage: ShaderStage) -> Result<CompiledShader> {
+	let module = glsl::Frontend::default()
+		.parse(&glsl::Options::from(stage), source)
+		.map_err(|err| anyhow::anyhow!(err.emit_to_string(source)))?;
+
+	let info =
+		Validator::new(ValidationFlags::all(), Capabilities::PUSH_CONSTANT)
+			.validate(&module)
+			.map_err(|err| anyhow::anyhow!(err.emit_to_string(source)))?;
+
+	let options = spv::Options {
+		lang_version: (1, 0),
+		..Default::default()
+	};
+	let spirv = spv::write_vec(&module, &info, &options, None)?;
+
+	Ok(CompiledShader { module, spirv })
+}
+
+/// Checks that each input of the fragment shader is written by the vertex
+/// shader with the same type, and that their push constant blocks agree
+/// where they overlap.
+pub fn check_interface(vertex: &Module, fragment: &Module) -> Result<()> {
+	let outputs = vertex.entry_points[0]
+		.function
+		.result
+		.iter()
+		.flat_map(|result| {
+			locations(vertex, result.ty, result.binding.as_ref())
+		})
+		.collect::<BTreeMap<_, _>>();
+
+	let inputs = fragment.entry_points[0]
+		.function
+		.arguments
+		.iter()
+		.flat_map(|arg| locations(fragment, arg.ty, arg.binding.as_ref()));
+
+	for (location, ty) in inputs {
+		match outputs.get(&location) {
+			Some(output) if *output == ty => {}
+			Some(output) => anyhow::bail!(
+				"Fragment input at location {} is {:?}, but the vertex \
+				 shader writes {:?}",
+				location,
+				ty,
+				output
+			),
+			None => anyhow::bail!(
+				"Fragment input at location {} is not written by the vertex \
+				 shader",
+				location
+			),
+		}
+	}
+
+	let vertex_constants = push_constants(vertex);
+	for (offset, ty) in push_constants(fragment) {
+		if let Some(other) = vertex_constants.get(&offset) {
+			if *other != ty {
+				anyhow::bail!(
+					"Push constant at offset {} is {:?} in the fragment \
+					 shader, but {:?} in the vertex shader",
+					offset,
+					ty,
+					other
+				);
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// Locations and types of a stage input or output, which is either bound
+/// directly or a struct of bound members.
+fn locations(
+	module: &Module,
+	ty: naga::Handle<naga::Type>,
+	binding: Option<&Binding>,
+) -> Vec<(u32, TypeInner)> {
+	let inner = &module.types[ty].inner;
+
+	match (binding, inner) {
+		(Some(Binding::Location { location, .. }), _) => {
+			vec![(*location, inner.clone())]
+		}
+		(None, TypeInner::Struct { members, .. }) => members
+			.iter()
+			.flat_map(|member| {
+				locations(module, member.ty, member.binding.as_ref())
+			})
+			.collect(),
+		_ => Vec::new(),
+	}
+}
+
+/// Offsets and types of the members of the push constant block.
+fn push_constants(module: &Module) -> BTreeMap<u32, TypeInner> {
+	module
+		.global_variables
+		.iter()
+		.filter(|(_, var)| var.space == AddressSpace::PushConstant)
+		.flat_map(|(_, var)| match &module.types[var.ty].inner {
+			TypeInner::Struct { members, .. } => members
+				.iter()
+				.map(|member| {
+					(member.offset, module.types[member.ty].inner.clone())
+				})
+				.collect(),
+			inner => vec![(0, inner.clone())],
+		})
+		.collect()
+}
@@ -0,0 +1,229 @@
+use anyhow::Result;
+use vulkanalia::vk::{
+	self, DeviceV1_0 as _, Handle as _, HasBuilder as _, KhrSwapchainExtension,
+};
+
+use super::{
+	get_swapchain_extent, get_swapchain_present_mode,
+	get_swapchain_surface_format, Renderer, SwapchainSupport, Target,
+};
+
+impl Renderer {
+	/// Rebuilds the swapchain and everything sized after it for a window of
+	/// `window_extent`, e.g. after a resize or once Vulkan reported the
+	/// current one as out of date.
+	///
+	/// While the window has a zero size, the swapchain is only destroyed and
+	/// frames are skipped until a later call gives it a size again.
+	///
+	/// # Safety
+	pub unsafe fn recreate_swapchain(
+		&mut self,
+		target: usize,
+		window_extent: vk::Extent2D,
+	) -> Result<()> {
+		self.with_target(target, |renderer, target| {
+			renderer.recreate_target_swapchain(target, window_extent)
+		})
+	}
+
+	/// [`Renderer::recreate_swapchain`] for a target taken out of
+	/// [`Renderer::targets`].
+	///
+	/// # Safety
+	pub(super) unsafe fn recreate_target_swapchain(
+		&self,
+		target: &mut Target,
+		window_extent: vk::Extent2D,
+	) -> Result<()> {
+		target.window_extent = window_extent;
+		target.swapchain_dirty = false;
+
+		self.device.device_wait_idle()?;
+		self.destroy_swapchain_resources(target);
+
+		let support = SwapchainSupport::get(
+			&self.instance,
+			target.surface,
+			self.physical_device,
+		)?;
+		let extent = get_swapchain_extent(window_extent, support.capabilities);
+
+		if extent.width == 0 || extent.height == 0 {
+			self.device.destroy_swapchain_khr(target.swapchain, None);
+			target.swapchain = vk::SwapchainKHR::null();
+			target.swapchain_extent = extent;
+			return Ok(());
+		}
+
+		let surface_format = get_swapchain_surface_format(&support.formats);
+		let present_mode = get_swapchain_present_mode(&support.present_modes);
+
+		let mut image_count = support.capabilities.min_image_count + 1;
+		if support.capabilities.max_image_count != 0
+			&& image_count > support.capabilities.max_image_count
+		{
+			image_count = support.capabilities.max_image_count;
+		}
+
+		let graphics = self.queue_family_indices.graphics;
+		let present = self.queue_family_indices.present.unwrap();
+
+		let mut indices = vec![];
+		let image_sharing_mode = if graphics != present {
+			indices.push(graphics);
+			indices.push(present);
+			vk::SharingMode::CONCURRENT
+		} else {
+			vk::SharingMode::EXCLUSIVE
+		};
+
+		// Blurring glass copies from the target images.
+		let blit = support
+			.capabilities
+			.supported_usage_flags
+			.contains(vk::ImageUsageFlags::TRANSFER_SRC);
+		let image_usage = if blit {
+			vk::ImageUsageFlags::COLOR_ATTACHMENT
+				| vk::ImageUsageFlags::TRANSFER_SRC
+		} else {
+			vk::ImageUsageFlags::COLOR_ATTACHMENT
+		};
+
+		// Passing the old swapchain lets the driver hand over resources and
+		// keep presenting its images until the new ones are ready.
+		let old_swapchain = target.swapchain;
+		let info = vk::SwapchainCreateInfoKHR::builder()
+			.surface(target.surface)
+			.min_image_count(image_count)
+			.image_format(surface_format.format)
+			.image_color_space(surface_format.color_space)
+			.image_extent(extent)
+			.image_array_layers(1)
+			.image_usage(image_usage)
+			.image_sharing_mode(image_sharing_mode)
+			.queue_family_indices(&indices)
+			.pre_transform(support.capabilities.current_transform)
+			.composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+			.present_mode(present_mode)
+			.clipped(true)
+			.old_swapchain(old_swapchain);
+
+		target.swapchain = self.device.create_swapchain_khr(&info, None)?;
+		self.device.destroy_swapchain_khr(old_swapchain, None);
+
+		target.swapchain_extent = extent;
+		target.swapchain_images =
+			self.device.get_swapchain_images_khr(target.swapchain)?;
+
+		self.set_object_name(target.swapchain, "swapchain");
+		for (index, image) in target.swapchain_images.iter().enumerate() {
+			self.set_object_name(*image, &format!("swapchain image {}", index));
+		}
+
+		self.create_image_resources(target)?;
+
+		if blit {
+			self.create_blur_levels(target)?;
+		} else {
+			tracing::warn!(
+				"Swapchain images cannot be copied, glass will not be blurred"
+			);
+		}
+
+		Ok(())
+	}
+
+	/// Creates the views, framebuffers and command buffers of the images in
+	/// `target.swapchain_images`.
+	pub(super) unsafe fn create_image_resources(
+		&self,
+		target: &mut Target,
+	) -> Result<()> {
+		target.swapchain_image_views = target
+			.swapchain_images
+			.iter()
+			.map(|i| {
+				let components = vk::ComponentMapping::builder()
+					.r(vk::ComponentSwizzle::IDENTITY)
+					.g(vk::ComponentSwizzle::IDENTITY)
+					.b(vk::ComponentSwizzle::IDENTITY)
+					.a(vk::ComponentSwizzle::IDENTITY);
+
+				let subresource_range = vk::ImageSubresourceRange::builder()
+					.aspect_mask(vk::ImageAspectFlags::COLOR)
+					.base_mip_level(0)
+					.level_count(1)
+					.base_array_layer(0)
+					.layer_count(1);
+
+				let info = vk::ImageViewCreateInfo::builder()
+					.image(*i)
+					.view_type(vk::ImageViewType::_2D)
+					.format(self.swapchain_format)
+					.components(components)
+					.subresource_range(subresource_range);
+
+				self.device.create_image_view(&info, None)
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+
+		target.framebuffers = target
+			.swapchain_image_views
+			.iter()
+			.map(|i| {
+				let attachments = &[*i];
+				let create_info = vk::FramebufferCreateInfo::builder()
+					.render_pass(self.render_pass)
+					.attachments(attachments)
+					.width(target.swapchain_extent.width)
+					.height(target.swapchain_extent.height)
+					.layers(1);
+
+				self.device.create_framebuffer(&create_info, None)
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+
+		let allocate_info = vk::CommandBufferAllocateInfo::builder()
+			.command_pool(self.command_pool)
+			.level(vk::CommandBufferLevel::PRIMARY)
+			.command_buffer_count(target.framebuffers.len() as u32);
+
+		target.command_buffers =
+			self.device.allocate_command_buffers(&allocate_info)?;
+
+		target.images_in_flight = target
+			.swapchain_images
+			.iter()
+			.map(|_| vk::Fence::null())
+			.collect();
+
+		Ok(())
+	}
+
+	/// Destroys what depends on the swapchain images, but not the swapchain
+	/// itself, which the next one is created from.
+	pub(super) unsafe fn destroy_swapchain_resources(
+		&self,
+		target: &mut Target,
+	) {
+		self.destroy_blur_levels(target);
+		if !target.command_buffers.is_empty() {
+			self.device.free_command_buffers(
+				self.command_pool,
+				&target.command_buffers,
+			);
+		}
+		target.command_buffers.clear();
+		target
+			.framebuffers
+			.drain(..)
+			.for_each(|f| self.device.destroy_framebuffer(f, None));
+		target
+			.swapchain_image_views
+			.drain(..)
+			.for_each(|v| self.device.destroy_image_view(v, None));
+		target.swapchain_images.clear();
+		target.images_in_flight.clear();
+	}
+}
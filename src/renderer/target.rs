@@ -0,0 +1,127 @@
+use anyhow::{Context as _, Result};
+use vulkanalia::vk::{
+	self, DeviceV1_0 as _, Handle as _, HasBuilder as _, KhrSurfaceExtension,
+	KhrSwapchainExtension,
+};
+
+use super::{blur::BlurLevel, Renderer, MAX_FRAMES_IN_FLIGHT};
+
+/// Where the frames of one output end up: the swapchain of a host window, or
+/// offscreen images when headless. Targets share the device, render passes
+/// and pipelines of the renderer, so they all render into images of
+/// [`Renderer::swapchain_format`].
+#[derive(Default)]
+pub struct Target {
+	/// Null when headless.
+	pub surface: vk::SurfaceKHR,
+	/// Null when headless, or while the window has a zero size.
+	pub swapchain: vk::SwapchainKHR,
+	/// Images frames are rendered into, either the swapchain's or offscreen
+	/// ones when headless.
+	pub swapchain_images: Vec<vk::Image>,
+	pub swapchain_extent: vk::Extent2D,
+	pub swapchain_image_views: Vec<vk::ImageView>,
+	/// Size of the window, used when the surface lets the swapchain pick its
+	/// extent.
+	pub window_extent: vk::Extent2D,
+	/// Set when presenting reported the swapchain as out of date or
+	/// suboptimal, so it is recreated before the next frame.
+	pub swapchain_dirty: bool,
+	pub framebuffers: Vec<vk::Framebuffer>,
+	pub command_buffers: Vec<vk::CommandBuffer>,
+	pub image_available_semaphores: Vec<vk::Semaphore>,
+	pub render_finished_semaphores: Vec<vk::Semaphore>,
+	pub frame: usize,
+	pub in_flight_fences: Vec<vk::Fence>,
+	pub images_in_flight: Vec<vk::Fence>,
+	/// The blur chain sized after the images. Empty when they cannot be
+	/// blurred, in which case glass elements are drawn without a backdrop.
+	pub blur_levels: Vec<BlurLevel>,
+	/// Backing memory of the images when headless, in which case they are
+	/// owned by the renderer instead of a swapchain.
+	pub offscreen_memory: Vec<vk::DeviceMemory>,
+	/// Index of the image holding the last rendered frame.
+	pub last_image: Option<usize>,
+}
+
+impl Renderer {
+	/// Creates a target presenting to `surface`, or a headless one if it is
+	/// null, with the synchronization objects of its frames in flight but no
+	/// images yet.
+	///
+	/// # Safety
+	pub(super) unsafe fn create_target(
+		&self,
+		surface: vk::SurfaceKHR,
+	) -> Result<Target> {
+		let semaphore_info = vk::SemaphoreCreateInfo::builder();
+		let fence_info = vk::FenceCreateInfo::builder()
+			.flags(vk::FenceCreateFlags::SIGNALED);
+
+		let mut target = Target {
+			surface,
+			..Default::default()
+		};
+		for _ in 0..MAX_FRAMES_IN_FLIGHT {
+			target
+				.image_available_semaphores
+				.push(self.device.create_semaphore(&semaphore_info, None)?);
+			target
+				.render_finished_semaphores
+				.push(self.device.create_semaphore(&semaphore_info, None)?);
+			target
+				.in_flight_fences
+				.push(self.device.create_fence(&fence_info, None)?);
+		}
+
+		Ok(target)
+	}
+
+	/// Destroys everything owned by `target`, including its swapchain and
+	/// surface. The device must be idle.
+	///
+	/// # Safety
+	pub(super) unsafe fn destroy_target(&self, target: &mut Target) {
+		target
+			.in_flight_fences
+			.drain(..)
+			.for_each(|f| self.device.destroy_fence(f, None));
+		target
+			.render_finished_semaphores
+			.drain(..)
+			.for_each(|s| self.device.destroy_semaphore(s, None));
+		target
+			.image_available_semaphores
+			.drain(..)
+			.for_each(|s| self.device.destroy_semaphore(s, None));
+		self.destroy_offscreen_images(target);
+		self.destroy_swapchain_resources(target);
+
+		// The swapchain and surface functions are not loaded when headless.
+		if !target.surface.is_null() {
+			self.device.destroy_swapchain_khr(target.swapchain, None);
+			self.instance.destroy_surface_khr(target.surface, None);
+		}
+		target.swapchain = vk::SwapchainKHR::null();
+		target.surface = vk::SurfaceKHR::null();
+	}
+
+	/// Runs `f` with the target `index`, which is taken out of
+	/// [`Renderer::targets`] meanwhile so `f` can borrow the rest of the
+	/// renderer.
+	pub(super) fn with_target<T>(
+		&mut self,
+		index: usize,
+		f: impl FnOnce(&mut Self, &mut Target) -> Result<T>,
+	) -> Result<T> {
+		let mut target = std::mem::take(
+			self.targets
+				.get_mut(index)
+				.with_context(|| format!("No render target {}.", index))?,
+		);
+		let result = f(self, &mut target);
+		self.targets[index] = target;
+
+		result
+	}
+}
@@ -0,0 +1,585 @@
+//! Screenshot tests rendering scenes headless and comparing them with the
+//! PNG goldens in `tests/golden`.
+//!
+//! Tests are skipped when no Vulkan device is available, or when it lacks a
+//! feature they need, unless `NEORA_REQUIRE_DEVICE=1` makes them fail
+//! instead. Running them with `NEORA_UPDATE_GOLDENS=1` rewrites the goldens
+//! from the captures instead.
+
+use std::{
+	io::Write as _,
+	os::fd::{FromRawFd as _, OwnedFd},
+	path::PathBuf,
+};
+
+use vulkanalia::vk;
+
+use super::{
+	BlurSettings, Dmabuf, DmabufPlane, Fill, FrameCapture, ImageData, Renderer,
+	SceneElement, TextureId, TextureTransform,
+};
+use crate::geometry::Rectangle;
+
+/// Largest difference allowed per channel, for rounding and filtering that
+/// differ between drivers.
+const TOLERANCE: u8 = 2;
+
+fn golden_path(name: &str) -> PathBuf {
+	PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/golden")
+		.join(format!("{}.png", name))
+}
+
+/// Reports why a test cannot run, failing it if a device is required.
+fn skip(reason: &str) {
+	if std::env::var_os("NEORA_REQUIRE_DEVICE").is_some() {
+		panic!("{}", reason);
+	}
+	eprintln!("Skipping, {}", reason);
+}
+
+/// A headless renderer of `width`x`height`, or `None` without a device.
+fn renderer(width: u32, height: u32) -> Option<Renderer> {
+	let extent = vk::Extent2D { width, height };
+
+	match unsafe { Renderer::new_headless(&[extent]) } {
+		Ok(renderer) => Some(renderer),
+		Err(err) => {
+			skip(&format!("no usable Vulkan device: {}", err));
+			None
+		}
+	}
+}
+
+/// Uploads a texture of a single premultiplied RGBA color.
+fn solid_texture(renderer: &mut Renderer, color: [u8; 4]) -> TextureId {
+	let data = color.repeat(4 * 4);
+	let data = ImageData {
+		data: &data,
+		width: 4,
+		height: 4,
+		stride: 4 * 4,
+		format: vk::Format::R8G8B8A8_UNORM,
+		opaque: color[3] == 255,
+	};
+
+	unsafe { renderer.upload_texture(None, data) }.unwrap()
+}
+
+/// Uploads a 2x2 texture of red and green on top of blue and white.
+fn quadrant_texture(renderer: &mut Renderer) -> TextureId {
+	let data = [
+		[255, 0, 0, 255],
+		[0, 255, 0, 255],
+		[0, 0, 255, 255],
+		[255, 255, 255, 255],
+	]
+	.concat();
+	let data = ImageData {
+		data: &data,
+		width: 2,
+		height: 2,
+		stride: 2 * 4,
+		format: vk::Format::R8G8B8A8_UNORM,
+		opaque: true,
+	};
+
+	unsafe { renderer.upload_texture(None, data) }.unwrap()
+}
+
+fn render(renderer: &mut Renderer, scene: &[SceneElement]) -> FrameCapture {
+	unsafe {
+		renderer.render_frame(0, scene).unwrap();
+		renderer.capture_frame(0).unwrap()
+	}
+}
+
+fn read_png(path: &PathBuf) -> FrameCapture {
+	let file = std::fs::File::open(path).unwrap_or_else(|err| {
+		panic!(
+			"Failed to open {:?}: {}, run with NEORA_UPDATE_GOLDENS=1 to \
+			 create it",
+			path, err
+		)
+	});
+	let mut decoder = png::Decoder::new(file);
+	decoder.set_transformations(png::Transformations::EXPAND);
+	let mut reader = decoder.read_info().unwrap();
+	let mut data = vec![0; reader.output_buffer_size()];
+	let info = reader.next_frame(&mut data).unwrap();
+	data.truncate(info.buffer_size());
+
+	assert_eq!(
+		info.color_type,
+		png::ColorType::Rgba,
+		"{:?} is not RGBA",
+		path
+	);
+
+	FrameCapture {
+		width: info.width,
+		height: info.height,
+		format: vk::Format::R8G8B8A8_UNORM,
+		data,
+	}
+}
+
+fn write_png(path: &PathBuf, capture: &FrameCapture) {
+	let file = std::fs::File::create(path).unwrap();
+	let mut encoder = png::Encoder::new(file, capture.width, capture.height);
+	encoder.set_color(png::ColorType::Rgba);
+	encoder.set_depth(png::BitDepth::Eight);
+	let mut writer = encoder.write_header().unwrap();
+	writer.write_image_data(&capture.data).unwrap();
+}
+
+/// Compares `capture` with the golden `name`, writing the capture next to
+/// the test binaries for inspection when they differ.
+fn assert_golden(name: &str, capture: &FrameCapture) {
+	let path = golden_path(name);
+
+	if std::env::var_os("NEORA_UPDATE_GOLDENS").is_some() {
+		std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+		write_png(&path, capture);
+		return;
+	}
+
+	let golden = read_png(&path);
+	assert_eq!(
+		(capture.width, capture.height),
+		(golden.width, golden.height),
+		"Capture of {} has the wrong size",
+		name
+	);
+
+	let mismatches = capture
+		.data
+		.chunks_exact(4)
+		.zip(golden.data.chunks_exact(4))
+		.enumerate()
+		.filter(|(_, (actual, expected))| {
+			actual
+				.iter()
+				.zip(expected.iter())
+				.any(|(a, e)| a.abs_diff(*e) > TOLERANCE)
+		})
+		.collect::<Vec<_>>();
+
+	if let Some((index, (actual, expected))) = mismatches.first() {
+		let actual_path = std::env::temp_dir().join(format!("{}.png", name));
+		write_png(&actual_path, capture);
+
+		panic!(
+			"Capture of {} differs from the golden in {} pixels, first at \
+			 ({}, {}): {:?} instead of {:?}. The capture was written to {:?}",
+			name,
+			mismatches.len(),
+			*index as u32 % capture.width,
+			*index as u32 / capture.width,
+			actual,
+			expected,
+			actual_path
+		);
+	}
+}
+
+#[test]
+fn empty_output() {
+	let Some(mut renderer) = renderer(64, 64) else {
+		return;
+	};
+
+	let capture = render(&mut renderer, &[]);
+	assert_golden("empty_output", &capture);
+}
+
+#[test]
+fn stacked_quads() {
+	let Some(mut renderer) = renderer(128, 96) else {
+		return;
+	};
+
+	let red = solid_texture(&mut renderer, [255, 0, 0, 255]);
+	let blue = solid_texture(&mut renderer, [0, 0, 255, 255]);
+
+	let scene = [
+		SceneElement {
+			fill: Fill::Texture(red),
+			geometry: Rectangle::new(8, 8, 48, 32),
+			source: SceneElement::WHOLE_TEXTURE,
+			transform: TextureTransform::Normal,
+			opacity: 1.0,
+			corner_radius: 0.0,
+			blur: false,
+		},
+		SceneElement {
+			fill: Fill::Texture(blue),
+			geometry: Rectangle::new(40, 24, 40, 40),
+			source: SceneElement::WHOLE_TEXTURE,
+			transform: TextureTransform::Normal,
+			opacity: 1.0,
+			corner_radius: 0.0,
+			blur: false,
+		},
+	];
+
+	let capture = render(&mut renderer, &scene);
+	assert_golden("stacked_quads", &capture);
+}
+
+#[test]
+fn translucency() {
+	let Some(mut renderer) = renderer(96, 64) else {
+		return;
+	};
+
+	let red = solid_texture(&mut renderer, [255, 0, 0, 255]);
+	let white = solid_texture(&mut renderer, [255, 255, 255, 255]);
+	// Premultiplied green at half alpha.
+	let green = solid_texture(&mut renderer, [0, 128, 0, 128]);
+
+	let scene = [
+		SceneElement {
+			fill: Fill::Texture(red),
+			geometry: Rectangle::new(0, 0, 48, 64),
+			source: SceneElement::WHOLE_TEXTURE,
+			transform: TextureTransform::Normal,
+			opacity: 1.0,
+			corner_radius: 0.0,
+			blur: false,
+		},
+		SceneElement {
+			fill: Fill::Texture(green),
+			geometry: Rectangle::new(16, 16, 32, 32),
+			source: SceneElement::WHOLE_TEXTURE,
+			transform: TextureTransform::Normal,
+			opacity: 1.0,
+			corner_radius: 0.0,
+			blur: false,
+		},
+		SceneElement {
+			fill: Fill::Texture(white),
+			geometry: Rectangle::new(56, 16, 32, 32),
+			source: SceneElement::WHOLE_TEXTURE,
+			transform: TextureTransform::Normal,
+			opacity: 0.5,
+			corner_radius: 0.0,
+			blur: false,
+		},
+	];
+
+	let capture = render(&mut renderer, &scene);
+	assert_golden("translucency", &capture);
+}
+
+#[test]
+fn rounded_gradient() {
+	let Some(mut renderer) = renderer(48, 40) else {
+		return;
+	};
+
+	let scene = [SceneElement {
+		fill: Fill::Gradient {
+			top: [1.0, 0.0, 0.0, 1.0],
+			bottom: [0.0, 0.0, 0.5, 0.5],
+		},
+		geometry: Rectangle::new(8, 4, 32, 32),
+		source: SceneElement::WHOLE_TEXTURE,
+		transform: TextureTransform::Normal,
+		opacity: 1.0,
+		corner_radius: 8.0,
+		blur: false,
+	}];
+
+	let capture = render(&mut renderer, &scene);
+	assert_golden("rounded_gradient", &capture);
+}
+
+#[test]
+fn cropped_texture() {
+	let Some(mut renderer) = renderer(16, 16) else {
+		return;
+	};
+
+	let texture = quadrant_texture(&mut renderer);
+
+	// Stretching the top right texel must not bleed its neighbours in.
+	let scene = [SceneElement {
+		fill: Fill::Texture(texture),
+		geometry: Rectangle::new(0, 0, 16, 16),
+		source: [0.5, 0.0, 0.5, 0.5],
+		transform: TextureTransform::Normal,
+		opacity: 1.0,
+		corner_radius: 0.0,
+		blur: false,
+	}];
+
+	let capture = render(&mut renderer, &scene);
+	for pixel in capture.data.chunks(4) {
+		assert_eq!(pixel, [0, 255, 0, 255], "only green is sampled");
+	}
+}
+
+#[test]
+fn transformed_texture() {
+	let Some(mut renderer) = renderer(16, 16) else {
+		return;
+	};
+	let texture = quadrant_texture(&mut renderer);
+
+	let red = [255, 0, 0, 255];
+	let green = [0, 255, 0, 255];
+	let blue = [0, 0, 255, 255];
+	let white = [255, 255, 255, 255];
+
+	// Corners from top left to bottom right once the transform is undone.
+	let cases = [
+		(TextureTransform::Rotated90, [blue, red, white, green]),
+		(TextureTransform::Rotated180, [white, blue, green, red]),
+		(TextureTransform::Flipped, [green, red, white, blue]),
+		(TextureTransform::Flipped270, [white, green, blue, red]),
+	];
+	for (transform, corners) in cases {
+		let scene = [SceneElement {
+			fill: Fill::Texture(texture),
+			geometry: Rectangle::new(0, 0, 16, 16),
+			source: SceneElement::WHOLE_TEXTURE,
+			transform,
+			opacity: 1.0,
+			corner_radius: 0.0,
+			blur: false,
+		}];
+
+		let capture = render(&mut renderer, &scene);
+		let actual = [(1, 1), (14, 1), (1, 14), (14, 14)]
+			.map(|(x, y)| capture.pixel(x, y));
+		assert_eq!(actual, corners, "corners of {:?}", transform);
+	}
+}
+
+#[test]
+fn separate_targets() {
+	let extents = [
+		vk::Extent2D {
+			width: 16,
+			height: 8,
+		},
+		vk::Extent2D {
+			width: 8,
+			height: 32,
+		},
+	];
+	let mut renderer = match unsafe { Renderer::new_headless(&extents) } {
+		Ok(renderer) => renderer,
+		Err(err) => {
+			skip(&format!("no usable Vulkan device: {}", err));
+			return;
+		}
+	};
+
+	let red = solid_texture(&mut renderer, [255, 0, 0, 255]);
+	let scene = [SceneElement {
+		fill: Fill::Texture(red),
+		geometry: Rectangle::new(0, 0, 64, 64),
+		source: SceneElement::WHOLE_TEXTURE,
+		transform: TextureTransform::Normal,
+		opacity: 1.0,
+		corner_radius: 0.0,
+		blur: false,
+	}];
+
+	// Only the first target gets the red quad, the second stays cleared.
+	let (first, second) = unsafe {
+		renderer.render_frame(0, &scene).unwrap();
+		renderer.render_frame(1, &[]).unwrap();
+		(
+			renderer.capture_frame(0).unwrap(),
+			renderer.capture_frame(1).unwrap(),
+		)
+	};
+
+	assert_eq!((first.width, first.height), (16, 8));
+	assert_eq!((second.width, second.height), (8, 32));
+	assert!(first.data.chunks(4).all(|pixel| pixel == [255, 0, 0, 255]));
+	assert!(second.data.chunks(4).all(|pixel| pixel == [0, 0, 0, 255]));
+}
+
+#[test]
+fn blur_softens_backdrop() {
+	let Some(mut renderer) = renderer(64, 32) else {
+		return;
+	};
+	if !renderer.blur.enabled(&renderer.targets[0]) {
+		skip("the target images cannot be blurred");
+		return;
+	}
+	renderer.blur.settings = BlurSettings {
+		radius: 8.0,
+		saturation: 1.0,
+	};
+
+	let white = solid_texture(&mut renderer, [255, 255, 255, 255]);
+
+	// An invisible glass pane over a white half and a black half, so only
+	// the blurred backdrop shows.
+	let scene = [
+		SceneElement {
+			fill: Fill::Texture(white),
+			geometry: Rectangle::new(0, 0, 32, 32),
+			source: SceneElement::WHOLE_TEXTURE,
+			transform: TextureTransform::Normal,
+			opacity: 1.0,
+			corner_radius: 0.0,
+			blur: false,
+		},
+		SceneElement {
+			fill: Fill::Gradient {
+				top: [0.0; 4],
+				bottom: [0.0; 4],
+			},
+			geometry: Rectangle::new(0, 0, 64, 32),
+			source: SceneElement::WHOLE_TEXTURE,
+			transform: TextureTransform::Normal,
+			opacity: 1.0,
+			corner_radius: 0.0,
+			blur: true,
+		},
+	];
+
+	let capture = render(&mut renderer, &scene);
+	let red = |x: u32| capture.data[((16 * capture.width + x) * 4) as usize];
+
+	// The exact falloff depends on the filtering of the driver, so only its
+	// shape is checked.
+	assert!(red(0) > 200, "far from the edge, white stays white");
+	assert!(red(63) < 55, "far from the edge, black stays black");
+	for x in [30, 31, 32, 33] {
+		assert!(
+			(40..=215).contains(&red(x)),
+			"the edge is blurred, got {} at x = {}",
+			red(x),
+			x
+		);
+	}
+	assert!(red(28) > red(35), "the backdrop fades from white to black");
+}
+
+/// `struct udmabuf_create` of `linux/udmabuf.h`.
+#[repr(C)]
+struct UdmabufCreate {
+	memfd: u32,
+	flags: u32,
+	offset: u64,
+	size: u64,
+}
+
+/// `UDMABUF_CREATE`, turning pages of a memfd into a dmabuf.
+struct CreateUdmabuf(UdmabufCreate);
+
+unsafe impl rustix::ioctl::Ioctl for CreateUdmabuf {
+	type Output = OwnedFd;
+
+	const OPCODE: rustix::ioctl::Opcode =
+		rustix::ioctl::Opcode::write::<UdmabufCreate>(b'u', 0x42);
+	const IS_MUTATING: bool = false;
+
+	fn as_ptr(&mut self) -> *mut std::ffi::c_void {
+		&mut self.0 as *mut UdmabufCreate as *mut _
+	}
+
+	unsafe fn output_from_ptr(
+		out: rustix::ioctl::IoctlOutput,
+		_: *mut std::ffi::c_void,
+	) -> rustix::io::Result<OwnedFd> {
+		Ok(OwnedFd::from_raw_fd(out))
+	}
+}
+
+/// A linear dmabuf holding `data`, or `None` without `/dev/udmabuf`.
+fn udmabuf(data: &[u8]) -> Option<OwnedFd> {
+	use rustix::fs::{MemfdFlags, SealFlags};
+
+	let device = std::fs::File::open("/dev/udmabuf").ok()?;
+
+	let size = data.len().next_multiple_of(4096);
+	let memfd = rustix::fs::memfd_create(
+		"udmabuf",
+		MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING,
+	)
+	.unwrap();
+	let mut file = std::fs::File::from(memfd);
+	file.write_all(data).unwrap();
+	file.set_len(size as u64).unwrap();
+	let memfd = OwnedFd::from(file);
+	rustix::fs::fcntl_add_seals(&memfd, SealFlags::SHRINK).unwrap();
+
+	let create = CreateUdmabuf(UdmabufCreate {
+		memfd: std::os::fd::AsRawFd::as_raw_fd(&memfd) as u32,
+		// UDMABUF_FLAGS_CLOEXEC
+		flags: 1,
+		offset: 0,
+		size: size as u64,
+	});
+	unsafe { rustix::ioctl::ioctl(&device, create) }.ok()
+}
+
+#[test]
+fn dmabuf_import() {
+	let Some(mut renderer) = renderer(16, 16) else {
+		return;
+	};
+	let format = vk::Format::R8G8B8A8_UNORM;
+	// DRM_FORMAT_MOD_LINEAR
+	if !unsafe { renderer.dmabuf_modifiers(format) }.contains(&0) {
+		skip("linear dmabufs cannot be imported");
+		return;
+	}
+
+	// Green on top of blue, stored upside down.
+	let data = [
+		[0, 0, 255, 255].repeat(16 * 8),
+		[0, 255, 0, 255].repeat(16 * 8),
+	]
+	.concat();
+	let Some(fd) = udmabuf(&data) else {
+		skip("/dev/udmabuf is not available");
+		return;
+	};
+
+	let dmabuf = Dmabuf {
+		width: 16,
+		height: 16,
+		format,
+		opaque: false,
+		modifier: 0,
+		planes: vec![DmabufPlane {
+			fd,
+			offset: 0,
+			stride: 16 * 4,
+		}],
+		y_invert: true,
+	};
+	let texture = unsafe {
+		let image = renderer.import_dmabuf(&dmabuf).unwrap();
+		renderer.copy_external_image(None, image).unwrap()
+	};
+
+	let scene = [SceneElement {
+		fill: Fill::Texture(texture),
+		geometry: Rectangle::new(0, 0, 16, 16),
+		source: SceneElement::WHOLE_TEXTURE,
+		transform: TextureTransform::Normal,
+		opacity: 1.0,
+		corner_radius: 0.0,
+		blur: false,
+	}];
+
+	let capture = render(&mut renderer, &scene);
+	let pixel = |y: u32| {
+		let index = (y * capture.width * 4) as usize;
+		&capture.data[index..index + 4]
+	};
+
+	assert_eq!(pixel(0), [0, 255, 0, 255], "the top row is green");
+	assert_eq!(pixel(15), [0, 0, 255, 255], "the bottom row is blue");
+}
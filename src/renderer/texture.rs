@@ -0,0 +1,382 @@
+use anyhow::Result;
+use vulkanalia::vk::{self, DeviceV1_0 as _, HasBuilder as _};
+
+use super::{get_memory_type_index, Renderer};
+
+pub type TextureId = u64;
+
+/// CPU-side pixels to upload into a [`Texture`].
+pub struct ImageData<'a> {
+	pub data: &'a [u8],
+	pub width: u32,
+	pub height: u32,
+	/// Bytes per row in `data`.
+	pub stride: u32,
+	pub format: vk::Format,
+	/// Whether the alpha channel is undefined and must be treated as 1.0.
+	pub opaque: bool,
+}
+
+/// A sampled image holding the contents of a client buffer.
+pub struct Texture {
+	pub image: vk::Image,
+	pub memory: vk::DeviceMemory,
+	pub view: vk::ImageView,
+	/// Binds `view` for the quad pipeline.
+	pub descriptor_set: vk::DescriptorSet,
+	pub format: vk::Format,
+	pub extent: vk::Extent2D,
+	pub opaque: bool,
+}
+
+impl Renderer {
+	/// Copies `data` into the texture `id` through the staging buffer, or into
+	/// a new texture if `id` is `None` or does not match the format or size of
+	/// `data`. Returns once the copy has completed on the GPU.
+	///
+	/// # Safety
+	pub unsafe fn upload_texture(
+		&mut self,
+		id: Option<TextureId>,
+		data: ImageData<'_>,
+	) -> Result<TextureId> {
+		let extent = vk::Extent2D {
+			width: data.width,
+			height: data.height,
+		};
+
+		let id = self.reserve_texture(id, data.format, extent, data.opaque)?;
+
+		let size = data.data.len() as vk::DeviceSize;
+		self.reserve_staging_buffer(size)?;
+
+		let memory = self.device.map_memory(
+			self.staging_memory,
+			0,
+			size,
+			vk::MemoryMapFlags::empty(),
+		)?;
+		std::ptr::copy_nonoverlapping(
+			data.data.as_ptr(),
+			memory.cast(),
+			data.data.len(),
+		);
+		self.device.unmap_memory(self.staging_memory);
+
+		let image = self.textures[&id].image;
+		let command_buffer = self.upload_command_buffer;
+
+		let info = vk::CommandBufferBeginInfo::builder()
+			.flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+		self.device.begin_command_buffer(command_buffer, &info)?;
+
+		// Previous frames may still sample the image, so the transfer has to
+		// wait for their fragment shaders.
+		self.cmd_transition_image(
+			command_buffer,
+			image,
+			(
+				vk::ImageLayout::UNDEFINED,
+				vk::PipelineStageFlags::FRAGMENT_SHADER,
+				vk::AccessFlags::empty(),
+			),
+			(
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::AccessFlags::TRANSFER_WRITE,
+			),
+		);
+
+		let subresource = vk::ImageSubresourceLayers::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.mip_level(0)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		let region = vk::BufferImageCopy::builder()
+			.buffer_offset(0)
+			.buffer_row_length(data.stride / 4)
+			.buffer_image_height(0)
+			.image_subresource(subresource)
+			.image_offset(vk::Offset3D::default())
+			.image_extent(vk::Extent3D {
+				width: extent.width,
+				height: extent.height,
+				depth: 1,
+			});
+
+		self.device.cmd_copy_buffer_to_image(
+			command_buffer,
+			self.staging_buffer,
+			image,
+			vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+			&[region],
+		);
+
+		self.cmd_transition_image(
+			command_buffer,
+			image,
+			(
+				vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+				vk::PipelineStageFlags::TRANSFER,
+				vk::AccessFlags::TRANSFER_WRITE,
+			),
+			(
+				vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+				vk::PipelineStageFlags::FRAGMENT_SHADER,
+				vk::AccessFlags::SHADER_READ,
+			),
+		);
+
+		self.device.end_command_buffer(command_buffer)?;
+
+		let command_buffers = &[command_buffer];
+		let submit_info =
+			vk::SubmitInfo::builder().command_buffers(command_buffers);
+
+		self.device.queue_submit(
+			self.graphics_queue,
+			&[submit_info],
+			self.upload_fence,
+		)?;
+		self.device
+			.wait_for_fences(&[self.upload_fence], true, u64::MAX)?;
+		self.device.reset_fences(&[self.upload_fence])?;
+
+		Ok(id)
+	}
+
+	/// Returns `id` if it is a texture of `format`, `extent` and `opaque`,
+	/// or else replaces it with a new one.
+	pub(super) unsafe fn reserve_texture(
+		&mut self,
+		id: Option<TextureId>,
+		format: vk::Format,
+		extent: vk::Extent2D,
+		opaque: bool,
+	) -> Result<TextureId> {
+		let reusable = id.filter(|id| {
+			self.textures.get(id).is_some_and(|t| {
+				t.format == format && t.extent == extent && t.opaque == opaque
+			})
+		});
+		if let Some(id) = reusable {
+			return Ok(id);
+		}
+
+		if let Some(id) = id {
+			self.destroy_texture(id)?;
+		}
+
+		let texture = self.create_texture(format, extent, opaque)?;
+		let id = self.next_texture_id;
+		self.next_texture_id += 1;
+		self.textures.insert(id, texture);
+
+		Ok(id)
+	}
+
+	/// # Safety
+	pub unsafe fn destroy_texture(&mut self, id: TextureId) -> Result<()> {
+		if let Some(texture) = self.textures.remove(&id) {
+			// In-flight frames may still sample it.
+			self.device.queue_wait_idle(self.graphics_queue)?;
+			self.destroy_texture_resources(&texture);
+		}
+
+		Ok(())
+	}
+
+	pub(super) unsafe fn destroy_texture_resources(&self, texture: &Texture) {
+		let _ = self.device.free_descriptor_sets(
+			self.descriptor_pool,
+			&[texture.descriptor_set],
+		);
+		self.device.destroy_image_view(texture.view, None);
+		self.device.destroy_image(texture.image, None);
+		self.device.free_memory(texture.memory, None);
+	}
+
+	unsafe fn create_texture(
+		&self,
+		format: vk::Format,
+		extent: vk::Extent2D,
+		opaque: bool,
+	) -> Result<Texture> {
+		let info = vk::ImageCreateInfo::builder()
+			.image_type(vk::ImageType::_2D)
+			.extent(vk::Extent3D {
+				width: extent.width,
+				height: extent.height,
+				depth: 1,
+			})
+			.mip_levels(1)
+			.array_layers(1)
+			.format(format)
+			.tiling(vk::ImageTiling::OPTIMAL)
+			.initial_layout(vk::ImageLayout::UNDEFINED)
+			.usage(
+				vk::ImageUsageFlags::SAMPLED
+					| vk::ImageUsageFlags::TRANSFER_DST,
+			)
+			.sharing_mode(vk::SharingMode::EXCLUSIVE)
+			.samples(vk::SampleCountFlags::_1);
+
+		let image = self.device.create_image(&info, None)?;
+
+		let requirements = self.device.get_image_memory_requirements(image);
+		let info = vk::MemoryAllocateInfo::builder()
+			.allocation_size(requirements.size)
+			.memory_type_index(get_memory_type_index(
+				&self.instance,
+				self.physical_device,
+				vk::MemoryPropertyFlags::DEVICE_LOCAL,
+				requirements,
+			)?);
+
+		let memory = self.device.allocate_memory(&info, None)?;
+		self.device.bind_image_memory(image, memory, 0)?;
+
+		let alpha = if opaque {
+			vk::ComponentSwizzle::ONE
+		} else {
+			vk::ComponentSwizzle::IDENTITY
+		};
+		let components = vk::ComponentMapping::builder()
+			.r(vk::ComponentSwizzle::IDENTITY)
+			.g(vk::ComponentSwizzle::IDENTITY)
+			.b(vk::ComponentSwizzle::IDENTITY)
+			.a(alpha);
+
+		let subresource_range = vk::ImageSubresourceRange::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.base_mip_level(0)
+			.level_count(1)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		let info = vk::ImageViewCreateInfo::builder()
+			.image(image)
+			.view_type(vk::ImageViewType::_2D)
+			.format(format)
+			.components(components)
+			.subresource_range(subresource_range);
+
+		let view = self.device.create_image_view(&info, None)?;
+
+		let set_layouts = &[self.descriptor_set_layout];
+		let info = vk::DescriptorSetAllocateInfo::builder()
+			.descriptor_pool(self.descriptor_pool)
+			.set_layouts(set_layouts);
+
+		let descriptor_set = self.device.allocate_descriptor_sets(&info)?[0];
+
+		let image_info = vk::DescriptorImageInfo::builder()
+			.image_view(view)
+			.image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+		let image_infos = &[image_info];
+		let write = vk::WriteDescriptorSet::builder()
+			.dst_set(descriptor_set)
+			.dst_binding(0)
+			.dst_array_element(0)
+			.descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+			.image_info(image_infos);
+
+		self.device
+			.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+
+		Ok(Texture {
+			image,
+			memory,
+			view,
+			descriptor_set,
+			format,
+			extent,
+			opaque,
+		})
+	}
+
+	/// Grows the staging buffer so it can hold at least `size` bytes.
+	pub(super) unsafe fn reserve_staging_buffer(
+		&mut self,
+		size: vk::DeviceSize,
+	) -> Result<()> {
+		if size <= self.staging_size {
+			return Ok(());
+		}
+
+		self.device.destroy_buffer(self.staging_buffer, None);
+		self.device.free_memory(self.staging_memory, None);
+
+		let info = vk::BufferCreateInfo::builder()
+			.size(size)
+			.usage(
+				vk::BufferUsageFlags::TRANSFER_SRC
+					| vk::BufferUsageFlags::TRANSFER_DST,
+			)
+			.sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+		self.staging_buffer = self.device.create_buffer(&info, None)?;
+
+		let requirements = self
+			.device
+			.get_buffer_memory_requirements(self.staging_buffer);
+		let info = vk::MemoryAllocateInfo::builder()
+			.allocation_size(requirements.size)
+			.memory_type_index(get_memory_type_index(
+				&self.instance,
+				self.physical_device,
+				vk::MemoryPropertyFlags::HOST_VISIBLE
+					| vk::MemoryPropertyFlags::HOST_COHERENT,
+				requirements,
+			)?);
+
+		self.staging_memory = self.device.allocate_memory(&info, None)?;
+		self.device.bind_buffer_memory(
+			self.staging_buffer,
+			self.staging_memory,
+			0,
+		)?;
+		self.staging_size = size;
+
+		Ok(())
+	}
+
+	/// Records a layout transition of the whole color image, waiting on
+	/// `src` and blocking `dst`, each given as (layout, stage, access).
+	pub(super) unsafe fn cmd_transition_image(
+		&self,
+		command_buffer: vk::CommandBuffer,
+		image: vk::Image,
+		src: (vk::ImageLayout, vk::PipelineStageFlags, vk::AccessFlags),
+		dst: (vk::ImageLayout, vk::PipelineStageFlags, vk::AccessFlags),
+	) {
+		let subresource_range = vk::ImageSubresourceRange::builder()
+			.aspect_mask(vk::ImageAspectFlags::COLOR)
+			.base_mip_level(0)
+			.level_count(1)
+			.base_array_layer(0)
+			.layer_count(1);
+
+		let barrier = vk::ImageMemoryBarrier::builder()
+			.old_layout(src.0)
+			.new_layout(dst.0)
+			.src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+			.image(image)
+			.subresource_range(subresource_range)
+			.src_access_mask(src.2)
+			.dst_access_mask(dst.2);
+
+		self.device.cmd_pipeline_barrier(
+			command_buffer,
+			src.1,
+			dst.1,
+			vk::DependencyFlags::empty(),
+			&[] as &[vk::MemoryBarrier],
+			&[] as &[vk::BufferMemoryBarrier],
+			&[barrier],
+		);
+	}
+}
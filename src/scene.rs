@@ -0,0 +1,122 @@
+use wayland_server::backend::ObjectId;
+
+use crate::{
+	geometry::{Point, Rectangle},
+	renderer::{Fill, SceneElement},
+	ServerState,
+};
+
+impl ServerState {
+	/// Builds the list of quads to composite for the next frame of the
+	/// output `output`, from bottom to top, moved and scaled from the global
+	/// coordinate space to its pixels.
+	pub fn scene(&self, output: usize) -> Vec<SceneElement> {
+		let mut elements = Vec::new();
+
+		for window in self.space.visible() {
+			elements.extend(self.decoration_elements(&window.surface));
+			elements.extend(
+				self.window_surfaces(&window.surface)
+					.into_iter()
+					.filter_map(|(id, geometry)| {
+						let surface = self.compositor.surfaces.get(&id)?;
+						Some(SceneElement {
+							fill: Fill::Texture(surface.texture?),
+							geometry,
+							source: surface.texture_source(),
+							transform: surface.texture_transform(),
+							opacity: 1.0,
+							corner_radius: 0.0,
+							blur: false,
+						})
+					}),
+			);
+		}
+
+		elements.extend(self.cursor_element());
+
+		let output = &self.outputs[output];
+		let origin = output.location;
+		let bounds = output.geometry();
+		elements.retain(|element| element.geometry.overlaps(bounds));
+		for element in &mut elements {
+			element.geometry = Rectangle::from_loc_and_size(
+				element.geometry.loc() - origin,
+				element.geometry.size(),
+			)
+			.scale(output.scale_factor);
+			element.corner_radius *= output.scale_factor as f32;
+		}
+
+		elements
+	}
+
+	/// Surfaces of a mapped window with their global geometry, from bottom
+	/// to top.
+	pub fn window_surfaces(&self, id: &ObjectId) -> Vec<(ObjectId, Rectangle)> {
+		let mut surfaces = Vec::new();
+		self.push_xdg_surface(id, &mut surfaces);
+		surfaces
+	}
+
+	/// Pushes a mapped xdg surface with its subsurfaces, followed by its
+	/// popups.
+	fn push_xdg_surface(
+		&self,
+		id: &ObjectId,
+		surfaces: &mut Vec<(ObjectId, Rectangle)>,
+	) {
+		let Some(xdg) = self.xdg_shell.surfaces.get(id) else {
+			return;
+		};
+		if !xdg.mapped {
+			return;
+		}
+
+		if let Some(origin) = self.xdg_surface_origin(id) {
+			self.push_surface_tree(id, origin, surfaces);
+		}
+
+		for popup in self.xdg_shell.popups(id) {
+			self.push_xdg_surface(&popup, surfaces);
+		}
+	}
+}
+
+impl ServerState {
+	/// Pushes a surface at the global position `origin` between the
+	/// subsurfaces stacked below and above it. Subsurfaces without a buffer
+	/// are unmapped, and so are their own subsurfaces.
+	fn push_surface_tree(
+		&self,
+		id: &ObjectId,
+		origin: Point,
+		surfaces: &mut Vec<(ObjectId, Rectangle)>,
+	) {
+		let Some(surface) = self.compositor.surfaces.get(id) else {
+			return;
+		};
+		let (Some(_), Some(size)) = (surface.texture, surface.size()) else {
+			return;
+		};
+
+		let push_subsurfaces =
+			|children: &[ObjectId], surfaces: &mut Vec<_>| {
+				for child in children {
+					if let Some(subsurface) =
+						self.subcompositor.subsurfaces.get(child)
+					{
+						self.push_surface_tree(
+							child,
+							origin + subsurface.position,
+							surfaces,
+						);
+					}
+				}
+			};
+
+		push_subsurfaces(&surface.current.subsurfaces_below, surfaces);
+		surfaces.push((id.clone(), Rectangle::from_loc_and_size(origin, size)));
+		push_subsurfaces(&surface.current.subsurfaces_above, surfaces);
+	}
+}
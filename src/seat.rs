@@ -0,0 +1,92 @@
+use anyhow::Result;
+use wayland_server::{
+	backend::GlobalId,
+	protocol::wl_seat::{self, WlSeat},
+	Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use crate::ServerState;
+
+mod cursor;
+mod keyboard;
+mod pointer;
+
+pub use cursor::{CursorImage, CursorTheme, NamedCursor};
+pub use keyboard::{Keyboard, Modifiers};
+pub use pointer::{button_code, Pointer, PointerFocus};
+
+const SEAT_VERSION: u32 = 9;
+const SEAT_NAME: &str = "seat0";
+
+/// State of the `wl_seat` global, forwarding the input of the host window.
+pub struct Seat {
+	pub global: GlobalId,
+	pub keyboard: Keyboard,
+	pub pointer: Pointer,
+	pub cursor_theme: CursorTheme,
+}
+
+impl Seat {
+	pub fn new(display: &DisplayHandle) -> Result<Self> {
+		Ok(Self {
+			global: display
+				.create_global::<ServerState, WlSeat, ()>(SEAT_VERSION, ()),
+			keyboard: Keyboard::new()?,
+			pointer: Pointer::default(),
+			cursor_theme: CursorTheme::default(),
+		})
+	}
+
+	fn capabilities(&self) -> wl_seat::Capability {
+		wl_seat::Capability::Keyboard | wl_seat::Capability::Pointer
+	}
+}
+
+impl GlobalDispatch<WlSeat, ()> for ServerState {
+	fn bind(
+		state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<WlSeat>,
+		_global_data: &(),
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		let seat = data_init.init(resource, ());
+		seat.capabilities(state.seat.capabilities());
+		if seat.version() >= 2 {
+			seat.name(SEAT_NAME.to_string());
+		}
+	}
+}
+
+impl Dispatch<WlSeat, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		_resource: &WlSeat,
+		request: wl_seat::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wl_seat::Request::GetKeyboard { id } => {
+				let keyboard = data_init.init(id, ());
+				state.add_keyboard(keyboard);
+			}
+			wl_seat::Request::GetPointer { id } => {
+				let pointer = data_init.init(id, ());
+				state.add_pointer(pointer);
+			}
+			wl_seat::Request::GetTouch { id } => {
+				data_init.post_error(
+					id,
+					wl_seat::Error::MissingCapability,
+					"the seat has no touchscreen",
+				);
+			}
+			wl_seat::Request::Release => {}
+			_ => unreachable!(),
+		}
+	}
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use vulkanalia::vk;
+use wayland_protocols::wp::cursor_shape::v1::server::wp_cursor_shape_device_v1::Shape;
+use wayland_server::{
+	backend::ObjectId, protocol::wl_surface::WlSurface, Resource as _,
+};
+
+use crate::{
+	compositor::SurfaceRole,
+	geometry::{Point, Rectangle, Size},
+	renderer::{Fill, ImageData, SceneElement, TextureId, TextureTransform},
+	ServerState,
+};
+
+const DEFAULT_THEME: &str = "default";
+const DEFAULT_SIZE: u32 = 24;
+
+/// What to draw at the pointer location.
+#[derive(Clone, Debug)]
+pub enum CursorImage {
+	Hidden,
+	/// A surface with the cursor role, set through `wl_pointer.set_cursor`.
+	Surface {
+		surface: WlSurface,
+		hotspot: Point,
+	},
+	/// A cursor from the XCursor theme.
+	Named(Shape),
+}
+
+impl Default for CursorImage {
+	fn default() -> Self {
+		Self::Named(Shape::Default)
+	}
+}
+
+/// A theme cursor uploaded into a texture.
+pub struct NamedCursor {
+	pub texture: TextureId,
+	pub size: Size,
+	pub hotspot: Point,
+}
+
+/// The XCursor theme named by `XCURSOR_THEME` and `XCURSOR_SIZE`, with its
+/// cursors loaded on first use.
+pub struct CursorTheme {
+	pub theme: xcursor::CursorTheme,
+	pub size: u32,
+	/// `None` for shapes that failed to load.
+	pub cursors: HashMap<Shape, Option<NamedCursor>>,
+}
+
+impl Default for CursorTheme {
+	fn default() -> Self {
+		let name = std::env::var("XCURSOR_THEME")
+			.unwrap_or_else(|_| DEFAULT_THEME.to_string());
+		let size = std::env::var("XCURSOR_SIZE")
+			.ok()
+			.and_then(|size| size.parse().ok())
+			.unwrap_or(DEFAULT_SIZE);
+
+		Self {
+			theme: xcursor::CursorTheme::load(&name),
+			size,
+			cursors: HashMap::new(),
+		}
+	}
+}
+
+impl CursorTheme {
+	/// Whether the theme has a default cursor to replace the host one with.
+	pub fn is_available(&self) -> bool {
+		icon_names(Shape::Default)
+			.iter()
+			.any(|name| self.theme.load_icon(name).is_some())
+	}
+
+	/// Loads the image of `shape` closest to the configured size. Animated
+	/// cursors only show their first frame.
+	fn load(&self, shape: Shape) -> Option<xcursor::parser::Image> {
+		let path = icon_names(shape)
+			.iter()
+			.find_map(|name| self.theme.load_icon(name))?;
+		let data = std::fs::read(&path)
+			.inspect_err(|err| {
+				tracing::warn!("Failed to read cursor {:?}: {}", path, err)
+			})
+			.ok()?;
+		let images = xcursor::parser::parse_xcursor(&data)?;
+
+		let size = images
+			.iter()
+			.map(|image| image.size)
+			.min_by_key(|size| size.abs_diff(self.size))?;
+
+		images.into_iter().find(|image| image.size == size)
+	}
+}
+
+/// XCursor names of a shape, the first being the one from the CSS spec and
+/// the rest legacy fallbacks.
+fn icon_names(shape: Shape) -> &'static [&'static str] {
+	match shape {
+		Shape::Default => &["default", "left_ptr"],
+		Shape::ContextMenu => &["context-menu"],
+		Shape::Help => &["help", "question_arrow"],
+		Shape::Pointer => &["pointer", "hand2"],
+		Shape::Progress => &["progress", "left_ptr_watch"],
+		Shape::Wait => &["wait", "watch"],
+		Shape::Cell => &["cell"],
+		Shape::Crosshair => &["crosshair", "cross"],
+		Shape::Text => &["text", "xterm"],
+		Shape::VerticalText => &["vertical-text"],
+		Shape::Alias => &["alias"],
+		Shape::Copy => &["copy"],
+		Shape::Move => &["move"],
+		Shape::NoDrop => &["no-drop"],
+		Shape::NotAllowed => &["not-allowed", "crossed_circle"],
+		Shape::Grab => &["grab", "openhand"],
+		Shape::Grabbing => &["grabbing", "closedhand"],
+		Shape::EResize => &["e-resize", "right_side"],
+		Shape::NResize => &["n-resize", "top_side"],
+		Shape::NeResize => &["ne-resize", "top_right_corner"],
+		Shape::NwResize => &["nw-resize", "top_left_corner"],
+		Shape::SResize => &["s-resize", "bottom_side"],
+		Shape::SeResize => &["se-resize", "bottom_right_corner"],
+		Shape::SwResize => &["sw-resize", "bottom_left_corner"],
+		Shape::WResize => &["w-resize", "left_side"],
+		Shape::EwResize => &["ew-resize", "sb_h_double_arrow"],
+		Shape::NsResize => &["ns-resize", "sb_v_double_arrow"],
+		Shape::NeswResize => &["nesw-resize", "fd_double_arrow"],
+		Shape::NwseResize => &["nwse-resize", "bd_double_arrow"],
+		Shape::ColResize => &["col-resize"],
+		Shape::RowResize => &["row-resize"],
+		Shape::AllScroll => &["all-scroll", "fleur"],
+		Shape::ZoomIn => &["zoom-in"],
+		Shape::ZoomOut => &["zoom-out"],
+		_ => &["default", "left_ptr"],
+	}
+}
+
+impl ServerState {
+	/// Uploads the theme cursor currently shown, if it was not yet.
+	pub fn prepare_cursor(&mut self) {
+		let CursorImage::Named(shape) = self.seat.pointer.cursor else {
+			return;
+		};
+		if self.seat.cursor_theme.cursors.contains_key(&shape) {
+			return;
+		}
+		let Some(renderer) = self.renderer.as_mut() else {
+			return;
+		};
+
+		let theme = &mut self.seat.cursor_theme;
+		let cursor = theme.load(shape).and_then(|image| {
+			// XCursor pixels are premultiplied little-endian ARGB, just like
+			// `wl_shm::Format::Argb8888`.
+			let data = ImageData {
+				data: &image.pixels_rgba,
+				width: image.width,
+				height: image.height,
+				stride: image.width * 4,
+				format: vk::Format::B8G8R8A8_UNORM,
+				opaque: false,
+			};
+
+			let texture = unsafe { renderer.upload_texture(None, data) }
+				.inspect_err(|err| {
+					tracing::error!("Failed to upload cursor: {}", err)
+				})
+				.ok()?;
+			unsafe {
+				renderer
+					.set_texture_name(texture, &format!("cursor {:?}", shape))
+			};
+
+			Some(NamedCursor {
+				texture,
+				size: Size::new(image.width as i32, image.height as i32),
+				hotspot: Point::new(image.xhot as i32, image.yhot as i32),
+			})
+		});
+
+		if cursor.is_none() {
+			tracing::warn!("Cursor {:?} is missing from the theme", shape);
+		}
+		theme.cursors.insert(shape, cursor);
+	}
+
+	/// The cursor, drawn above everything else.
+	pub fn cursor_element(&self) -> Option<SceneElement> {
+		let (x, y) = self.seat.pointer.location?;
+		let location = Point::new(x.floor() as i32, y.floor() as i32);
+
+		let (texture, source, transform, size, hotspot) =
+			match &self.seat.pointer.cursor {
+				CursorImage::Hidden => return None,
+				CursorImage::Surface { surface, hotspot } => {
+					let surface =
+						self.compositor.surfaces.get(&surface.id())?;
+					(
+						surface.texture?,
+						surface.texture_source(),
+						surface.texture_transform(),
+						surface.size()?,
+						*hotspot,
+					)
+				}
+				CursorImage::Named(shape) => {
+					let cursor =
+						self.seat.cursor_theme.cursors.get(shape)?.as_ref()?;
+					(
+						cursor.texture,
+						SceneElement::WHOLE_TEXTURE,
+						TextureTransform::Normal,
+						cursor.size,
+						cursor.hotspot,
+					)
+				}
+			};
+
+		Some(SceneElement {
+			fill: Fill::Texture(texture),
+			geometry: Rectangle::from_loc_and_size(location - hotspot, size),
+			source,
+			transform,
+			opacity: 1.0,
+			corner_radius: 0.0,
+			blur: false,
+		})
+	}
+
+	/// Moves the hotspot of a cursor surface by the offset of its new buffer.
+	pub fn cursor_commit(&mut self, id: &ObjectId) {
+		let Some(surface) = self.compositor.surfaces.get(id) else {
+			return;
+		};
+		if surface.role != Some(SurfaceRole::Cursor) {
+			return;
+		}
+
+		let offset = surface.current.offset;
+		if let CursorImage::Surface {
+			surface: cursor,
+			hotspot,
+		} = &mut self.seat.pointer.cursor
+		{
+			if cursor.id() == *id {
+				*hotspot = *hotspot - offset;
+			}
+		}
+	}
+}
@@ -0,0 +1,455 @@
+use std::{
+	fs::File,
+	io::Write as _,
+	os::fd::{AsFd as _, OwnedFd},
+};
+
+use anyhow::{anyhow, Result};
+use rustix::fs::{MemfdFlags, SealFlags};
+use wayland_server::{
+	backend::ClientId,
+	protocol::{
+		wl_keyboard::{self, WlKeyboard},
+		wl_surface::WlSurface,
+	},
+	Client, DataInit, Dispatch, DisplayHandle, Resource,
+};
+use winit::keyboard::ModifiersState;
+use xkbcommon::xkb;
+
+use crate::ServerState;
+
+#[cfg(test)]
+mod tests;
+
+/// Keys per second once repeat kicks in.
+const REPEAT_RATE: i32 = 25;
+/// Milliseconds before a held key starts repeating.
+const REPEAT_DELAY: i32 = 600;
+
+/// Evdev keycodes are offset by 8 from xkb keycodes.
+const EVDEV_OFFSET: u32 = 8;
+
+/// Serialized xkb modifier state, as sent in `wl_keyboard.modifiers`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+	pub depressed: u32,
+	pub latched: u32,
+	pub locked: u32,
+	pub group: u32,
+}
+
+impl Modifiers {
+	fn from_state(state: &xkb::State) -> Self {
+		Self {
+			depressed: state.serialize_mods(xkb::STATE_MODS_DEPRESSED),
+			latched: state.serialize_mods(xkb::STATE_MODS_LATCHED),
+			locked: state.serialize_mods(xkb::STATE_MODS_LOCKED),
+			group: state.serialize_layout(xkb::STATE_LAYOUT_EFFECTIVE),
+		}
+	}
+
+	/// The modifiers left once every key is released, which keeps the locks.
+	fn released(self) -> Self {
+		Self {
+			depressed: 0,
+			latched: 0,
+			..self
+		}
+	}
+
+	/// Replaces the depressed modifiers among `known` with those of `host`,
+	/// both masks of xkb modifiers.
+	fn with_host_depressed(self, known: u32, host: u32) -> Self {
+		Self {
+			depressed: (self.depressed & !known) | (host & known),
+			..self
+		}
+	}
+}
+
+/// Masks of the xkb modifiers in `host`, and of all those the host reports,
+/// given the xkb mask of each winit modifier in `masks`.
+fn host_mask(
+	host: ModifiersState,
+	masks: &[(ModifiersState, u32)],
+) -> (u32, u32) {
+	masks
+		.iter()
+		.fold((0, 0), |(pressed, known), (modifier, mask)| {
+			let pressed = if host.contains(*modifier) {
+				pressed | mask
+			} else {
+				pressed
+			};
+			(pressed, known | mask)
+		})
+}
+
+/// The seat keyboard, fed with the key events of the host window.
+pub struct Keyboard {
+	/// Keymap sent to clients, in the xkb text format.
+	pub keymap: String,
+	pub state: xkb::State,
+	/// Evdev keycodes of the keys currently held.
+	pub keys: Vec<u32>,
+	pub modifiers: Modifiers,
+	/// Surface receiving key events.
+	pub focus: Option<WlSurface>,
+	/// Whether the host window has keyboard focus. The focused surface only
+	/// has an active enter while it does.
+	pub host_focused: bool,
+	pub resources: Vec<WlKeyboard>,
+}
+
+impl Keyboard {
+	/// Compiles the keymap named by the `XKB_DEFAULT_*` environment
+	/// variables, or the system default.
+	pub fn new() -> Result<Self> {
+		let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+		let keymap = xkb::Keymap::new_from_names(
+			&context,
+			"",
+			"",
+			"",
+			"",
+			None,
+			xkb::KEYMAP_COMPILE_NO_FLAGS,
+		)
+		.ok_or_else(|| anyhow!("failed to compile the xkb keymap"))?;
+
+		let state = xkb::State::new(&keymap);
+
+		Ok(Self {
+			keymap: keymap.get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1),
+			modifiers: Modifiers::from_state(&state),
+			state,
+			keys: Vec::new(),
+			focus: None,
+			host_focused: false,
+			resources: Vec::new(),
+		})
+	}
+
+	/// Resets the xkb state to `modifiers`, forgetting the keys held.
+	fn reset_state(&mut self, modifiers: Modifiers) {
+		self.state = xkb::State::new(&self.state.get_keymap());
+		self.state.update_mask(
+			modifiers.depressed,
+			modifiers.latched,
+			modifiers.locked,
+			0,
+			0,
+			modifiers.group,
+		);
+		self.keys.clear();
+	}
+
+	/// xkb masks of the modifiers winit reports, missing from the keymap
+	/// ones left out.
+	fn host_mod_masks(&self) -> Vec<(ModifiersState, u32)> {
+		let keymap = self.state.get_keymap();
+		[
+			(ModifiersState::SHIFT, xkb::MOD_NAME_SHIFT),
+			(ModifiersState::CONTROL, xkb::MOD_NAME_CTRL),
+			(ModifiersState::ALT, xkb::MOD_NAME_ALT),
+			(ModifiersState::SUPER, xkb::MOD_NAME_LOGO),
+		]
+		.into_iter()
+		.filter_map(|(modifier, name)| {
+			let index = keymap.mod_get_index(name);
+			(index != xkb::MOD_INVALID).then(|| (modifier, 1 << index))
+		})
+		.collect()
+	}
+
+	/// Surface that currently holds an enter.
+	fn entered(&self) -> Option<&WlSurface> {
+		self.focus.as_ref().filter(|_| self.host_focused)
+	}
+
+	/// Keyboards of the client owning `surface`.
+	fn resources_for<'a>(
+		&'a self,
+		surface: &'a WlSurface,
+	) -> impl Iterator<Item = &'a WlKeyboard> {
+		self.resources
+			.iter()
+			.filter(|keyboard| keyboard.id().same_client_as(&surface.id()))
+	}
+
+	/// Writes the keymap into a sealed memfd, so each client can map it
+	/// without being able to change it for others.
+	fn keymap_fd(&self) -> Result<(OwnedFd, u32)> {
+		let fd = rustix::fs::memfd_create(
+			"neora-keymap",
+			MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING,
+		)?;
+
+		let mut file = File::from(fd);
+		file.write_all(self.keymap.as_bytes())?;
+		file.write_all(&[0])?;
+
+		let fd = OwnedFd::from(file);
+		rustix::fs::fcntl_add_seals(
+			&fd,
+			SealFlags::SHRINK
+				| SealFlags::GROW
+				| SealFlags::WRITE
+				| SealFlags::SEAL,
+		)?;
+
+		Ok((fd, self.keymap.len() as u32 + 1))
+	}
+
+	fn send_enter(
+		&self,
+		keyboard: &WlKeyboard,
+		surface: &WlSurface,
+		serial: u32,
+	) {
+		let keys = self.keys.iter().flat_map(|key| key.to_ne_bytes()).collect();
+		keyboard.enter(serial, surface, keys);
+		self.send_modifiers(keyboard, serial);
+	}
+
+	fn send_modifiers(&self, keyboard: &WlKeyboard, serial: u32) {
+		let modifiers = self.modifiers;
+		keyboard.modifiers(
+			serial,
+			modifiers.depressed,
+			modifiers.latched,
+			modifiers.locked,
+			modifiers.group,
+		);
+	}
+}
+
+impl ServerState {
+	/// Forwards a key of the host window, given as an evdev keycode.
+	///
+	/// Synthetic events, which the host sends for keys pressed or released
+	/// while it was not focused, only update the keyboard state. Clients
+	/// learn about those keys from the next `enter`.
+	pub fn handle_key(&mut self, keycode: u32, pressed: bool, synthetic: bool) {
+		let keyboard = &mut self.seat.keyboard;
+
+		let direction = if pressed {
+			xkb::KeyDirection::Down
+		} else {
+			xkb::KeyDirection::Up
+		};
+		keyboard
+			.state
+			.update_key(xkb::Keycode::new(keycode + EVDEV_OFFSET), direction);
+
+		let was_pressed = keyboard.keys.contains(&keycode);
+		if pressed && !was_pressed {
+			keyboard.keys.push(keycode);
+		} else if !pressed {
+			keyboard.keys.retain(|key| *key != keycode);
+		}
+
+		if !synthetic && pressed != was_pressed && keyboard.entered().is_some()
+		{
+			let serial = self.next_serial();
+			let time = self.now();
+			let keyboard = &self.seat.keyboard;
+			let surface = keyboard.entered().unwrap();
+
+			let state = if pressed {
+				wl_keyboard::KeyState::Pressed
+			} else {
+				wl_keyboard::KeyState::Released
+			};
+			for resource in keyboard.resources_for(surface) {
+				resource.key(serial, time, keycode, state);
+			}
+		}
+
+		self.update_modifiers();
+	}
+
+	/// Takes the modifiers the host reports as held over the ones of the
+	/// keys seen, which miss keys pressed or released while unfocused if
+	/// the host sends no synthetic events for them. The host does not report
+	/// locks, so those toggled while unfocused stay out of sync.
+	pub fn handle_host_modifiers(&mut self, host: ModifiersState) {
+		let keyboard = &mut self.seat.keyboard;
+		let (pressed, known) = host_mask(host, &keyboard.host_mod_masks());
+		let modifiers = keyboard.modifiers.with_host_depressed(known, pressed);
+		if modifiers == keyboard.modifiers {
+			return;
+		}
+
+		keyboard.state.update_mask(
+			modifiers.depressed,
+			modifiers.latched,
+			modifiers.locked,
+			0,
+			0,
+			modifiers.group,
+		);
+		self.update_modifiers();
+	}
+
+	/// Sends the modifiers of the xkb state to the focused client if they
+	/// changed.
+	fn update_modifiers(&mut self) {
+		let keyboard = &mut self.seat.keyboard;
+		let modifiers = Modifiers::from_state(&keyboard.state);
+		if modifiers == keyboard.modifiers {
+			return;
+		}
+		keyboard.modifiers = modifiers;
+
+		if keyboard.entered().is_none() {
+			return;
+		}
+
+		let serial = self.next_serial();
+		let keyboard = &self.seat.keyboard;
+		let surface = keyboard.entered().unwrap();
+		for resource in keyboard.resources_for(surface) {
+			keyboard.send_modifiers(resource, serial);
+		}
+	}
+
+	/// Tracks the keyboard focus of the host window, sending `leave` while
+	/// it is lost and `enter` again once it comes back.
+	pub fn set_host_focus(&mut self, focused: bool) {
+		if self.seat.keyboard.host_focused == focused {
+			return;
+		}
+
+		let serial = self.next_serial();
+		let keyboard = &mut self.seat.keyboard;
+
+		if let Some(surface) = keyboard.entered() {
+			for resource in keyboard.resources_for(surface) {
+				resource.leave(serial, surface);
+			}
+		}
+
+		keyboard.host_focused = focused;
+
+		// Keys released while unfocused would otherwise stay held. The keys
+		// still held once focus comes back are pressed again by the host,
+		// or reported by `handle_host_modifiers`.
+		if !focused {
+			keyboard.reset_state(keyboard.modifiers.released());
+			keyboard.modifiers = Modifiers::from_state(&keyboard.state);
+		}
+
+		if let Some(surface) = keyboard.entered() {
+			for resource in keyboard.resources_for(surface) {
+				keyboard.send_enter(resource, surface, serial);
+			}
+		}
+	}
+
+	/// Moves keyboard focus to `surface`, updating the activated state of
+	/// the windows involved.
+	pub fn set_keyboard_focus(&mut self, surface: Option<WlSurface>) {
+		if self.seat.keyboard.focus == surface {
+			return;
+		}
+
+		let serial = self.next_serial();
+		let keyboard = &mut self.seat.keyboard;
+
+		let previous = keyboard.focus.take();
+		if let Some(previous) =
+			previous.as_ref().filter(|_| keyboard.host_focused)
+		{
+			for resource in keyboard.resources_for(previous) {
+				resource.leave(serial, previous);
+			}
+		}
+
+		keyboard.focus = surface.clone();
+
+		if let Some(surface) = keyboard.entered() {
+			for resource in keyboard.resources_for(surface) {
+				keyboard.send_enter(resource, surface, serial);
+			}
+		}
+
+		// Popups taking focus leave their window activated.
+		let previous = previous.and_then(|s| self.window_of(&s.id()));
+		let window = surface.and_then(|s| self.window_of(&s.id()));
+		if previous == window {
+			return;
+		}
+
+		if let Some(previous) = previous {
+			self.update_toplevel(&previous, |toplevel, _| {
+				toplevel.requested.activated = false;
+			});
+		}
+		if let Some(window) = window {
+			self.space.focused(&window);
+			self.update_toplevel(&window, |toplevel, _| {
+				toplevel.requested.activated = true;
+			});
+		}
+	}
+
+	pub(super) fn add_keyboard(&mut self, resource: WlKeyboard) {
+		let keyboard = &mut self.seat.keyboard;
+
+		match keyboard.keymap_fd() {
+			Ok((fd, size)) => resource.keymap(
+				wl_keyboard::KeymapFormat::XkbV1,
+				fd.as_fd(),
+				size,
+			),
+			Err(err) => tracing::error!("Failed to share keymap: {}", err),
+		}
+
+		if resource.version() >= 4 {
+			resource.repeat_info(REPEAT_RATE, REPEAT_DELAY);
+		}
+
+		let entered = keyboard
+			.entered()
+			.filter(|surface| resource.id().same_client_as(&surface.id()))
+			.cloned();
+		keyboard.resources.push(resource.clone());
+
+		if let Some(surface) = entered {
+			let serial = self.next_serial();
+			self.seat.keyboard.send_enter(&resource, &surface, serial);
+		}
+	}
+}
+
+impl Dispatch<WlKeyboard, ()> for ServerState {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &WlKeyboard,
+		request: wl_keyboard::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wl_keyboard::Request::Release => {}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		resource: &WlKeyboard,
+		_data: &(),
+	) {
+		state
+			.seat
+			.keyboard
+			.resources
+			.retain(|keyboard| keyboard != resource);
+	}
+}
@@ -0,0 +1,82 @@
+use winit::keyboard::ModifiersState;
+
+use super::{host_mask, Modifiers};
+
+const SHIFT: u32 = 1 << 0;
+const CAPS: u32 = 1 << 1;
+const CTRL: u32 = 1 << 2;
+const ALT: u32 = 1 << 3;
+const LOGO: u32 = 1 << 6;
+
+/// The masks of the default keymap.
+const MASKS: &[(ModifiersState, u32)] = &[
+	(ModifiersState::SHIFT, SHIFT),
+	(ModifiersState::CONTROL, CTRL),
+	(ModifiersState::ALT, ALT),
+	(ModifiersState::SUPER, LOGO),
+];
+
+#[test]
+fn host_mask_maps_winit_modifiers() {
+	assert_eq!(
+		host_mask(ModifiersState::empty(), MASKS),
+		(0, SHIFT | CTRL | ALT | LOGO)
+	);
+	assert_eq!(
+		host_mask(ModifiersState::SHIFT | ModifiersState::SUPER, MASKS),
+		(SHIFT | LOGO, SHIFT | CTRL | ALT | LOGO)
+	);
+	// Modifiers missing from the keymap are never reported.
+	assert_eq!(
+		host_mask(ModifiersState::ALT, &MASKS[..2]),
+		(0, SHIFT | CTRL)
+	);
+}
+
+#[test]
+fn modifiers_clear_after_focus_loss_and_regain() {
+	let held = Modifiers {
+		depressed: SHIFT | CTRL,
+		latched: ALT,
+		locked: CAPS,
+		group: 1,
+	};
+
+	let unfocused = held.released();
+	assert_eq!(
+		unfocused,
+		Modifiers {
+			depressed: 0,
+			latched: 0,
+			locked: CAPS,
+			group: 1,
+		}
+	);
+
+	// Back in focus, with nothing held on the host.
+	let (pressed, known) = host_mask(ModifiersState::empty(), MASKS);
+	assert_eq!(unfocused.with_host_depressed(known, pressed), unfocused);
+
+	// Back in focus, with Control held on the host, of which no key was seen.
+	let (pressed, known) = host_mask(ModifiersState::CONTROL, MASKS);
+	assert_eq!(
+		unfocused.with_host_depressed(known, pressed),
+		Modifiers {
+			depressed: CTRL,
+			..unfocused
+		}
+	);
+}
+
+#[test]
+fn host_clears_stale_modifiers() {
+	// Shift was released without a key event reaching us.
+	let stale = Modifiers {
+		depressed: SHIFT | (1 << 7),
+		..Modifiers::default()
+	};
+
+	let (pressed, known) = host_mask(ModifiersState::empty(), MASKS);
+	// Modifiers the host does not know about are left alone.
+	assert_eq!(stale.with_host_depressed(known, pressed).depressed, 1 << 7);
+}
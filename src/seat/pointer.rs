@@ -0,0 +1,486 @@
+use wayland_protocols::wp::cursor_shape::v1::server::wp_cursor_shape_device_v1::Shape;
+use wayland_server::{
+	backend::{ClientId, ObjectId},
+	protocol::{
+		wl_pointer::{self, WlPointer},
+		wl_surface::WlSurface,
+	},
+	Client, DataInit, Dispatch, DisplayHandle, Resource,
+};
+
+use crate::{compositor::SurfaceRole, geometry::Point, ServerState};
+
+use super::CursorImage;
+
+/// Scroll distance of one wheel detent, in surface-local pixels.
+const WHEEL_STEP: f64 = 15.0;
+
+// Linux input event codes of the mouse buttons.
+const BTN_MOUSE: u32 = 0x110;
+const BTN_MOUSE_LAST: u32 = 0x11f;
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+const BTN_SIDE: u32 = 0x113;
+const BTN_EXTRA: u32 = 0x114;
+
+/// Surface holding the pointer focus.
+#[derive(Clone, Debug)]
+pub struct PointerFocus {
+	pub surface: WlSurface,
+	/// Global position of the surface when it gained focus.
+	pub origin: Point,
+}
+
+/// The seat pointer, fed with the cursor events of the host window.
+#[derive(Default)]
+pub struct Pointer {
+	/// Position in global coordinates, or `None` while the cursor is outside
+	/// the host window.
+	pub location: Option<(f64, f64)>,
+	pub focus: Option<PointerFocus>,
+	/// Buttons currently held. While any is, the focus stays on the surface
+	/// that got the first press.
+	pub buttons: Vec<u32>,
+	/// Serial of the first press of the held buttons, which move and resize
+	/// requests must match.
+	pub press_serial: Option<u32>,
+	/// Serial of the last `enter`, which `set_cursor` requests must match.
+	pub enter_serial: Option<u32>,
+	pub cursor: CursorImage,
+	pub resources: Vec<WlPointer>,
+}
+
+impl Pointer {
+	/// Pointers of the client owning `surface`.
+	fn resources_for<'a>(
+		&'a self,
+		surface: &'a WlSurface,
+	) -> impl Iterator<Item = &'a WlPointer> {
+		self.resources
+			.iter()
+			.filter(|pointer| pointer.id().same_client_as(&surface.id()))
+	}
+
+	/// Whether the client owning `object` may change the cursor image, given
+	/// the serial of the `enter` it is responding to.
+	pub fn accepts_cursor_from(&self, object: &ObjectId, serial: u32) -> bool {
+		self.focus
+			.as_ref()
+			.is_some_and(|focus| focus.surface.id().same_client_as(object))
+			&& self.enter_serial == Some(serial)
+	}
+
+	/// Location relative to the focused surface.
+	fn local_location(&self) -> Option<(f64, f64)> {
+		let (x, y) = self.location?;
+		let origin = self.focus.as_ref()?.origin;
+
+		Some((x - origin.x as f64, y - origin.y as f64))
+	}
+
+	/// Calls `f` on each pointer of the focused client, then ends the group
+	/// of events with `frame`.
+	fn send(&self, f: impl Fn(&WlPointer)) {
+		let Some(focus) = &self.focus else {
+			return;
+		};
+
+		for pointer in self.resources_for(&focus.surface) {
+			f(pointer);
+			if pointer.version() >= 5 {
+				pointer.frame();
+			}
+		}
+	}
+}
+
+/// Converts a winit button into a Linux input event code, or `None` for
+/// buttons without one.
+pub fn button_code(button: winit::event::MouseButton) -> Option<u32> {
+	match button {
+		winit::event::MouseButton::Left => Some(BTN_LEFT),
+		winit::event::MouseButton::Right => Some(BTN_RIGHT),
+		winit::event::MouseButton::Middle => Some(BTN_MIDDLE),
+		// Hosts fold both side button codes into these, so report the ones
+		// mice and X11 use for back and forward.
+		winit::event::MouseButton::Back => Some(BTN_SIDE),
+		winit::event::MouseButton::Forward => Some(BTN_EXTRA),
+		// Wayland hosts report the remaining mouse buttons by their event
+		// code, which is passed on. X11 hosts report them by their X button
+		// number, which has no event code, so they are dropped.
+		winit::event::MouseButton::Other(code) => Some(u32::from(code))
+			.filter(|code| (BTN_MOUSE..=BTN_MOUSE_LAST).contains(code)),
+	}
+}
+
+impl ServerState {
+	/// Topmost surface accepting input at the global position `(x, y)`, with
+	/// its global position.
+	pub fn surface_under(&self, x: f64, y: f64) -> Option<(WlSurface, Point)> {
+		let point = Point::new(x.floor() as i32, y.floor() as i32);
+
+		for window in self.space.visible().rev() {
+			let under = self
+				.window_surfaces(&window.surface)
+				.into_iter()
+				.rev()
+				.find_map(|(id, geometry)| {
+					let surface = self.compositor.surfaces.get(&id)?;
+					surface
+						.accepts_input(point - geometry.loc())
+						.then(|| (surface.handle.clone(), geometry.loc()))
+				});
+			if under.is_some() {
+				return under;
+			}
+
+			// Decorations hide the windows below.
+			if self.decoration_part_at(&window.surface, point).is_some() {
+				return None;
+			}
+		}
+
+		None
+	}
+
+	pub fn handle_pointer_motion(&mut self, x: f64, y: f64) {
+		self.seat.pointer.location = Some((x, y));
+
+		// Clients see no motion while their window is dragged around.
+		if self.space.grab.is_some() {
+			self.grab_motion(x, y);
+			return;
+		}
+
+		if self.update_pointer_focus() {
+			return;
+		}
+
+		if self.seat.pointer.focus.is_none() {
+			let shape = self.decoration_cursor(x, y);
+			self.seat.pointer.cursor = CursorImage::Named(shape);
+			return;
+		}
+
+		let time = self.now();
+		let pointer = &self.seat.pointer;
+		if let Some((x, y)) = pointer.local_location() {
+			pointer.send(|resource| resource.motion(time, x, y));
+		}
+	}
+
+	pub fn handle_pointer_button(
+		&mut self,
+		button: winit::event::MouseButton,
+		pressed: bool,
+	) {
+		let Some(button) = button_code(button) else {
+			return;
+		};
+		let pointer = &mut self.seat.pointer;
+
+		if pressed {
+			if pointer.buttons.contains(&button) {
+				return;
+			}
+			pointer.buttons.push(button);
+		} else {
+			let held = pointer.buttons.len();
+			pointer.buttons.retain(|other| *other != button);
+			if pointer.buttons.len() == held {
+				return;
+			}
+		}
+
+		if pressed && self.seat.pointer.buttons.len() == 1 {
+			self.popup_grab_press();
+		}
+
+		// The buttons belong to the grab until the last one is released.
+		if self.space.grab.is_some() {
+			if self.seat.pointer.buttons.is_empty() {
+				self.end_grab();
+				self.update_pointer_focus();
+			}
+			return;
+		}
+
+		// Server-side decorations handle the left button themselves.
+		if pressed && self.seat.pointer.buttons == [BTN_LEFT] {
+			let decoration = self
+				.seat
+				.pointer
+				.location
+				.filter(|_| self.seat.pointer.focus.is_none())
+				.and_then(|(x, y)| self.decoration_under(x, y));
+			if let Some((window, part)) = decoration {
+				self.press_decoration(&window, part);
+				return;
+			}
+		}
+
+		// Clicking a window raises it and gives it keyboard focus.
+		if pressed && self.seat.pointer.buttons.len() == 1 {
+			if let Some(id) =
+				self.seat.pointer.focus.as_ref().map(|f| f.surface.id())
+			{
+				self.activate_window(&id);
+			}
+		}
+
+		let serial = self.next_serial();
+		if pressed && self.seat.pointer.buttons.len() == 1 {
+			self.seat.pointer.press_serial = Some(serial);
+		} else if self.seat.pointer.buttons.is_empty() {
+			self.seat.pointer.press_serial = None;
+		}
+		let time = self.now();
+		let state = if pressed {
+			wl_pointer::ButtonState::Pressed
+		} else {
+			wl_pointer::ButtonState::Released
+		};
+		self.seat
+			.pointer
+			.send(|resource| resource.button(serial, time, button, state));
+
+		// The implicit grab ended, so the pointer may be over another
+		// surface by now.
+		if self.seat.pointer.buttons.is_empty() {
+			self.update_pointer_focus();
+		}
+	}
+
+	pub fn handle_pointer_axis(
+		&mut self,
+		delta: winit::event::MouseScrollDelta,
+		phase: winit::event::TouchPhase,
+	) {
+		let time = self.now();
+
+		// Positive winit deltas move the content right and down, while
+		// positive axis values scroll the view right and down.
+		let (source, x, y, steps) = match delta {
+			winit::event::MouseScrollDelta::LineDelta(x, y) => (
+				wl_pointer::AxisSource::Wheel,
+				-x as f64 * WHEEL_STEP,
+				-y as f64 * WHEEL_STEP,
+				Some((-x as f64, -y as f64)),
+			),
+			winit::event::MouseScrollDelta::PixelDelta(delta) => {
+				(wl_pointer::AxisSource::Finger, -delta.x, -delta.y, None)
+			}
+		};
+		let stopped = source == wl_pointer::AxisSource::Finger
+			&& matches!(
+				phase,
+				winit::event::TouchPhase::Ended
+					| winit::event::TouchPhase::Cancelled
+			);
+
+		self.seat.pointer.send(|resource| {
+			if resource.version() >= 5 {
+				resource.axis_source(source);
+			}
+
+			let axes = [
+				(wl_pointer::Axis::HorizontalScroll, x, steps.map(|s| s.0)),
+				(wl_pointer::Axis::VerticalScroll, y, steps.map(|s| s.1)),
+			];
+			for (axis, value, steps) in axes {
+				if stopped {
+					if resource.version() >= 5 {
+						resource.axis_stop(time, axis);
+					}
+					continue;
+				}
+				if value == 0.0 {
+					continue;
+				}
+
+				if let Some(steps) = steps {
+					if resource.version() >= 8 {
+						resource.axis_value120(axis, (steps * 120.0) as i32);
+					} else if resource.version() >= 5 {
+						resource.axis_discrete(axis, steps as i32);
+					}
+				}
+				resource.axis(time, axis, value);
+			}
+		});
+	}
+
+	/// Handles the cursor leaving the host window.
+	pub fn handle_pointer_leave(&mut self) {
+		self.seat.pointer.location = None;
+		self.set_pointer_focus(None);
+	}
+
+	/// Moves the pointer focus to the surface under the cursor, unless a
+	/// button is held. Returns whether the focus changed, in which case the
+	/// `enter` event already carried the new position.
+	fn update_pointer_focus(&mut self) -> bool {
+		if !self.seat.pointer.buttons.is_empty() {
+			return false;
+		}
+
+		let focus = self
+			.seat
+			.pointer
+			.location
+			.and_then(|(x, y)| self.surface_under(x, y))
+			.map(|(surface, origin)| PointerFocus { surface, origin });
+
+		let unchanged = match (&self.seat.pointer.focus, &focus) {
+			(Some(old), Some(new)) => {
+				old.surface == new.surface && old.origin == new.origin
+			}
+			(None, None) => true,
+			_ => false,
+		};
+		if unchanged {
+			return false;
+		}
+
+		let hovered = focus.as_ref().map(|focus| focus.surface.id());
+		self.set_pointer_focus(focus);
+
+		if let Some(id) = self.space.focus_policy.hover_focus(hovered) {
+			self.focus_window(&id);
+		}
+
+		true
+	}
+
+	fn set_pointer_focus(&mut self, focus: Option<PointerFocus>) {
+		let same_surface = matches!(
+			(&self.seat.pointer.focus, &focus),
+			(Some(old), Some(new)) if old.surface == new.surface
+		);
+
+		if !same_surface {
+			if let Some(surface) =
+				self.seat.pointer.focus.as_ref().map(|f| f.surface.clone())
+			{
+				let serial = self.next_serial();
+				self.seat
+					.pointer
+					.send(|resource| resource.leave(serial, &surface));
+			}
+
+			// Clients set their cursor in response to `enter`, until then
+			// show the default one.
+			self.seat.pointer.enter_serial = None;
+			self.seat.pointer.cursor = CursorImage::Named(Shape::Default);
+		}
+
+		self.seat.pointer.focus = focus;
+
+		let pointer = &self.seat.pointer;
+		let Some((x, y)) = pointer.local_location() else {
+			return;
+		};
+
+		if same_surface {
+			// The surface moved under the cursor.
+			let time = self.now();
+			pointer.send(|resource| resource.motion(time, x, y));
+		} else {
+			let serial = self.next_serial();
+			self.seat.pointer.enter_serial = Some(serial);
+			let pointer = &self.seat.pointer;
+			let surface = pointer.focus.as_ref().unwrap().surface.clone();
+			pointer.send(|resource| resource.enter(serial, &surface, x, y));
+		}
+	}
+
+	pub(super) fn add_pointer(&mut self, resource: WlPointer) {
+		self.seat.pointer.resources.push(resource.clone());
+
+		let pointer = &self.seat.pointer;
+		let (Some(focus), Some((x, y))) =
+			(&pointer.focus, pointer.local_location())
+		else {
+			return;
+		};
+
+		if resource.id().same_client_as(&focus.surface.id()) {
+			let surface = focus.surface.clone();
+			let serial = self.next_serial();
+			self.seat.pointer.enter_serial = Some(serial);
+			resource.enter(serial, &surface, x, y);
+			if resource.version() >= 5 {
+				resource.frame();
+			}
+		}
+	}
+}
+
+impl Dispatch<WlPointer, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &WlPointer,
+		request: wl_pointer::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wl_pointer::Request::SetCursor {
+				serial,
+				surface,
+				hotspot_x,
+				hotspot_y,
+			} => {
+				if !state
+					.seat
+					.pointer
+					.accepts_cursor_from(&resource.id(), serial)
+				{
+					return;
+				}
+
+				let Some(surface) = surface else {
+					state.seat.pointer.cursor = CursorImage::Hidden;
+					return;
+				};
+
+				let Some(cursor) =
+					state.compositor.surfaces.get_mut(&surface.id())
+				else {
+					return;
+				};
+				if cursor.role.is_some_and(|role| role != SurfaceRole::Cursor) {
+					resource.post_error(
+						wl_pointer::Error::Role,
+						"surface already has another role",
+					);
+					return;
+				}
+				cursor.role = Some(SurfaceRole::Cursor);
+
+				state.seat.pointer.cursor = CursorImage::Surface {
+					surface,
+					hotspot: Point::new(hotspot_x, hotspot_y),
+				};
+			}
+			wl_pointer::Request::Release => {}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		resource: &WlPointer,
+		_data: &(),
+	) {
+		state
+			.seat
+			.pointer
+			.resources
+			.retain(|pointer| pointer != resource);
+	}
+}
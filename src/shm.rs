@@ -0,0 +1,299 @@
+use std::{
+	os::fd::OwnedFd,
+	sync::{Arc, Mutex},
+};
+
+use memmap2::{Mmap, MmapOptions};
+use vulkanalia::vk;
+use wayland_server::{
+	backend::GlobalId,
+	protocol::{
+		wl_buffer::{self, WlBuffer},
+		wl_shm::{self, WlShm},
+		wl_shm_pool::{self, WlShmPool},
+	},
+	Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+	WEnum,
+};
+
+use crate::{renderer::ImageData, ServerState};
+
+mod sigbus;
+#[cfg(test)]
+mod tests;
+
+const SHM_VERSION: u32 = 1;
+
+/// Formats advertised to clients. `Argb8888` and `Xrgb8888` are mandatory,
+/// the rest map directly onto a Vulkan format as well.
+const FORMATS: &[wl_shm::Format] = &[
+	wl_shm::Format::Argb8888,
+	wl_shm::Format::Xrgb8888,
+	wl_shm::Format::Abgr8888,
+	wl_shm::Format::Xbgr8888,
+];
+
+pub struct Shm {
+	pub global: GlobalId,
+}
+
+impl Shm {
+	pub fn new(display: &DisplayHandle) -> Self {
+		Self {
+			global: display
+				.create_global::<ServerState, WlShm, ()>(SHM_VERSION, ()),
+		}
+	}
+}
+
+/// Returns the Vulkan format matching a shm format, and whether its alpha
+/// channel must be ignored.
+pub fn vk_format(format: wl_shm::Format) -> Option<(vk::Format, bool)> {
+	// wl_shm formats are little-endian, so `Argb8888` is B, G, R, A in memory.
+	match format {
+		wl_shm::Format::Argb8888 => Some((vk::Format::B8G8R8A8_UNORM, false)),
+		wl_shm::Format::Xrgb8888 => Some((vk::Format::B8G8R8A8_UNORM, true)),
+		wl_shm::Format::Abgr8888 => Some((vk::Format::R8G8B8A8_UNORM, false)),
+		wl_shm::Format::Xbgr8888 => Some((vk::Format::R8G8B8A8_UNORM, true)),
+		_ => None,
+	}
+}
+
+/// The client memory backing a `wl_shm_pool`.
+///
+/// Buffers keep a reference to it, as they outlive the pool object itself.
+pub struct ShmPool {
+	fd: OwnedFd,
+	map: Mmap,
+	/// Whether the file is sealed against shrinking, so that reading the
+	/// pool cannot fault.
+	sealed: bool,
+}
+
+impl ShmPool {
+	fn new(fd: OwnedFd, size: usize) -> std::io::Result<Self> {
+		let map = Self::map(&fd, size)?;
+		let sealed = rustix::fs::fcntl_get_seals(&fd)
+			.is_ok_and(|seals| seals.contains(rustix::fs::SealFlags::SHRINK));
+		Ok(Self { fd, map, sealed })
+	}
+
+	fn map(fd: &OwnedFd, size: usize) -> std::io::Result<Mmap> {
+		// Refuse mappings larger than the file up front. The client may
+		// still shrink it later, see `ShmBuffer::with_data`.
+		let file_size = rustix::fs::fstat(fd)?.st_size as usize;
+		if file_size < size {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!(
+					"pool of {} bytes exceeds file size {}",
+					size, file_size
+				),
+			));
+		}
+
+		unsafe { MmapOptions::new().len(size).map(fd) }
+	}
+
+	fn resize(&mut self, size: usize) -> std::io::Result<()> {
+		self.map = Self::map(&self.fd, size)?;
+		Ok(())
+	}
+}
+
+/// User data of a `wl_buffer` created from a `wl_shm_pool`.
+pub struct ShmBuffer {
+	pub pool: Arc<Mutex<ShmPool>>,
+	pub offset: usize,
+	pub width: i32,
+	pub height: i32,
+	pub stride: i32,
+	pub format: wl_shm::Format,
+}
+
+impl ShmBuffer {
+	/// Calls `f` with the pixels of the buffer.
+	///
+	/// Fails if the client shrank the file of the pool, in which case `f`
+	/// read zeros instead and the client deserves an `invalid_fd` error.
+	pub fn with_data<T>(
+		&self,
+		f: impl FnOnce(ImageData<'_>) -> T,
+	) -> std::io::Result<T> {
+		let (format, opaque) = vk_format(self.format).unwrap();
+		let pool = self.pool.lock().unwrap();
+		let len = self.stride as usize * self.height as usize;
+
+		let read = || {
+			f(ImageData {
+				data: &pool.map[self.offset..self.offset + len],
+				width: self.width as u32,
+				height: self.height as u32,
+				stride: self.stride as u32,
+				format,
+				opaque,
+			})
+		};
+		if pool.sealed {
+			return Ok(read());
+		}
+
+		match sigbus::guard(&pool.map, read) {
+			(result, false) => Ok(result),
+			(_, true) => Err(std::io::Error::new(
+				std::io::ErrorKind::UnexpectedEof,
+				"pool file shrank while reading the buffer",
+			)),
+		}
+	}
+}
+
+impl GlobalDispatch<WlShm, ()> for ServerState {
+	fn bind(
+		_state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<WlShm>,
+		_global_data: &(),
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		let shm = data_init.init(resource, ());
+		for format in FORMATS {
+			shm.format(*format);
+		}
+	}
+}
+
+impl Dispatch<WlShm, ()> for ServerState {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		resource: &WlShm,
+		request: wl_shm::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wl_shm::Request::CreatePool { id, fd, size } => {
+				if size <= 0 {
+					resource.post_error(
+						wl_shm::Error::InvalidStride,
+						format!("invalid pool size {}", size),
+					);
+					return;
+				}
+
+				match ShmPool::new(fd, size as usize) {
+					Ok(pool) => {
+						data_init.init(id, Arc::new(Mutex::new(pool)));
+					}
+					Err(err) => resource.post_error(
+						wl_shm::Error::InvalidFd,
+						format!("failed to map pool: {}", err),
+					),
+				}
+			}
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<WlShmPool, Arc<Mutex<ShmPool>>> for ServerState {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		resource: &WlShmPool,
+		request: wl_shm_pool::Request,
+		data: &Arc<Mutex<ShmPool>>,
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wl_shm_pool::Request::CreateBuffer {
+				id,
+				offset,
+				width,
+				height,
+				stride,
+				format,
+			} => {
+				let format = match format {
+					WEnum::Value(format) if FORMATS.contains(&format) => format,
+					_ => {
+						resource.post_error(
+							wl_shm::Error::InvalidFormat,
+							format!("unsupported format {:?}", format),
+						);
+						return;
+					}
+				};
+
+				let pool_size = data.lock().unwrap().map.len() as i64;
+				let end = offset as i64 + stride as i64 * height as i64;
+				if offset < 0
+					|| width <= 0 || height <= 0
+					|| (stride as i64) < width as i64 * 4
+					|| end > pool_size
+				{
+					resource.post_error(
+						wl_shm::Error::InvalidStride,
+						format!(
+							"invalid buffer {}x{}, stride {}, offset {}",
+							width, height, stride, offset
+						),
+					);
+					return;
+				}
+
+				data_init.init(
+					id,
+					ShmBuffer {
+						pool: data.clone(),
+						offset: offset as usize,
+						width,
+						height,
+						stride,
+						format,
+					},
+				);
+			}
+			wl_shm_pool::Request::Resize { size } => {
+				let mut pool = data.lock().unwrap();
+				if size < pool.map.len() as i32 {
+					resource.post_error(
+						wl_shm::Error::InvalidStride,
+						"pools can only grow",
+					);
+					return;
+				}
+
+				if let Err(err) = pool.resize(size as usize) {
+					resource.post_error(
+						wl_shm::Error::InvalidFd,
+						format!("failed to remap pool: {}", err),
+					);
+				}
+			}
+			wl_shm_pool::Request::Destroy => {}
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<WlBuffer, ShmBuffer> for ServerState {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &WlBuffer,
+		request: wl_buffer::Request,
+		_data: &ShmBuffer,
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wl_buffer::Request::Destroy => {}
+			_ => unreachable!(),
+		}
+	}
+}
@@ -0,0 +1,83 @@
+//! Survives clients shrinking the file behind a pool while it is read, like
+//! `wl_shm_buffer_begin_access` does in libwayland.
+//!
+//! Reading past the end of the file raises `SIGBUS`. While a pool is being
+//! read, the handler maps zero pages over it instead so the read completes,
+//! and flags the access so the client can be told. `SIGBUS` is delivered to
+//! the faulting thread, so the access is tracked per thread.
+
+use std::{cell::Cell, ffi::c_void, sync::Once};
+
+thread_local! {
+	/// Start and length of the mapping being read, zero when none is.
+	static ACCESS: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+	/// Whether the mapping being read faulted.
+	static FAULTED: Cell<bool> = const { Cell::new(false) };
+}
+
+static INSTALL: Once = Once::new();
+
+/// Calls `f`, which reads the pool mapping `map`, and returns whether it
+/// faulted, in which case it read zeros past the end of the file.
+pub fn guard<T>(map: &[u8], f: impl FnOnce() -> T) -> (T, bool) {
+	INSTALL.call_once(install_handler);
+
+	ACCESS.set((map.as_ptr() as usize, map.len()));
+	FAULTED.set(false);
+
+	let result = f();
+
+	ACCESS.set((0, 0));
+	(result, FAULTED.replace(false))
+}
+
+fn install_handler() {
+	unsafe {
+		let mut action: libc::sigaction = std::mem::zeroed();
+		action.sa_sigaction = handle_sigbus
+			as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut c_void)
+			as usize;
+		action.sa_flags = libc::SA_SIGINFO | libc::SA_NODEFER;
+		libc::sigemptyset(&mut action.sa_mask);
+
+		if libc::sigaction(libc::SIGBUS, &action, std::ptr::null_mut()) != 0 {
+			tracing::error!(
+				"Failed to install the SIGBUS handler: {}",
+				std::io::Error::last_os_error()
+			);
+		}
+	}
+}
+
+extern "C" fn handle_sigbus(
+	_signal: libc::c_int,
+	info: *mut libc::siginfo_t,
+	_context: *mut c_void,
+) {
+	let fault = unsafe { (*info).si_addr() } as usize;
+	let (addr, len) = ACCESS.get();
+
+	if len > 0 && (addr..addr + len).contains(&fault) {
+		FAULTED.set(true);
+
+		let zeros = unsafe {
+			libc::mmap(
+				addr as *mut c_void,
+				len,
+				libc::PROT_READ,
+				libc::MAP_PRIVATE | libc::MAP_FIXED | libc::MAP_ANONYMOUS,
+				-1,
+				0,
+			)
+		};
+		if zeros != libc::MAP_FAILED {
+			return;
+		}
+	}
+
+	// Not a pool read, so restore the default action, which the faulting
+	// instruction triggers again once this returns.
+	unsafe {
+		libc::signal(libc::SIGBUS, libc::SIG_DFL);
+	}
+}
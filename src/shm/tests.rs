@@ -0,0 +1,63 @@
+use std::{
+	os::fd::OwnedFd,
+	sync::{Arc, Mutex},
+};
+
+use rustix::fs::{MemfdFlags, SealFlags};
+use wayland_server::protocol::wl_shm;
+
+use super::{ShmBuffer, ShmPool};
+
+const SIZE: usize = 64 * 64 * 4;
+
+/// A file for a pool, filled with `0xff`.
+fn pool_file(flags: MemfdFlags) -> OwnedFd {
+	let fd = rustix::fs::memfd_create("neora-test", flags).unwrap();
+	rustix::io::write(&fd, &[0xff; SIZE]).unwrap();
+	fd
+}
+
+/// A 64x64 buffer covering a pool of `fd`.
+fn buffer(fd: &OwnedFd) -> ShmBuffer {
+	let pool = ShmPool::new(fd.try_clone().unwrap(), SIZE).unwrap();
+
+	ShmBuffer {
+		pool: Arc::new(Mutex::new(pool)),
+		offset: 0,
+		width: 64,
+		height: 64,
+		stride: 64 * 4,
+		format: wl_shm::Format::Argb8888,
+	}
+}
+
+fn sum(buffer: &ShmBuffer) -> std::io::Result<u64> {
+	buffer.with_data(|image| image.data.iter().map(|b| *b as u64).sum())
+}
+
+#[test]
+fn read_pool() {
+	let fd = pool_file(MemfdFlags::CLOEXEC);
+
+	assert_eq!(sum(&buffer(&fd)).unwrap(), SIZE as u64 * 0xff);
+}
+
+#[test]
+fn shrunk_pool_fails_instead_of_crashing() {
+	let fd = pool_file(MemfdFlags::CLOEXEC);
+	let buffer = buffer(&fd);
+	rustix::fs::ftruncate(&fd, 0).unwrap();
+
+	assert!(sum(&buffer).is_err());
+}
+
+#[test]
+fn sealed_pool_is_not_guarded() {
+	let fd = pool_file(MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING);
+	rustix::fs::fcntl_add_seals(&fd, SealFlags::SHRINK).unwrap();
+	let buffer = buffer(&fd);
+
+	assert!(buffer.pool.lock().unwrap().sealed);
+	assert!(rustix::fs::ftruncate(&fd, 0).is_err());
+	assert_eq!(sum(&buffer).unwrap(), SIZE as u64 * 0xff);
+}
@@ -0,0 +1,219 @@
+use wayland_protocols::xdg::shell::server::xdg_toplevel::ResizeEdge;
+use wayland_server::{backend::ObjectId, Resource as _};
+
+use crate::{
+	geometry::{Point, Rectangle},
+	ServerState,
+};
+
+mod grab;
+#[cfg(test)]
+mod tests;
+
+pub use grab::{resize_cursor, Grab, GrabKind};
+
+/// How keyboard focus follows the pointer.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FocusPolicy {
+	/// Clicking a window focuses and raises it.
+	#[default]
+	ClickToFocus,
+	/// Hovering a window focuses it without raising it, and focus stays
+	/// there when the pointer leaves for the background. Clicking still
+	/// raises.
+	FocusFollowsMouse,
+}
+
+impl FocusPolicy {
+	/// Window to focus when the pointer moves onto `hovered`, the window
+	/// under it if any.
+	pub fn hover_focus(self, hovered: Option<ObjectId>) -> Option<ObjectId> {
+		match self {
+			Self::ClickToFocus => None,
+			Self::FocusFollowsMouse => hovered,
+		}
+	}
+}
+
+/// A mapped toplevel.
+pub struct Window {
+	/// Id of the `wl_surface` of the toplevel.
+	pub surface: ObjectId,
+	/// Global position of the window geometry.
+	pub position: Point,
+	/// Position to return to when leaving the maximized or fullscreen state.
+	pub restore_position: Option<Point>,
+	pub minimized: bool,
+	/// Edges dragged by the ongoing resize and the window geometry when it
+	/// started, to keep the opposite edges in place.
+	pub resizing: Option<(ResizeEdge, Rectangle)>,
+}
+
+/// Mapped toplevels with their placement, stacking order and focus history.
+pub struct Space {
+	/// From bottom to top.
+	pub windows: Vec<Window>,
+	/// Windows in the order they last had keyboard focus, most recent last.
+	pub focus_history: Vec<ObjectId>,
+	pub focus_policy: FocusPolicy,
+	pub grab: Option<Grab>,
+}
+
+impl Space {
+	pub fn new(focus_policy: FocusPolicy) -> Self {
+		Self {
+			windows: Vec::new(),
+			focus_history: Vec::new(),
+			focus_policy,
+			grab: None,
+		}
+	}
+
+	pub fn window(&self, surface: &ObjectId) -> Option<&Window> {
+		self.windows
+			.iter()
+			.find(|window| window.surface == *surface)
+	}
+
+	pub fn window_mut(&mut self, surface: &ObjectId) -> Option<&mut Window> {
+		self.windows
+			.iter_mut()
+			.find(|window| window.surface == *surface)
+	}
+
+	/// Windows that are not minimized, from bottom to top.
+	pub fn visible(&self) -> impl DoubleEndedIterator<Item = &Window> {
+		self.windows.iter().filter(|window| !window.minimized)
+	}
+
+	/// Adds a window on top of the others.
+	pub fn map(&mut self, surface: ObjectId, position: Point) {
+		self.unmap(&surface);
+		self.windows.push(Window {
+			surface,
+			position,
+			restore_position: None,
+			minimized: false,
+			resizing: None,
+		});
+	}
+
+	/// Forgets a window, cancelling any grab of it.
+	pub fn unmap(&mut self, surface: &ObjectId) {
+		if self.grab.as_ref().is_some_and(|g| g.window == *surface) {
+			self.grab = None;
+		}
+		self.focus_history.retain(|id| id != surface);
+		self.windows.retain(|window| window.surface != *surface);
+	}
+
+	/// Moves a window on top of the others.
+	pub fn raise(&mut self, surface: &ObjectId) {
+		if let Some(index) =
+			self.windows.iter().position(|w| w.surface == *surface)
+		{
+			let window = self.windows.remove(index);
+			self.windows.push(window);
+		}
+	}
+
+	/// Records that a window received keyboard focus.
+	pub fn focused(&mut self, surface: &ObjectId) {
+		if self.window(surface).is_none() {
+			return;
+		}
+
+		self.focus_history.retain(|id| id != surface);
+		self.focus_history.push(surface.clone());
+	}
+
+	/// The window to focus when the focused one goes away: the most
+	/// recently focused visible window, or else the topmost one.
+	pub fn next_focus(&self) -> Option<&ObjectId> {
+		let visible = |id: &&ObjectId| {
+			self.window(id).is_some_and(|window| !window.minimized)
+		};
+
+		self.focus_history
+			.iter()
+			.rev()
+			.find(visible)
+			.or_else(|| self.visible().next_back().map(|w| &w.surface))
+	}
+}
+
+impl ServerState {
+	/// The toplevel a surface belongs to, following subsurface and popup
+	/// parents.
+	pub fn window_of(&self, surface: &ObjectId) -> Option<ObjectId> {
+		self.xdg_shell
+			.toplevel_of(&self.subcompositor.root(surface))
+	}
+
+	/// Global window geometry of a mapped toplevel.
+	pub fn window_geometry(&self, id: &ObjectId) -> Option<Rectangle> {
+		let xdg = self.xdg_shell.surfaces.get(id)?;
+		let size = self.compositor.surfaces.get(id)?.size()?;
+
+		Some(Rectangle::from_loc_and_size(
+			self.space.window(id)?.position,
+			xdg.geometry(size).size(),
+		))
+	}
+
+	/// Raises the window `surface` belongs to, restoring it if minimized,
+	/// and gives it keyboard focus.
+	pub fn activate_window(&mut self, surface: &ObjectId) {
+		let Some(toplevel) = self.window_of(surface) else {
+			return;
+		};
+
+		if let Some(window) = self.space.window_mut(&toplevel) {
+			window.minimized = false;
+		}
+		self.space.raise(&toplevel);
+		self.focus_window(&toplevel);
+	}
+
+	/// Gives keyboard focus to the window `surface` belongs to without
+	/// changing the stacking order.
+	pub fn focus_window(&mut self, surface: &ObjectId) {
+		let Some(toplevel) = self.window_of(surface) else {
+			return;
+		};
+
+		let handle = self
+			.compositor
+			.surfaces
+			.get(&toplevel)
+			.map(|surface| surface.handle.clone());
+		self.set_keyboard_focus(handle);
+	}
+
+	/// Gives keyboard focus to the window that had it before, see
+	/// [`Space::next_focus`].
+	pub fn focus_previous_window(&mut self) {
+		let surface = self
+			.space
+			.next_focus()
+			.and_then(|id| self.compositor.surfaces.get(id))
+			.map(|surface| surface.handle.clone());
+
+		self.set_keyboard_focus(surface);
+	}
+
+	/// Hides a window until it is activated again, moving focus away from
+	/// it.
+	pub fn minimize_window(&mut self, surface: &ObjectId) {
+		let Some(window) = self.space.window_mut(surface) else {
+			return;
+		};
+		window.minimized = true;
+
+		let focused = self.seat.keyboard.focus.as_ref().map(|s| s.id());
+		let focused = focused.and_then(|id| self.window_of(&id));
+		if focused.as_ref() == Some(surface) {
+			self.focus_previous_window();
+		}
+	}
+}
@@ -0,0 +1,403 @@
+use wayland_protocols::{
+	wp::cursor_shape::v1::server::wp_cursor_shape_device_v1::Shape,
+	xdg::shell::server::xdg_toplevel::ResizeEdge,
+};
+use wayland_server::{backend::ObjectId, Resource as _};
+
+use crate::{
+	geometry::{Point, Rectangle, Size},
+	seat::CursorImage,
+	xdg_shell::XdgRole,
+	ServerState,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Distance within which a dragged edge sticks to the edge of an output or
+/// of another window.
+const SNAP_DISTANCE: i32 = 12;
+
+/// An interactive move or resize, lasting until the buttons are released.
+pub struct Grab {
+	/// The toplevel being moved or resized.
+	pub window: ObjectId,
+	/// Pointer location when the grab started.
+	pub start: (f64, f64),
+	/// Window geometry when the grab started.
+	pub initial: Rectangle,
+	/// Cursor to restore when the grab ends.
+	pub cursor: CursorImage,
+	pub kind: GrabKind,
+}
+
+pub enum GrabKind {
+	Move,
+	Resize {
+		edges: ResizeEdge,
+		/// Size to configure once the client acknowledges the previous
+		/// configure, so a slow client is not flooded with them.
+		pending: Option<Size>,
+	},
+}
+
+/// Whether `edges` includes `edge`, where both may be corners.
+fn has_edge(edges: ResizeEdge, edge: ResizeEdge) -> bool {
+	edges as u32 & edge as u32 != 0
+}
+
+/// Cursor shown while dragging `edges`.
+pub fn resize_cursor(edges: ResizeEdge) -> Shape {
+	match edges {
+		ResizeEdge::Top => Shape::NResize,
+		ResizeEdge::Bottom => Shape::SResize,
+		ResizeEdge::Left => Shape::WResize,
+		ResizeEdge::Right => Shape::EResize,
+		ResizeEdge::TopLeft => Shape::NwResize,
+		ResizeEdge::TopRight => Shape::NeResize,
+		ResizeEdge::BottomLeft => Shape::SwResize,
+		ResizeEdge::BottomRight => Shape::SeResize,
+		_ => Shape::Default,
+	}
+}
+
+/// Distance to add to one of `edges` to bring it onto the closest of
+/// `targets`, or zero if none is within [`SNAP_DISTANCE`].
+fn snap_offset(edges: &[i32], targets: &[i32]) -> i32 {
+	edges
+		.iter()
+		.flat_map(|edge| targets.iter().map(move |target| target - edge))
+		.filter(|offset| offset.abs() <= SNAP_DISTANCE)
+		.min_by_key(|offset| offset.abs())
+		.unwrap_or(0)
+}
+
+/// Position of the frame `initial` moved by `delta`, with its edges snapped
+/// to the vertical lines `xs` and horizontal lines `ys`.
+fn moved_frame(
+	initial: Rectangle,
+	delta: Point,
+	xs: &[i32],
+	ys: &[i32],
+) -> Point {
+	let mut position = initial.loc() + delta;
+	position.x += snap_offset(&[position.x, position.x + initial.width], xs);
+	position.y += snap_offset(&[position.y, position.y + initial.height], ys);
+	position
+}
+
+/// The frame `initial` with `edges` dragged by `delta`, each snapped to the
+/// vertical lines `xs` or horizontal lines `ys`.
+fn resized_frame(
+	initial: Rectangle,
+	edges: ResizeEdge,
+	delta: Point,
+	xs: &[i32],
+	ys: &[i32],
+) -> Rectangle {
+	let mut left = initial.x;
+	let mut top = initial.y;
+	let mut right = initial.x + initial.width;
+	let mut bottom = initial.y + initial.height;
+
+	if has_edge(edges, ResizeEdge::Left) {
+		left += delta.x;
+		left += snap_offset(&[left], xs);
+	}
+	if has_edge(edges, ResizeEdge::Right) {
+		right += delta.x;
+		right += snap_offset(&[right], xs);
+	}
+	if has_edge(edges, ResizeEdge::Top) {
+		top += delta.y;
+		top += snap_offset(&[top], ys);
+	}
+	if has_edge(edges, ResizeEdge::Bottom) {
+		bottom += delta.y;
+		bottom += snap_offset(&[bottom], ys);
+	}
+
+	Rectangle::new(left, top, right - left, bottom - top)
+}
+
+/// Clamps `size` to the min and max sizes of a toplevel, where zero means
+/// unconstrained.
+fn clamp_size(size: Size, min: Size, max: Size) -> Size {
+	Size::new(
+		clamp_length(size.width, min.width, max.width),
+		clamp_length(size.height, min.height, max.height),
+	)
+}
+
+/// Clamps one dimension to the min and max sizes of a toplevel, where zero
+/// means unconstrained.
+fn clamp_length(length: i32, min: i32, max: i32) -> i32 {
+	let length = if max > 0 { length.min(max) } else { length };
+	length.max(min).max(1)
+}
+
+/// Position of a window that was `initial` when the resize of `edges`
+/// started and now has `size`, keeping the edges opposite to the dragged
+/// ones in place.
+fn anchored_position(
+	position: Point,
+	edges: ResizeEdge,
+	initial: Rectangle,
+	size: Size,
+) -> Point {
+	let mut position = position;
+	if has_edge(edges, ResizeEdge::Left) {
+		position.x = initial.x + initial.width - size.width;
+	}
+	if has_edge(edges, ResizeEdge::Top) {
+		position.y = initial.y + initial.height - size.height;
+	}
+	position
+}
+
+impl ServerState {
+	/// Starts moving `window` with the pointer, if `serial` is the press
+	/// that is still holding the pointer on it.
+	pub fn start_move(&mut self, window: &ObjectId, serial: u32) {
+		if !self.grab_allowed(window, serial) {
+			return;
+		}
+
+		self.start_grab(window, GrabKind::Move, Shape::Move);
+	}
+
+	/// Starts resizing `window` by dragging `edges`, see
+	/// [`ServerState::start_move`].
+	pub fn start_resize(
+		&mut self,
+		window: &ObjectId,
+		serial: u32,
+		edges: ResizeEdge,
+	) {
+		if edges == ResizeEdge::None || !self.grab_allowed(window, serial) {
+			return;
+		}
+
+		let kind = GrabKind::Resize {
+			edges,
+			pending: None,
+		};
+		self.start_grab(window, kind, resize_cursor(edges));
+	}
+
+	/// Whether a grab of `window` may start in response to the button press
+	/// `serial`. Maximized and fullscreen windows stay where they are.
+	fn grab_allowed(&self, window: &ObjectId, serial: u32) -> bool {
+		let pointer = &self.seat.pointer;
+		let pressed =
+			!pointer.buttons.is_empty() && pointer.press_serial == Some(serial);
+		let focused = pointer
+			.focus
+			.as_ref()
+			.and_then(|focus| self.window_of(&focus.surface.id()))
+			.is_some_and(|toplevel| toplevel == *window);
+		let floating = matches!(
+			self.xdg_shell.surfaces.get(window).map(|xdg| &xdg.role),
+			Some(XdgRole::Toplevel(toplevel))
+				if !toplevel.current.maximized && !toplevel.current.fullscreen
+		);
+
+		self.space.grab.is_none() && pressed && focused && floating
+	}
+
+	/// Starts a grab of `window` showing `cursor`, without the checks of a
+	/// client request.
+	pub fn start_grab(
+		&mut self,
+		window: &ObjectId,
+		kind: GrabKind,
+		cursor: Shape,
+	) {
+		let (Some(start), Some(initial)) =
+			(self.seat.pointer.location, self.window_geometry(window))
+		else {
+			return;
+		};
+
+		let cursor = std::mem::replace(
+			&mut self.seat.pointer.cursor,
+			CursorImage::Named(cursor),
+		);
+
+		if let GrabKind::Resize { edges, .. } = kind {
+			if let Some(window) = self.space.window_mut(window) {
+				window.resizing = Some((edges, initial));
+			}
+		}
+
+		self.space.grab = Some(Grab {
+			window: window.clone(),
+			start,
+			initial,
+			cursor,
+			kind,
+		});
+	}
+
+	/// Moves or resizes the grabbed window after the pointer moved to
+	/// `(x, y)`.
+	pub fn grab_motion(&mut self, x: f64, y: f64) {
+		let Some(grab) = &self.space.grab else {
+			return;
+		};
+
+		let delta = Point::new(
+			(x - grab.start.0).round() as i32,
+			(y - grab.start.1).round() as i32,
+		);
+		let (xs, ys) = self.snap_targets(&grab.window);
+		let id = grab.window.clone();
+
+		// Snapping lines up the outer edges of the decorations.
+		let insets = self.decoration_insets(&id);
+		let initial = insets.outset(grab.initial);
+
+		match grab.kind {
+			GrabKind::Move => {
+				let position = moved_frame(initial, delta, &xs, &ys);
+				if let Some(window) = self.space.window_mut(&id) {
+					window.position =
+						position + Point::new(insets.left, insets.top);
+				}
+				self.reposition_popups(&id);
+			}
+			GrabKind::Resize { edges, .. } => {
+				let frame = resized_frame(initial, edges, delta, &xs, &ys);
+				let size = Size::new(
+					frame.width - insets.left - insets.right,
+					frame.height - insets.top - insets.bottom,
+				);
+				self.request_resize(&id, size);
+			}
+		}
+	}
+
+	/// Configures the grabbed window with `size`, or keeps it for later if
+	/// the client has not caught up with the last configure yet.
+	fn request_resize(&mut self, id: &ObjectId, size: Size) {
+		let Some(xdg) = self.xdg_shell.surfaces.get(id) else {
+			return;
+		};
+		let XdgRole::Toplevel(toplevel) = &xdg.role else {
+			return;
+		};
+
+		let size = clamp_size(size, toplevel.min_size, toplevel.max_size);
+
+		if !xdg.pending_configures.is_empty() {
+			if let Some(Grab {
+				kind: GrabKind::Resize { pending, .. },
+				..
+			}) = &mut self.space.grab
+			{
+				*pending = Some(size);
+			}
+			return;
+		}
+
+		if toplevel.requested.size == Some(size) && toplevel.requested.resizing
+		{
+			return;
+		}
+
+		self.update_toplevel(id, |toplevel, _| {
+			toplevel.requested.size = Some(size);
+			toplevel.requested.resizing = true;
+		});
+	}
+
+	/// Sends the size kept by [`ServerState::request_resize`] once the
+	/// client acknowledged every configure of `id`.
+	pub fn flush_resize(&mut self, id: &ObjectId) {
+		let Some(Grab {
+			window,
+			kind: GrabKind::Resize { pending, .. },
+			..
+		}) = &mut self.space.grab
+		else {
+			return;
+		};
+		if window != id {
+			return;
+		}
+
+		if let Some(size) = pending.take() {
+			self.request_resize(&id.clone(), size);
+		}
+	}
+
+	/// Ends the grab, if any, once the buttons are released.
+	pub fn end_grab(&mut self) {
+		let Some(grab) = self.space.grab.take() else {
+			return;
+		};
+
+		self.seat.pointer.cursor = grab.cursor;
+
+		if let GrabKind::Resize { pending, .. } = grab.kind {
+			self.update_toplevel(&grab.window, |toplevel, _| {
+				if let Some(size) = pending {
+					toplevel.requested.size = Some(size);
+				}
+				toplevel.requested.resizing = false;
+			});
+		}
+	}
+
+	/// Keeps the edges opposite to the dragged ones in place when a window
+	/// being resized commits a new size.
+	pub fn anchor_resized_window(&mut self, id: &ObjectId) {
+		let resizing = match self.xdg_shell.surfaces.get(id).map(|x| &x.role) {
+			Some(XdgRole::Toplevel(toplevel)) => toplevel.current.resizing,
+			_ => return,
+		};
+		let Some(size) = self.window_geometry(id).map(|g| g.size()) else {
+			return;
+		};
+		let Some(window) = self.space.window_mut(id) else {
+			return;
+		};
+		let Some((edges, initial)) = window.resizing else {
+			return;
+		};
+
+		window.position =
+			anchored_position(window.position, edges, initial, size);
+
+		// This is the commit for the configure that ended the resize.
+		if !resizing {
+			window.resizing = None;
+		}
+	}
+
+	/// Vertical and horizontal edges that a window other than `except`
+	/// snaps to: those of the outputs and of the frames of the other visible
+	/// windows.
+	fn snap_targets(&self, except: &ObjectId) -> (Vec<i32>, Vec<i32>) {
+		let mut xs = Vec::new();
+		let mut ys = Vec::new();
+
+		for output in &self.outputs {
+			let geometry = output.geometry();
+			xs.extend([geometry.x, geometry.x + geometry.width]);
+			ys.extend([geometry.y, geometry.y + geometry.height]);
+		}
+
+		for window in self.space.visible() {
+			if window.surface == *except {
+				continue;
+			}
+			if let Some(geometry) = self.frame_geometry(&window.surface) {
+				xs.extend([geometry.x, geometry.x + geometry.width]);
+				ys.extend([geometry.y, geometry.y + geometry.height]);
+			}
+		}
+
+		(xs, ys)
+	}
+}
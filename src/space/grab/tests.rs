@@ -0,0 +1,137 @@
+use wayland_protocols::xdg::shell::server::xdg_toplevel::ResizeEdge;
+
+use super::{
+	anchored_position, clamp_size, moved_frame, resized_frame, snap_offset,
+	SNAP_DISTANCE,
+};
+use crate::geometry::{Point, Rectangle, Size};
+
+const FRAME: Rectangle = Rectangle {
+	x: 100,
+	y: 100,
+	width: 200,
+	height: 100,
+};
+
+#[test]
+fn snap_to_closest_target() {
+	assert_eq!(snap_offset(&[100], &[95, 108]), -5);
+	assert_eq!(snap_offset(&[100], &[104, 90]), 4);
+}
+
+#[test]
+fn snap_either_edge() {
+	// The right edge at 300 is closer to its target than the left one.
+	assert_eq!(snap_offset(&[100, 300], &[90, 302]), 2);
+}
+
+#[test]
+fn snap_within_distance_only() {
+	assert_eq!(snap_offset(&[100], &[100 + SNAP_DISTANCE]), SNAP_DISTANCE);
+	assert_eq!(snap_offset(&[100], &[100 - SNAP_DISTANCE - 1]), 0);
+	assert_eq!(snap_offset(&[100], &[]), 0);
+}
+
+#[test]
+fn move_follows_pointer() {
+	assert_eq!(
+		moved_frame(FRAME, Point::new(30, -40), &[], &[]),
+		Point::new(130, 60)
+	);
+}
+
+#[test]
+fn move_snaps_edges() {
+	// The left edge lands at 5 and the bottom one at 795.
+	let position =
+		moved_frame(FRAME, Point::new(-95, 595), &[0, 1000], &[0, 800]);
+
+	assert_eq!(position, Point::new(0, 700));
+}
+
+#[test]
+fn resize_moves_dragged_edges_only() {
+	let delta = Point::new(20, 30);
+
+	assert_eq!(
+		resized_frame(FRAME, ResizeEdge::BottomRight, delta, &[], &[]),
+		Rectangle::new(100, 100, 220, 130)
+	);
+	assert_eq!(
+		resized_frame(FRAME, ResizeEdge::TopLeft, delta, &[], &[]),
+		Rectangle::new(120, 130, 180, 70)
+	);
+	assert_eq!(
+		resized_frame(FRAME, ResizeEdge::Left, delta, &[], &[]),
+		Rectangle::new(120, 100, 180, 100)
+	);
+}
+
+#[test]
+fn resize_snaps_dragged_edges() {
+	let frame = resized_frame(
+		FRAME,
+		ResizeEdge::TopRight,
+		Point::new(95, -95),
+		&[400],
+		&[0],
+	);
+
+	assert_eq!(frame, Rectangle::new(100, 0, 300, 200));
+}
+
+#[test]
+fn clamp_to_min_and_max_size() {
+	let min = Size::new(50, 40);
+	let max = Size::new(300, 200);
+
+	assert_eq!(clamp_size(Size::new(10, 500), min, max), Size::new(50, 200));
+	assert_eq!(clamp_size(Size::new(400, 10), min, max), Size::new(300, 40));
+	assert_eq!(
+		clamp_size(Size::new(100, 100), min, max),
+		Size::new(100, 100)
+	);
+}
+
+#[test]
+fn clamp_without_constraints() {
+	let none = Size::default();
+
+	assert_eq!(
+		clamp_size(Size::new(5000, 3000), none, none),
+		Size::new(5000, 3000)
+	);
+	// Windows keep at least a pixel when dragged inside out.
+	assert_eq!(clamp_size(Size::new(-20, 0), none, none), Size::new(1, 1));
+}
+
+#[test]
+fn anchor_opposite_edges() {
+	let position = FRAME.loc();
+	let size = Size::new(150, 80);
+
+	// Dragging the left or top edge keeps the right or bottom one in place.
+	assert_eq!(
+		anchored_position(position, ResizeEdge::TopLeft, FRAME, size),
+		Point::new(150, 120)
+	);
+	assert_eq!(
+		anchored_position(position, ResizeEdge::Left, FRAME, size),
+		Point::new(150, 100)
+	);
+	assert_eq!(
+		anchored_position(position, ResizeEdge::BottomRight, FRAME, size),
+		position
+	);
+}
+
+#[test]
+fn anchor_follows_clamped_size() {
+	// The client settled on a bigger size than requested, e.g. its minimum.
+	let size = Size::new(250, 100);
+
+	assert_eq!(
+		anchored_position(FRAME.loc(), ResizeEdge::Left, FRAME, size),
+		Point::new(50, 100)
+	);
+}
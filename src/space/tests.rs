@@ -0,0 +1,207 @@
+use std::{os::unix::net::UnixStream, sync::Arc};
+
+use wayland_protocols::wp::cursor_shape::v1::server::wp_cursor_shape_device_v1::Shape;
+use wayland_server::{
+	backend::ObjectId, protocol::wl_callback::WlCallback, Client, DataInit,
+	Dispatch, Display, DisplayHandle, Resource as _,
+};
+
+use super::{FocusPolicy, Grab, GrabKind, Space};
+use crate::{
+	geometry::{Point, Rectangle},
+	seat::CursorImage,
+	ClientState,
+};
+
+struct Objects;
+
+impl Dispatch<WlCallback, ()> for Objects {
+	fn request(
+		_state: &mut Self,
+		_client: &Client,
+		_resource: &WlCallback,
+		_request: <WlCallback as wayland_server::Resource>::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+	}
+}
+
+/// Ids of `count` distinct objects, standing in for toplevel surfaces.
+fn ids(count: usize) -> Vec<ObjectId> {
+	let display = Display::<Objects>::new().unwrap();
+	let (stream, _peer) = UnixStream::pair().unwrap();
+	let client = display
+		.handle()
+		.insert_client(stream, Arc::new(ClientState))
+		.unwrap();
+
+	(0..count)
+		.map(|_| {
+			client
+				.create_resource::<WlCallback, (), Objects>(
+					&display.handle(),
+					1,
+					(),
+				)
+				.unwrap()
+				.id()
+		})
+		.collect()
+}
+
+/// A space with a window for each of `ids`, mapped in order.
+fn space(ids: &[ObjectId]) -> Space {
+	let mut space = Space::new(FocusPolicy::default());
+	for id in ids {
+		space.map(id.clone(), Point::default());
+	}
+	space
+}
+
+fn stacking(space: &Space) -> Vec<ObjectId> {
+	space.windows.iter().map(|w| w.surface.clone()).collect()
+}
+
+#[test]
+fn map_stacks_on_top() {
+	let ids = ids(2);
+	let mut space = space(&ids);
+	assert_eq!(stacking(&space), [ids[0].clone(), ids[1].clone()]);
+
+	space.map(ids[0].clone(), Point::new(10, 10));
+	assert_eq!(stacking(&space), [ids[1].clone(), ids[0].clone()]);
+	assert_eq!(space.window(&ids[0]).unwrap().position, Point::new(10, 10));
+}
+
+#[test]
+fn raise() {
+	let ids = ids(3);
+	let mut space = space(&ids);
+
+	space.raise(&ids[0]);
+	assert_eq!(
+		stacking(&space),
+		[ids[1].clone(), ids[2].clone(), ids[0].clone()]
+	);
+}
+
+#[test]
+fn visible_skips_minimized() {
+	let ids = ids(2);
+	let mut space = space(&ids);
+	space.window_mut(&ids[1]).unwrap().minimized = true;
+
+	let visible = space.visible().map(|w| &w.surface).collect::<Vec<_>>();
+	assert_eq!(visible, [&ids[0]]);
+}
+
+#[test]
+fn focus_history_keeps_most_recent_last() {
+	let ids = ids(3);
+	let mut space = space(&ids);
+
+	space.focused(&ids[0]);
+	space.focused(&ids[1]);
+	space.focused(&ids[0]);
+	assert_eq!(space.focus_history, [ids[1].clone(), ids[0].clone()]);
+	assert_eq!(space.next_focus(), Some(&ids[0]));
+}
+
+#[test]
+fn focus_history_ignores_unmapped_surfaces() {
+	let ids = ids(2);
+	let mut space = space(&ids[..1]);
+
+	space.focused(&ids[1]);
+	assert!(space.focus_history.is_empty());
+}
+
+#[test]
+fn next_focus_skips_minimized() {
+	let ids = ids(3);
+	let mut space = space(&ids);
+	space.focused(&ids[0]);
+	space.focused(&ids[1]);
+
+	space.window_mut(&ids[1]).unwrap().minimized = true;
+	assert_eq!(space.next_focus(), Some(&ids[0]));
+
+	space.window_mut(&ids[0]).unwrap().minimized = true;
+	assert_eq!(space.next_focus(), Some(&ids[2]));
+
+	space.window_mut(&ids[2]).unwrap().minimized = true;
+	assert_eq!(space.next_focus(), None);
+}
+
+#[test]
+fn next_focus_falls_back_to_topmost() {
+	let ids = ids(2);
+	let mut space = space(&ids);
+
+	assert_eq!(space.next_focus(), Some(&ids[1]));
+
+	space.raise(&ids[0]);
+	assert_eq!(space.next_focus(), Some(&ids[0]));
+}
+
+#[test]
+fn unmap_forgets_window() {
+	let ids = ids(2);
+	let mut space = space(&ids);
+	space.focused(&ids[0]);
+	space.focused(&ids[1]);
+	space.grab = Some(Grab {
+		window: ids[1].clone(),
+		start: (0.0, 0.0),
+		initial: Rectangle::default(),
+		cursor: CursorImage::Named(Shape::Default),
+		kind: GrabKind::Move,
+	});
+
+	space.unmap(&ids[1]);
+	assert_eq!(stacking(&space), [ids[0].clone()]);
+	assert_eq!(space.focus_history, [ids[0].clone()]);
+	assert!(space.grab.is_none());
+	assert_eq!(space.next_focus(), Some(&ids[0]));
+}
+
+#[test]
+fn unmap_keeps_grab_of_other_window() {
+	let ids = ids(2);
+	let mut space = space(&ids);
+	space.grab = Some(Grab {
+		window: ids[0].clone(),
+		start: (0.0, 0.0),
+		initial: Rectangle::default(),
+		cursor: CursorImage::Named(Shape::Default),
+		kind: GrabKind::Move,
+	});
+
+	space.unmap(&ids[1]);
+	assert!(space.grab.is_some());
+}
+
+#[test]
+fn click_to_focus_ignores_hover() {
+	let ids = ids(1);
+
+	assert_eq!(
+		FocusPolicy::ClickToFocus.hover_focus(Some(ids[0].clone())),
+		None
+	);
+}
+
+#[test]
+fn focus_follows_mouse() {
+	let ids = ids(1);
+	let policy = FocusPolicy::FocusFollowsMouse;
+
+	assert_eq!(
+		policy.hover_focus(Some(ids[0].clone())),
+		Some(ids[0].clone())
+	);
+	// Focus stays when the pointer leaves for the background.
+	assert_eq!(policy.hover_focus(None), None);
+}
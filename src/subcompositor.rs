@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+
+use wayland_server::{
+	backend::{ClientId, GlobalId, ObjectId},
+	protocol::{
+		wl_subcompositor::{self, WlSubcompositor},
+		wl_subsurface::{self, WlSubsurface},
+		wl_surface::WlSurface,
+	},
+	Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use crate::{compositor::SurfaceRole, geometry::Point, ServerState};
+
+const SUBCOMPOSITOR_VERSION: u32 = 1;
+
+/// State of the `wl_subcompositor` global and every subsurface.
+pub struct Subcompositor {
+	pub global: GlobalId,
+	/// Keyed by the id of the underlying `wl_surface`.
+	pub subsurfaces: HashMap<ObjectId, Subsurface>,
+}
+
+impl Subcompositor {
+	pub fn new(display: &DisplayHandle) -> Self {
+		Self {
+			global: display.create_global::<ServerState, WlSubcompositor, ()>(
+				SUBCOMPOSITOR_VERSION,
+				(),
+			),
+			subsurfaces: HashMap::new(),
+		}
+	}
+
+	/// Whether commits of `surface` are cached until the state of its parent
+	/// is applied, because it or one of its ancestors is in synchronized
+	/// mode.
+	pub fn is_synchronized(&self, surface: &ObjectId) -> bool {
+		self.subsurfaces.get(surface).is_some_and(|subsurface| {
+			subsurface.sync || self.is_synchronized(&subsurface.parent)
+		})
+	}
+
+	/// The surface at the root of the subsurface tree `surface` is in.
+	pub fn root(&self, surface: &ObjectId) -> ObjectId {
+		match self.subsurfaces.get(surface) {
+			Some(subsurface) => self.root(&subsurface.parent),
+			None => surface.clone(),
+		}
+	}
+
+	/// Whether `ancestor` is found by following the parents of `surface`.
+	fn is_ancestor(&self, ancestor: &ObjectId, surface: &ObjectId) -> bool {
+		self.subsurfaces.get(surface).is_some_and(|subsurface| {
+			subsurface.parent == *ancestor
+				|| self.is_ancestor(ancestor, &subsurface.parent)
+		})
+	}
+
+	/// Subsurfaces whose parent is `surface`.
+	pub fn children(&self, surface: &ObjectId) -> Vec<ObjectId> {
+		self.subsurfaces
+			.iter()
+			.filter(|(_, subsurface)| subsurface.parent == *surface)
+			.map(|(id, _)| id.clone())
+			.collect()
+	}
+
+	/// Detaches the subsurfaces of a destroyed parent, which leaves them
+	/// unmapped for good.
+	pub fn orphan_children(&mut self, parent: &ObjectId) {
+		self.subsurfaces
+			.retain(|_, subsurface| subsurface.parent != *parent);
+	}
+}
+
+pub struct Subsurface {
+	pub handle: WlSubsurface,
+	/// Id of the parent `wl_surface`.
+	pub parent: ObjectId,
+	/// Relative to the parent surface.
+	pub position: Point,
+	/// Applied along with the next state of the parent.
+	pub pending_position: Option<Point>,
+	/// Whether in synchronized mode, which is the default.
+	pub sync: bool,
+}
+
+/// Where to restack a subsurface relative to its sibling or parent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Placement {
+	Above,
+	Below,
+}
+
+impl ServerState {
+	/// Applies the pending positions of the subsurfaces of a surface whose
+	/// state was just applied, along with the cached state of the
+	/// synchronized ones.
+	pub fn subsurface_parent_commit(&mut self, parent: &ObjectId) {
+		for id in self.subcompositor.children(parent) {
+			let Some(subsurface) = self.subcompositor.subsurfaces.get_mut(&id)
+			else {
+				continue;
+			};
+			if let Some(position) = subsurface.pending_position.take() {
+				subsurface.position = position;
+			}
+
+			if self.subcompositor.is_synchronized(&id) {
+				self.apply_cached_state(&id);
+			}
+		}
+	}
+
+	/// Forgets the subsurface role of `surface`, which unmaps it right away.
+	pub fn remove_subsurface(&mut self, surface: &ObjectId) {
+		let Some(subsurface) = self.subcompositor.subsurfaces.remove(surface)
+		else {
+			return;
+		};
+
+		let Some(parent) = self.compositor.surfaces.get_mut(&subsurface.parent)
+		else {
+			return;
+		};
+		parent.pending.remove_subsurface(surface);
+		parent.current.remove_subsurface(surface);
+		for queued in &mut parent.queued {
+			queued.remove_subsurface(surface);
+		}
+		if let Some(cached) = &mut parent.cached {
+			cached.remove_subsurface(surface);
+		}
+	}
+
+	/// Moves a subsurface right above or below `sibling` in the pending
+	/// stacking order of its parent.
+	fn place_subsurface(
+		&mut self,
+		resource: &WlSubsurface,
+		surface: &ObjectId,
+		sibling: &ObjectId,
+		placement: Placement,
+	) {
+		let Some(parent) = self
+			.subcompositor
+			.subsurfaces
+			.get(surface)
+			.map(|subsurface| subsurface.parent.clone())
+		else {
+			return;
+		};
+
+		let is_sibling = sibling != surface
+			&& self
+				.subcompositor
+				.subsurfaces
+				.get(sibling)
+				.is_some_and(|other| other.parent == parent);
+		if *sibling != parent && !is_sibling {
+			resource.post_error(
+				wl_subsurface::Error::BadSurface,
+				"reference surface is neither a sibling nor the parent",
+			);
+			return;
+		}
+
+		let Some(state) = self
+			.compositor
+			.surfaces
+			.get_mut(&parent)
+			.map(|parent| &mut parent.pending)
+		else {
+			return;
+		};
+		state.remove_subsurface(surface);
+
+		if *sibling == parent {
+			match placement {
+				Placement::Above => {
+					state.subsurfaces_above.insert(0, surface.clone())
+				}
+				Placement::Below => {
+					state.subsurfaces_below.push(surface.clone())
+				}
+			}
+			return;
+		}
+
+		for list in [&mut state.subsurfaces_below, &mut state.subsurfaces_above]
+		{
+			if let Some(index) = list.iter().position(|id| id == sibling) {
+				let index = match placement {
+					Placement::Above => index + 1,
+					Placement::Below => index,
+				};
+				list.insert(index, surface.clone());
+				return;
+			}
+		}
+	}
+}
+
+impl GlobalDispatch<WlSubcompositor, ()> for ServerState {
+	fn bind(
+		_state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<WlSubcompositor>,
+		_global_data: &(),
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		data_init.init(resource, ());
+	}
+}
+
+impl Dispatch<WlSubcompositor, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &WlSubcompositor,
+		request: wl_subcompositor::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wl_subcompositor::Request::Destroy => {}
+			wl_subcompositor::Request::GetSubsurface {
+				id,
+				surface,
+				parent,
+			} => {
+				let surface_id = surface.id();
+				let parent_id = parent.id();
+
+				if surface_id == parent_id
+					|| state.subcompositor.is_ancestor(&surface_id, &parent_id)
+				{
+					resource.post_error(
+						wl_subcompositor::Error::BadParent,
+						"parent is the surface itself or one of its descendants",
+					);
+					return;
+				}
+
+				let Some(child) =
+					state.compositor.surfaces.get_mut(&surface_id)
+				else {
+					return;
+				};
+				let has_other_role = child
+					.role
+					.is_some_and(|role| role != SurfaceRole::Subsurface);
+				if has_other_role
+					|| state.subcompositor.subsurfaces.contains_key(&surface_id)
+				{
+					resource.post_error(
+						wl_subcompositor::Error::BadSurface,
+						"surface already has another role",
+					);
+					return;
+				}
+				child.role = Some(SurfaceRole::Subsurface);
+
+				// Like the rest of the stacking order, adding a subsurface
+				// takes effect with the next state of the parent.
+				if let Some(parent) =
+					state.compositor.surfaces.get_mut(&parent_id)
+				{
+					parent.pending.subsurfaces_above.push(surface_id.clone());
+				}
+
+				let handle = data_init.init(id, surface);
+				state.subcompositor.subsurfaces.insert(
+					surface_id,
+					Subsurface {
+						handle,
+						parent: parent_id,
+						position: Point::default(),
+						pending_position: None,
+						sync: true,
+					},
+				);
+			}
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<WlSubsurface, WlSurface> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &WlSubsurface,
+		request: wl_subsurface::Request,
+		data: &WlSurface,
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		let id = data.id();
+		// Inert once the surface or its parent is gone.
+		if state
+			.subcompositor
+			.subsurfaces
+			.get(&id)
+			.is_none_or(|subsurface| subsurface.handle != *resource)
+		{
+			return;
+		}
+
+		match request {
+			wl_subsurface::Request::Destroy => {}
+			wl_subsurface::Request::SetPosition { x, y } => {
+				if let Some(subsurface) =
+					state.subcompositor.subsurfaces.get_mut(&id)
+				{
+					subsurface.pending_position = Some(Point::new(x, y));
+				}
+			}
+			wl_subsurface::Request::PlaceAbove { sibling } => {
+				state.place_subsurface(
+					resource,
+					&id,
+					&sibling.id(),
+					Placement::Above,
+				);
+			}
+			wl_subsurface::Request::PlaceBelow { sibling } => {
+				state.place_subsurface(
+					resource,
+					&id,
+					&sibling.id(),
+					Placement::Below,
+				);
+			}
+			wl_subsurface::Request::SetSync => {
+				if let Some(subsurface) =
+					state.subcompositor.subsurfaces.get_mut(&id)
+				{
+					subsurface.sync = true;
+				}
+			}
+			wl_subsurface::Request::SetDesync => {
+				// The cached state stays until the next commit, which
+				// applies it along with the new one.
+				if let Some(subsurface) =
+					state.subcompositor.subsurfaces.get_mut(&id)
+				{
+					subsurface.sync = false;
+				}
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		resource: &WlSubsurface,
+		data: &WlSurface,
+	) {
+		let id = data.id();
+		if state
+			.subcompositor
+			.subsurfaces
+			.get(&id)
+			.is_some_and(|subsurface| subsurface.handle == *resource)
+		{
+			state.remove_subsurface(&id);
+		}
+	}
+}
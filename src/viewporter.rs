@@ -0,0 +1,201 @@
+use wayland_protocols::wp::viewporter::server::{
+	wp_viewport::{self, WpViewport},
+	wp_viewporter::{self, WpViewporter},
+};
+use wayland_server::{
+	backend::{ClientId, GlobalId, ObjectId},
+	protocol::wl_surface::WlSurface,
+	Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+};
+
+use crate::{geometry::Size, ServerState};
+
+const VIEWPORTER_VERSION: u32 = 1;
+
+/// State of the `wp_viewporter` global, through which clients crop and scale
+/// their buffers instead of doing it themselves.
+pub struct Viewporter {
+	pub global: GlobalId,
+}
+
+impl Viewporter {
+	pub fn new(display: &DisplayHandle) -> Self {
+		Self {
+			global: display.create_global::<ServerState, WpViewporter, ()>(
+				VIEWPORTER_VERSION,
+				(),
+			),
+		}
+	}
+}
+
+impl ServerState {
+	/// Checks the viewport of a surface against the state just applied,
+	/// which the protocol only allows once a buffer is involved.
+	pub fn viewport_commit(&mut self, id: &ObjectId) {
+		let Some(surface) = self.compositor.surfaces.get(id) else {
+			return;
+		};
+		let (Some(viewport), Some([x, y, width, height])) =
+			(&surface.viewport, surface.current.source)
+		else {
+			return;
+		};
+
+		if surface.current.destination.is_none()
+			&& (width.fract() != 0.0 || height.fract() != 0.0)
+		{
+			viewport.post_error(
+				wp_viewport::Error::BadSize,
+				"source size is not integer and no destination is set",
+			);
+			return;
+		}
+
+		let Some(size) = surface.buffer_local_size() else {
+			return;
+		};
+		if x + width > size.width as f64 || y + height > size.height as f64 {
+			viewport.post_error(
+				wp_viewport::Error::OutOfBuffer,
+				format!(
+					"source rectangle {}x{}+{}+{} extends outside of the \
+					 {}x{} buffer",
+					width, height, x, y, size.width, size.height
+				),
+			);
+		}
+	}
+}
+
+impl GlobalDispatch<WpViewporter, ()> for ServerState {
+	fn bind(
+		_state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<WpViewporter>,
+		_global_data: &(),
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		data_init.init(resource, ());
+	}
+}
+
+impl Dispatch<WpViewporter, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &WpViewporter,
+		request: wp_viewporter::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			wp_viewporter::Request::Destroy => {}
+			wp_viewporter::Request::GetViewport { id, surface } => {
+				let viewport = data_init.init(id, surface.clone());
+
+				let Some(surface) =
+					state.compositor.surfaces.get_mut(&surface.id())
+				else {
+					return;
+				};
+				if surface.viewport.is_some() {
+					resource.post_error(
+						wp_viewporter::Error::ViewportExists,
+						"the surface already has a viewport",
+					);
+					return;
+				}
+				surface.viewport = Some(viewport);
+			}
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<WpViewport, WlSurface> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &WpViewport,
+		request: wp_viewport::Request,
+		data: &WlSurface,
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		if let wp_viewport::Request::Destroy = request {
+			return;
+		}
+
+		let Some(surface) = state.compositor.surfaces.get_mut(&data.id())
+		else {
+			resource.post_error(
+				wp_viewport::Error::NoSurface,
+				"the surface of the viewport was destroyed",
+			);
+			return;
+		};
+
+		match request {
+			wp_viewport::Request::SetSource {
+				x,
+				y,
+				width,
+				height,
+			} => {
+				if [x, y, width, height] == [-1.0; 4] {
+					surface.pending.source = None;
+				} else if x < 0.0 || y < 0.0 || width <= 0.0 || height <= 0.0 {
+					resource.post_error(
+						wp_viewport::Error::BadValue,
+						format!(
+							"invalid source rectangle {}x{}+{}+{}",
+							width, height, x, y
+						),
+					);
+				} else {
+					surface.pending.source = Some([x, y, width, height]);
+				}
+			}
+			wp_viewport::Request::SetDestination { width, height } => {
+				if (width, height) == (-1, -1) {
+					surface.pending.destination = None;
+				} else if width <= 0 || height <= 0 {
+					resource.post_error(
+						wp_viewport::Error::BadValue,
+						format!(
+							"invalid destination size {}x{}",
+							width, height
+						),
+					);
+				} else {
+					surface.pending.destination =
+						Some(Size::new(width, height));
+				}
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		resource: &WpViewport,
+		data: &WlSurface,
+	) {
+		let Some(surface) = state.compositor.surfaces.get_mut(&data.id())
+		else {
+			return;
+		};
+		if surface.viewport.as_ref() != Some(resource) {
+			return;
+		}
+
+		// The surface goes back to its buffer size on the next commit.
+		surface.viewport = None;
+		surface.pending.source = None;
+		surface.pending.destination = None;
+	}
+}
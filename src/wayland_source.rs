@@ -0,0 +1,81 @@
+use std::{
+	io,
+	os::fd::{BorrowedFd, OwnedFd},
+	sync::mpsc,
+	thread,
+};
+
+use rustix::event::{poll, PollFd, PollFlags};
+use winit::event_loop::EventLoopProxy;
+
+use crate::UserEvent;
+
+/// Wakes the winit event loop whenever one of the Wayland file descriptors
+/// (the listening socket or the display backend) becomes readable, or a
+/// dmabuf that commits wait for.
+pub struct WaylandSource {
+	resume: mpsc::Sender<Vec<OwnedFd>>,
+}
+
+impl WaylandSource {
+	/// Spawns the polling thread. After each [`UserEvent::Wayland`] the thread
+	/// waits for [`WaylandSource::resume`] before polling again, so it does not
+	/// spin on descriptors that are still readable but not yet dispatched.
+	pub fn spawn(
+		proxy: EventLoopProxy<UserEvent>,
+		fds: Vec<OwnedFd>,
+	) -> io::Result<Self> {
+		let (resume, resumed) = mpsc::channel();
+
+		thread::Builder::new().name("wayland-source".into()).spawn(
+			move || {
+				let mut fences = Vec::new();
+				loop {
+					let mut poll_fds = fds
+						.iter()
+						.chain(&fences)
+						.map(|fd| PollFd::new(fd, PollFlags::IN))
+						.collect::<Vec<_>>();
+
+					match poll(&mut poll_fds, -1) {
+						Ok(_) => {}
+						Err(rustix::io::Errno::INTR) => continue,
+						Err(err) => {
+							tracing::error!(
+								"Failed to poll Wayland sources: {}",
+								err
+							);
+							return;
+						}
+					}
+
+					if proxy.send_event(UserEvent::Wayland).is_err() {
+						return;
+					}
+					match resumed.recv() {
+						Ok(next) => fences = next,
+						Err(_) => return,
+					}
+				}
+			},
+		)?;
+
+		Ok(Self { resume })
+	}
+
+	/// Lets the polling thread continue once pending requests were
+	/// dispatched, also waking up once one of `fences` becomes readable.
+	pub fn resume(&self, fences: &[BorrowedFd<'_>]) {
+		let fences = fences
+			.iter()
+			.filter_map(|fd| {
+				fd.try_clone_to_owned()
+					.inspect_err(|err| {
+						tracing::error!("Failed to duplicate fence: {}", err)
+					})
+					.ok()
+			})
+			.collect();
+		let _ = self.resume.send(fences);
+	}
+}
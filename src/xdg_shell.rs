@@ -0,0 +1,1240 @@
+use std::collections::HashMap;
+
+use wayland_protocols::xdg::{
+	decoration::zv1::server::zxdg_toplevel_decoration_v1::{
+		self, Mode, ZxdgToplevelDecorationV1,
+	},
+	shell::server::{
+		xdg_popup::{self, XdgPopup},
+		xdg_positioner::{self, XdgPositioner},
+		xdg_surface::{self, XdgSurface as XdgSurfaceResource},
+		xdg_toplevel::{self, XdgToplevel},
+		xdg_wm_base::{self, XdgWmBase},
+	},
+};
+use wayland_server::{
+	backend::{ClientId, GlobalId, ObjectId},
+	protocol::wl_surface::WlSurface,
+	Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+	WEnum,
+};
+
+use crate::{
+	compositor::SurfaceRole,
+	decoration::Insets,
+	geometry::{Point, Rectangle, Size},
+	space::Window,
+	ServerState,
+};
+
+mod grab;
+mod positioner;
+
+pub use positioner::Positioner;
+
+const XDG_WM_BASE_VERSION: u32 = 6;
+
+/// State of the `xdg_wm_base` global and every xdg surface.
+pub struct XdgShell {
+	pub global: GlobalId,
+	/// Keyed by the id of the underlying `wl_surface`.
+	pub surfaces: HashMap<ObjectId, XdgSurface>,
+	pub positioners: HashMap<ObjectId, Positioner>,
+	/// Popups holding an explicit grab, from the first one to the topmost.
+	pub popup_grab: Vec<ObjectId>,
+}
+
+impl XdgShell {
+	pub fn new(display: &DisplayHandle) -> Self {
+		Self {
+			global: display.create_global::<ServerState, XdgWmBase, ()>(
+				XDG_WM_BASE_VERSION,
+				(),
+			),
+			surfaces: HashMap::new(),
+			positioners: HashMap::new(),
+			popup_grab: Vec::new(),
+		}
+	}
+
+	/// Forgets everything about a destroyed `wl_surface`.
+	pub fn remove_surface(&mut self, surface: &ObjectId) {
+		self.surfaces.remove(surface);
+		self.popup_grab.retain(|popup| popup != surface);
+	}
+
+	/// The toplevel `surface` belongs to, following popup parents.
+	pub fn toplevel_of(&self, surface: &ObjectId) -> Option<ObjectId> {
+		match &self.surfaces.get(surface)?.role {
+			XdgRole::Toplevel(_) => Some(surface.clone()),
+			XdgRole::Popup(popup) => self.toplevel_of(&popup.parent.id()),
+			XdgRole::None => None,
+		}
+	}
+
+	/// Popups whose parent is `surface`, in creation order.
+	pub fn popups(&self, surface: &ObjectId) -> Vec<ObjectId> {
+		let mut popups = self
+			.surfaces
+			.iter()
+			.filter(|(_, xdg)| match &xdg.role {
+				XdgRole::Popup(popup) => popup.parent.id() == *surface,
+				_ => false,
+			})
+			.map(|(id, _)| id.clone())
+			.collect::<Vec<_>>();
+
+		popups.sort_by_key(|id| id.protocol_id());
+		popups
+	}
+}
+
+/// User data of an `xdg_surface`.
+pub struct XdgSurfaceData {
+	pub surface: WlSurface,
+}
+
+pub struct XdgSurface {
+	pub handle: XdgSurfaceResource,
+	pub wm_base: XdgWmBase,
+	pub role: XdgRole,
+	pub pending_geometry: Option<Rectangle>,
+	/// Window geometry in surface-local coordinates, `None` for the whole
+	/// surface.
+	pub geometry: Option<Rectangle>,
+	/// Configures sent but not acknowledged yet, oldest first.
+	pub pending_configures: Vec<(u32, Configure)>,
+	/// Whether the initial configure was sent since the surface was last
+	/// unmapped.
+	pub configure_sent: bool,
+	/// Whether a configure was acknowledged since then.
+	pub configured: bool,
+	pub mapped: bool,
+}
+
+impl XdgSurface {
+	/// The window geometry, defaulting to the bounds of a surface of `size`.
+	pub fn geometry(&self, size: Size) -> Rectangle {
+		self.geometry
+			.unwrap_or(Rectangle::from_loc_and_size(Point::default(), size))
+	}
+}
+
+pub enum XdgRole {
+	None,
+	Toplevel(Toplevel),
+	Popup(Popup),
+}
+
+/// The state sent along with an `xdg_surface.configure`.
+#[derive(Copy, Clone, Debug)]
+pub enum Configure {
+	Toplevel(ToplevelState),
+	Popup(Rectangle),
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ToplevelState {
+	/// `None` lets the client pick its size.
+	pub size: Option<Size>,
+	pub maximized: bool,
+	pub fullscreen: bool,
+	pub activated: bool,
+	pub resizing: bool,
+	/// Whether the compositor draws the title bar and borders.
+	pub decorated: bool,
+}
+
+impl ToplevelState {
+	/// The states array of `xdg_toplevel.configure`.
+	fn states(&self) -> Vec<u8> {
+		[
+			(self.maximized, xdg_toplevel::State::Maximized),
+			(self.fullscreen, xdg_toplevel::State::Fullscreen),
+			(self.activated, xdg_toplevel::State::Activated),
+			(self.resizing, xdg_toplevel::State::Resizing),
+		]
+		.into_iter()
+		.filter(|(set, _)| *set)
+		.flat_map(|(_, state)| (state as u32).to_ne_bytes())
+		.collect()
+	}
+}
+
+pub struct Toplevel {
+	pub handle: XdgToplevel,
+	pub title: Option<String>,
+	pub app_id: Option<String>,
+	pub parent: Option<WlSurface>,
+	pub pending_min_size: Size,
+	pub pending_max_size: Size,
+	/// Zero means unconstrained.
+	pub min_size: Size,
+	pub max_size: Size,
+	/// State of the last configure sent.
+	pub requested: ToplevelState,
+	/// State last acknowledged by the client.
+	pub current: ToplevelState,
+	pub decoration: Option<ZxdgToplevelDecorationV1>,
+	/// Index of the output the toplevel is made fullscreen on, while it is
+	/// requested to be.
+	pub fullscreen_output: Option<usize>,
+}
+
+pub struct Popup {
+	pub handle: XdgPopup,
+	pub parent: WlSurface,
+	pub positioner: Positioner,
+	/// Relative to the window geometry of the parent.
+	pub geometry: Rectangle,
+	/// Whether the popup took an explicit grab, even if it ended since.
+	pub grab: bool,
+	/// Whether the positioner was made for a state the parent did not
+	/// commit yet, in which case the popup is repositioned once it does.
+	pub awaiting_parent: bool,
+}
+
+impl ServerState {
+	/// Handles a commit of the `wl_surface` of an xdg surface: sends the
+	/// initial configure, then maps or unmaps it as buffers come and go.
+	pub fn xdg_commit(&mut self, id: &ObjectId) {
+		let Some(surface) = self.compositor.surfaces.get(id) else {
+			return;
+		};
+		let has_buffer = surface.texture.is_some();
+
+		let Some(xdg) = self.xdg_shell.surfaces.get_mut(id) else {
+			return;
+		};
+
+		if matches!(xdg.role, XdgRole::None) {
+			return;
+		}
+
+		xdg.geometry = xdg.pending_geometry;
+		if let XdgRole::Toplevel(toplevel) = &mut xdg.role {
+			toplevel.min_size = toplevel.pending_min_size;
+			toplevel.max_size = toplevel.pending_max_size;
+		}
+
+		if !xdg.configured && has_buffer {
+			xdg.handle.post_error(
+				xdg_surface::Error::UnconfiguredBuffer,
+				"buffer attached before the first configure was acked",
+			);
+			return;
+		}
+
+		if !xdg.configure_sent {
+			self.send_xdg_configure(id);
+		} else if has_buffer && !xdg.mapped {
+			self.map_xdg_surface(id);
+		} else if !has_buffer && xdg.mapped {
+			self.unmap_xdg_surface(id);
+		} else if xdg.mapped {
+			self.anchor_resized_window(id);
+		}
+
+		if self
+			.xdg_shell
+			.surfaces
+			.get(id)
+			.is_some_and(|xdg| xdg.mapped)
+		{
+			self.reposition_popups(id);
+		}
+	}
+
+	/// Sends a configure with the current compositor-side state of the role.
+	pub fn send_xdg_configure(&mut self, id: &ObjectId) {
+		let serial = self.next_serial();
+		let output = self.output_geometry(id);
+
+		let Some(xdg) = self.xdg_shell.surfaces.get_mut(id) else {
+			return;
+		};
+
+		let configure = match &xdg.role {
+			XdgRole::Toplevel(toplevel) => {
+				let state = toplevel.requested;
+				let size = state.size.unwrap_or_default();
+
+				if let Some(decoration) = &toplevel.decoration {
+					decoration.configure(if state.decorated {
+						Mode::ServerSide
+					} else {
+						Mode::ClientSide
+					});
+				}
+				if toplevel.handle.version() >= 4 {
+					toplevel
+						.handle
+						.configure_bounds(output.width, output.height);
+				}
+				toplevel.handle.configure(
+					size.width,
+					size.height,
+					state.states(),
+				);
+
+				Configure::Toplevel(state)
+			}
+			XdgRole::Popup(popup) => {
+				let geometry = popup.geometry;
+				popup.handle.configure(
+					geometry.x,
+					geometry.y,
+					geometry.width,
+					geometry.height,
+				);
+
+				Configure::Popup(geometry)
+			}
+			XdgRole::None => return,
+		};
+
+		xdg.handle.configure(serial);
+		xdg.pending_configures.push((serial, configure));
+		xdg.configure_sent = true;
+	}
+
+	/// Global position of the window geometry of an xdg surface.
+	pub fn window_origin(&self, id: &ObjectId) -> Option<Point> {
+		match &self.xdg_shell.surfaces.get(id)?.role {
+			XdgRole::Toplevel(_) => Some(self.space.window(id)?.position),
+			XdgRole::Popup(popup) => Some(
+				self.window_origin(&popup.parent.id())? + popup.geometry.loc(),
+			),
+			XdgRole::None => None,
+		}
+	}
+
+	/// Global position of the `wl_surface` of an xdg surface, which differs
+	/// from [`ServerState::window_origin`] by the window geometry offset.
+	pub fn xdg_surface_origin(&self, id: &ObjectId) -> Option<Point> {
+		let xdg = self.xdg_shell.surfaces.get(id)?;
+		let size = self.compositor.surfaces.get(id)?.size()?;
+
+		Some(self.window_origin(id)? - xdg.geometry(size).loc())
+	}
+
+	fn map_xdg_surface(&mut self, id: &ObjectId) {
+		let output = self.output_geometry(id);
+		let size = self
+			.compositor
+			.surfaces
+			.get(id)
+			.and_then(|surface| surface.size())
+			.unwrap_or_default();
+
+		let Some(xdg) = self.xdg_shell.surfaces.get_mut(id) else {
+			return;
+		};
+
+		xdg.mapped = true;
+
+		if let XdgRole::Toplevel(toplevel) = &xdg.role {
+			let geometry = xdg.geometry.unwrap_or(
+				Rectangle::from_loc_and_size(Point::default(), size),
+			);
+
+			let state = toplevel.current;
+			let position = if state.fullscreen {
+				output.loc()
+			} else if state.maximized {
+				Insets::maximized_area(output, state.decorated).loc()
+			} else {
+				// Center the window along with its decorations.
+				let frame = Insets::for_state(state).outset(
+					Rectangle::from_loc_and_size(
+						Point::default(),
+						geometry.size(),
+					),
+				);
+				Point::new(
+					output.x + (output.width - frame.width) / 2 - frame.x,
+					output.y + (output.height - frame.height) / 2 - frame.y,
+				)
+			};
+
+			// New windows go on top and take focus.
+			self.space.map(id.clone(), position);
+			self.focus_window(id);
+		} else if self.xdg_shell.popup_grab.last() == Some(id) {
+			// The topmost grabbing popup takes keyboard focus.
+			let handle = self
+				.compositor
+				.surfaces
+				.get(id)
+				.map(|surface| surface.handle.clone());
+			self.set_keyboard_focus(handle);
+		}
+	}
+
+	fn unmap_xdg_surface(&mut self, id: &ObjectId) {
+		// The popup grab ends with the popup, or with the surface it was
+		// taken from.
+		let xdg_shell = &self.xdg_shell;
+		let grab = xdg_shell.popup_grab.iter().position(|popup| {
+			popup == id
+				|| matches!(
+					xdg_shell.surfaces.get(popup).map(|xdg| &xdg.role),
+					Some(XdgRole::Popup(popup)) if popup.parent.id() == *id
+				)
+		});
+		let mut dismissed = match grab {
+			Some(start) => self.xdg_shell.popup_grab.split_off(start),
+			None => Vec::new(),
+		};
+		dismissed.reverse();
+		for popup in self.xdg_shell.popups(id) {
+			if !dismissed.contains(&popup) {
+				dismissed.push(popup);
+			}
+		}
+
+		for popup in dismissed.iter().filter(|popup| *popup != id) {
+			if let Some(XdgRole::Popup(popup)) =
+				self.xdg_shell.surfaces.get(popup).map(|xdg| &xdg.role)
+			{
+				popup.handle.popup_done();
+			}
+		}
+
+		self.space.unmap(id);
+
+		if self.seat.keyboard.focus.as_ref().map(|s| s.id()).as_ref()
+			== Some(id)
+		{
+			match self.xdg_shell.surfaces.get(id).map(|xdg| &xdg.role) {
+				// Focus goes back to the popup below in the grab, or else
+				// to the window.
+				Some(XdgRole::Popup(popup)) => {
+					let parent = popup.parent.id();
+					let below = self
+						.xdg_shell
+						.popup_grab
+						.last()
+						.filter(|below| **below == parent)
+						.and_then(|below| self.compositor.surfaces.get(below))
+						.map(|surface| surface.handle.clone());
+					match below {
+						Some(below) => self.set_keyboard_focus(Some(below)),
+						None => self.focus_window(&parent),
+					}
+				}
+				_ => self.focus_previous_window(),
+			}
+		}
+
+		if let Some(xdg) = self.xdg_shell.surfaces.get_mut(id) {
+			// An unmapped surface has to redo the initial commit.
+			xdg.mapped = false;
+			xdg.configure_sent = false;
+			xdg.configured = false;
+			xdg.pending_configures.clear();
+		}
+	}
+
+	/// Maximizes or restores the toplevel `id`. Maximized windows fill the
+	/// output below their title bar.
+	pub fn set_maximized(&mut self, id: &ObjectId, maximized: bool) {
+		self.update_toplevel(id, |toplevel, output| {
+			toplevel.requested.maximized = maximized;
+			if toplevel.requested.fullscreen {
+				return;
+			}
+
+			toplevel.requested.size = maximized.then(|| {
+				Insets::maximized_area(output, toplevel.requested.decorated)
+					.size()
+			});
+		});
+	}
+
+	/// Applies a change of the requested toplevel state, sending a configure
+	/// if the client already went through the initial commit.
+	pub fn update_toplevel(
+		&mut self,
+		id: &ObjectId,
+		f: impl FnOnce(&mut Toplevel, Rectangle),
+	) {
+		let output = self.output_geometry(id);
+
+		let Some(xdg) = self.xdg_shell.surfaces.get_mut(id) else {
+			return;
+		};
+		let XdgRole::Toplevel(toplevel) = &mut xdg.role else {
+			return;
+		};
+
+		f(toplevel, output);
+
+		if xdg.configure_sent {
+			self.send_xdg_configure(id);
+		}
+	}
+}
+
+/// Moves a toplevel in or out of the maximized or fullscreen position
+/// according to the newly acknowledged state. Unmapped toplevels are placed when they are mapped.
+fn apply_toplevel_state(
+	toplevel: &mut Toplevel,
+	window: Option<&mut Window>,
+	state: ToplevelState,
+	output: Rectangle,
+) {
+	let covers_output =
+		|state: ToplevelState| state.maximized || state.fullscreen;
+
+	if let Some(window) = window {
+		if covers_output(state) {
+			if !covers_output(toplevel.current) {
+				window.restore_position = Some(window.position);
+			}
+			window.position = if state.fullscreen {
+				output.loc()
+			} else {
+				Insets::maximized_area(output, state.decorated).loc()
+			};
+		} else if !covers_output(state) && covers_output(toplevel.current) {
+			if let Some(position) = window.restore_position.take() {
+				window.position = position;
+			}
+		}
+	}
+
+	toplevel.current = state;
+}
+
+impl GlobalDispatch<XdgWmBase, ()> for ServerState {
+	fn bind(
+		_state: &mut Self,
+		_handle: &DisplayHandle,
+		_client: &Client,
+		resource: New<XdgWmBase>,
+		_global_data: &(),
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		data_init.init(resource, ());
+	}
+}
+
+impl Dispatch<XdgWmBase, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &XdgWmBase,
+		request: xdg_wm_base::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		match request {
+			xdg_wm_base::Request::Destroy => {
+				if state
+					.xdg_shell
+					.surfaces
+					.values()
+					.any(|xdg| xdg.wm_base == *resource)
+				{
+					resource.post_error(
+						xdg_wm_base::Error::DefunctSurfaces,
+						"xdg_wm_base destroyed before its surfaces",
+					);
+				}
+			}
+			xdg_wm_base::Request::CreatePositioner { id } => {
+				let positioner = data_init.init(id, ());
+				state
+					.xdg_shell
+					.positioners
+					.insert(positioner.id(), Positioner::default());
+			}
+			xdg_wm_base::Request::GetXdgSurface { id, surface } => {
+				let Some(wl_surface) =
+					state.compositor.surfaces.get(&surface.id())
+				else {
+					return;
+				};
+
+				if !matches!(
+					wl_surface.role,
+					None | Some(SurfaceRole::XdgToplevel)
+						| Some(SurfaceRole::XdgPopup)
+				) {
+					resource.post_error(
+						xdg_wm_base::Error::Role,
+						"surface already has a non-xdg role",
+					);
+					return;
+				}
+
+				if wl_surface.texture.is_some()
+					|| wl_surface.pending.buffer.is_some()
+				{
+					resource.post_error(
+						xdg_wm_base::Error::InvalidSurfaceState,
+						"surface already has a buffer",
+					);
+					return;
+				}
+
+				if state.xdg_shell.surfaces.contains_key(&surface.id()) {
+					resource.post_error(
+						xdg_wm_base::Error::Role,
+						"surface already has an xdg_surface",
+					);
+					return;
+				}
+
+				let handle = data_init.init(
+					id,
+					XdgSurfaceData {
+						surface: surface.clone(),
+					},
+				);
+				state.xdg_shell.surfaces.insert(
+					surface.id(),
+					XdgSurface {
+						handle,
+						wm_base: resource.clone(),
+						role: XdgRole::None,
+						pending_geometry: None,
+						geometry: None,
+						pending_configures: Vec::new(),
+						configure_sent: false,
+						configured: false,
+						mapped: false,
+					},
+				);
+			}
+			xdg_wm_base::Request::Pong { .. } => {}
+			_ => unreachable!(),
+		}
+	}
+}
+
+impl Dispatch<XdgPositioner, ()> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &XdgPositioner,
+		request: xdg_positioner::Request,
+		_data: &(),
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		let Some(positioner) =
+			state.xdg_shell.positioners.get_mut(&resource.id())
+		else {
+			return;
+		};
+
+		let invalid_input = |message: String| {
+			resource.post_error(xdg_positioner::Error::InvalidInput, message)
+		};
+
+		match request {
+			xdg_positioner::Request::Destroy => {}
+			xdg_positioner::Request::SetSize { width, height } => {
+				if width <= 0 || height <= 0 {
+					return invalid_input(format!(
+						"invalid size {}x{}",
+						width, height
+					));
+				}
+
+				positioner.size = Size::new(width, height);
+			}
+			xdg_positioner::Request::SetAnchorRect {
+				x,
+				y,
+				width,
+				height,
+			} => {
+				if width < 0 || height < 0 {
+					return invalid_input(format!(
+						"invalid anchor rect size {}x{}",
+						width, height
+					));
+				}
+
+				positioner.anchor_rect = Rectangle::new(x, y, width, height);
+				positioner.anchor_rect_set = true;
+			}
+			xdg_positioner::Request::SetAnchor { anchor } => match anchor {
+				WEnum::Value(anchor) => positioner.anchor = anchor,
+				WEnum::Unknown(value) => {
+					invalid_input(format!("invalid anchor {}", value))
+				}
+			},
+			xdg_positioner::Request::SetGravity { gravity } => match gravity {
+				WEnum::Value(gravity) => positioner.gravity = gravity,
+				WEnum::Unknown(value) => {
+					invalid_input(format!("invalid gravity {}", value))
+				}
+			},
+			xdg_positioner::Request::SetConstraintAdjustment {
+				constraint_adjustment,
+			} => {
+				positioner.constraint_adjustment =
+					xdg_positioner::ConstraintAdjustment::from_bits_truncate(
+						constraint_adjustment.into(),
+					);
+			}
+			xdg_positioner::Request::SetOffset { x, y } => {
+				positioner.offset = Point::new(x, y);
+			}
+			xdg_positioner::Request::SetReactive => {
+				positioner.reactive = true;
+			}
+			xdg_positioner::Request::SetParentSize {
+				parent_width,
+				parent_height,
+			} => {
+				positioner.parent_size =
+					Some(Size::new(parent_width, parent_height));
+			}
+			xdg_positioner::Request::SetParentConfigure { serial } => {
+				positioner.parent_configure = Some(serial);
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		resource: &XdgPositioner,
+		_data: &(),
+	) {
+		state.xdg_shell.positioners.remove(&resource.id());
+	}
+}
+
+impl Dispatch<XdgSurfaceResource, XdgSurfaceData> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &XdgSurfaceResource,
+		request: xdg_surface::Request,
+		data: &XdgSurfaceData,
+		_dhandle: &DisplayHandle,
+		data_init: &mut DataInit<'_, Self>,
+	) {
+		let id = data.surface.id();
+		let output = state.output_geometry(&id);
+		let Some(xdg) = state.xdg_shell.surfaces.get_mut(&id) else {
+			return;
+		};
+
+		match request {
+			xdg_surface::Request::Destroy => {
+				if !matches!(xdg.role, XdgRole::None) {
+					resource.post_error(
+						xdg_surface::Error::DefunctRoleObject,
+						"xdg_surface destroyed before its role object",
+					);
+				}
+			}
+			xdg_surface::Request::GetToplevel { id: toplevel } => {
+				if !matches!(xdg.role, XdgRole::None) {
+					resource.post_error(
+						xdg_surface::Error::AlreadyConstructed,
+						"xdg_surface already has a role object",
+					);
+					return;
+				}
+
+				let Some(surface) = state.compositor.surfaces.get_mut(&id)
+				else {
+					return;
+				};
+				if surface.role == Some(SurfaceRole::XdgPopup) {
+					xdg.wm_base.post_error(
+						xdg_wm_base::Error::Role,
+						"surface was a popup",
+					);
+					return;
+				}
+				surface.role = Some(SurfaceRole::XdgToplevel);
+
+				let handle = data_init.init(toplevel, data.surface.clone());
+				if handle.version() >= 5 {
+					let capabilities = [
+						xdg_toplevel::WmCapabilities::Maximize,
+						xdg_toplevel::WmCapabilities::Fullscreen,
+						xdg_toplevel::WmCapabilities::Minimize,
+					]
+					.into_iter()
+					.flat_map(|c| (c as u32).to_ne_bytes())
+					.collect();
+					handle.wm_capabilities(capabilities);
+				}
+
+				xdg.role = XdgRole::Toplevel(Toplevel {
+					handle,
+					title: None,
+					app_id: None,
+					parent: None,
+					pending_min_size: Size::default(),
+					pending_max_size: Size::default(),
+					min_size: Size::default(),
+					max_size: Size::default(),
+					requested: ToplevelState::default(),
+					current: ToplevelState::default(),
+					decoration: None,
+					fullscreen_output: None,
+				});
+			}
+			xdg_surface::Request::GetPopup {
+				id: popup,
+				parent,
+				positioner,
+			} => {
+				if !matches!(xdg.role, XdgRole::None) {
+					resource.post_error(
+						xdg_surface::Error::AlreadyConstructed,
+						"xdg_surface already has a role object",
+					);
+					return;
+				}
+
+				let Some(parent) = parent
+					.as_ref()
+					.and_then(|p| p.data::<XdgSurfaceData>())
+					.map(|p| p.surface.clone())
+				else {
+					xdg.wm_base.post_error(
+						xdg_wm_base::Error::InvalidPopupParent,
+						"popups without a parent are not supported",
+					);
+					return;
+				};
+
+				let Some(positioner) = state
+					.xdg_shell
+					.positioners
+					.get(&positioner.id())
+					.copied()
+					.filter(Positioner::is_complete)
+				else {
+					xdg.wm_base.post_error(
+						xdg_wm_base::Error::InvalidPositioner,
+						"incomplete positioner",
+					);
+					return;
+				};
+
+				let Some(surface) = state.compositor.surfaces.get_mut(&id)
+				else {
+					return;
+				};
+				if surface.role == Some(SurfaceRole::XdgToplevel) {
+					xdg.wm_base.post_error(
+						xdg_wm_base::Error::Role,
+						"surface was a toplevel",
+					);
+					return;
+				}
+				surface.role = Some(SurfaceRole::XdgPopup);
+
+				let handle = data_init.init(popup, data.surface.clone());
+				let geometry = state.popup_geometry(&parent, &positioner);
+				let awaiting_parent = state.awaits_parent(&parent, &positioner);
+
+				let Some(xdg) = state.xdg_shell.surfaces.get_mut(&id) else {
+					return;
+				};
+				xdg.role = XdgRole::Popup(Popup {
+					handle,
+					parent,
+					positioner,
+					geometry,
+					grab: false,
+					awaiting_parent,
+				});
+			}
+			xdg_surface::Request::SetWindowGeometry {
+				x,
+				y,
+				width,
+				height,
+			} => {
+				if width <= 0 || height <= 0 {
+					resource.post_error(
+						xdg_surface::Error::InvalidSize,
+						format!("invalid window geometry {}x{}", width, height),
+					);
+					return;
+				}
+
+				xdg.pending_geometry =
+					Some(Rectangle::new(x, y, width, height));
+			}
+			xdg_surface::Request::AckConfigure { serial } => {
+				let Some(index) = xdg
+					.pending_configures
+					.iter()
+					.position(|(pending, _)| *pending == serial)
+				else {
+					resource.post_error(
+						xdg_surface::Error::InvalidSerial,
+						format!("unknown configure serial {}", serial),
+					);
+					return;
+				};
+
+				// Acking a configure implicitly acks all older ones.
+				let (_, configure) =
+					xdg.pending_configures.drain(..=index).next_back().unwrap();
+				xdg.configured = true;
+
+				if let (
+					Configure::Toplevel(configure),
+					XdgRole::Toplevel(toplevel),
+				) = (configure, &mut xdg.role)
+				{
+					let window = state.space.window_mut(&id);
+					apply_toplevel_state(toplevel, window, configure, output);
+				}
+
+				if xdg.pending_configures.is_empty() {
+					state.flush_resize(&id);
+				}
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		_resource: &XdgSurfaceResource,
+		data: &XdgSurfaceData,
+	) {
+		state.xdg_shell.remove_surface(&data.surface.id());
+	}
+}
+
+impl ServerState {
+	/// Geometry of a popup relative to its parent's window geometry,
+	/// constrained to the output.
+	fn popup_geometry(
+		&self,
+		parent: &WlSurface,
+		positioner: &Positioner,
+	) -> Rectangle {
+		let output = self.output_geometry(&parent.id());
+		let origin = self.window_origin(&parent.id()).unwrap_or_default();
+		let bounds =
+			Rectangle::from_loc_and_size(output.loc() - origin, output.size());
+
+		positioner.constrained_geometry(bounds)
+	}
+
+	/// Whether `positioner` was made for a state `parent` did not commit
+	/// yet: the configure it was given for, or else a new size.
+	fn awaits_parent(
+		&self,
+		parent: &WlSurface,
+		positioner: &Positioner,
+	) -> bool {
+		let id = parent.id();
+		let Some(xdg) = self.xdg_shell.surfaces.get(&id) else {
+			return false;
+		};
+		let size = self
+			.compositor
+			.surfaces
+			.get(&id)
+			.and_then(|surface| surface.size())
+			.map(|size| xdg.geometry(size).size());
+
+		match positioner.parent_configure {
+			Some(serial) => xdg
+				.pending_configures
+				.iter()
+				.any(|(pending, _)| *pending == serial),
+			None => positioner.parent_size.is_some_and(|s| Some(s) != size),
+		}
+	}
+
+	/// Repositions the popups of `parent` after it moved or committed a new
+	/// state: the reactive ones, and those positioned for that state. Their
+	/// own popups follow.
+	pub fn reposition_popups(&mut self, parent: &ObjectId) {
+		for id in self.xdg_shell.popups(parent) {
+			let Some(xdg) = self.xdg_shell.surfaces.get(&id) else {
+				continue;
+			};
+			let XdgRole::Popup(popup) = &xdg.role else {
+				continue;
+			};
+
+			let follows = popup.positioner.reactive
+				|| popup.awaiting_parent
+					&& !self.awaits_parent(&popup.parent, &popup.positioner);
+			if xdg.configure_sent && follows {
+				let geometry =
+					self.popup_geometry(&popup.parent, &popup.positioner);
+
+				let Some(XdgRole::Popup(popup)) = self
+					.xdg_shell
+					.surfaces
+					.get_mut(&id)
+					.map(|xdg| &mut xdg.role)
+				else {
+					continue;
+				};
+				popup.awaiting_parent = false;
+				if popup.geometry != geometry {
+					popup.geometry = geometry;
+					self.send_xdg_configure(&id);
+				}
+			}
+
+			self.reposition_popups(&id);
+		}
+	}
+}
+
+impl Dispatch<XdgToplevel, WlSurface> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		resource: &XdgToplevel,
+		request: xdg_toplevel::Request,
+		data: &WlSurface,
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		let id = data.id();
+		let Some(XdgRole::Toplevel(toplevel)) = state
+			.xdg_shell
+			.surfaces
+			.get_mut(&id)
+			.map(|xdg| &mut xdg.role)
+		else {
+			return;
+		};
+
+		match request {
+			xdg_toplevel::Request::Destroy => {}
+			xdg_toplevel::Request::SetParent { parent } => {
+				toplevel.parent = parent
+					.as_ref()
+					.and_then(|p| p.data::<WlSurface>())
+					.cloned();
+			}
+			xdg_toplevel::Request::SetTitle { title } => {
+				toplevel.title = Some(title);
+			}
+			xdg_toplevel::Request::SetAppId { app_id } => {
+				toplevel.app_id = Some(app_id);
+			}
+			xdg_toplevel::Request::ShowWindowMenu { .. } => {
+				tracing::debug!("Window menus are not supported");
+			}
+			xdg_toplevel::Request::Move { serial, .. } => {
+				state.start_move(&id, serial);
+			}
+			xdg_toplevel::Request::Resize { serial, edges, .. } => {
+				let WEnum::Value(edges) = edges else {
+					resource.post_error(
+						xdg_toplevel::Error::InvalidResizeEdge,
+						"invalid resize edge",
+					);
+					return;
+				};
+
+				state.start_resize(&id, serial, edges);
+			}
+			xdg_toplevel::Request::SetMaxSize { width, height } => {
+				let min = toplevel.pending_min_size;
+				if width < 0
+					|| height < 0 || (width > 0 && width < min.width)
+					|| (height > 0 && height < min.height)
+				{
+					resource.post_error(
+						xdg_toplevel::Error::InvalidSize,
+						format!("invalid max size {}x{}", width, height),
+					);
+					return;
+				}
+
+				toplevel.pending_max_size = Size::new(width, height);
+			}
+			xdg_toplevel::Request::SetMinSize { width, height } => {
+				let max = toplevel.pending_max_size;
+				if width < 0
+					|| height < 0 || (max.width > 0 && width > max.width)
+					|| (max.height > 0 && height > max.height)
+				{
+					resource.post_error(
+						xdg_toplevel::Error::InvalidSize,
+						format!("invalid min size {}x{}", width, height),
+					);
+					return;
+				}
+
+				toplevel.pending_min_size = Size::new(width, height);
+			}
+			xdg_toplevel::Request::SetMaximized => {
+				state.set_maximized(&id, true);
+			}
+			xdg_toplevel::Request::UnsetMaximized => {
+				state.set_maximized(&id, false);
+			}
+			xdg_toplevel::Request::SetFullscreen { output } => {
+				// Without an output, the window stays on the one it is on.
+				let index = output
+					.and_then(|output| output.data::<usize>().copied())
+					.unwrap_or_else(|| state.window_output(&id));
+				if let Some(XdgRole::Toplevel(toplevel)) = state
+					.xdg_shell
+					.surfaces
+					.get_mut(&id)
+					.map(|xdg| &mut xdg.role)
+				{
+					toplevel.fullscreen_output = Some(index);
+				}
+
+				state.update_toplevel(&id, |toplevel, output| {
+					toplevel.requested.fullscreen = true;
+					toplevel.requested.size = Some(output.size());
+				});
+			}
+			xdg_toplevel::Request::UnsetFullscreen => {
+				state.update_toplevel(&id, |toplevel, output| {
+					toplevel.requested.fullscreen = false;
+					toplevel.fullscreen_output = None;
+					toplevel.requested.size =
+						toplevel.requested.maximized.then(|| {
+							Insets::maximized_area(
+								output,
+								toplevel.requested.decorated,
+							)
+							.size()
+						});
+				});
+			}
+			xdg_toplevel::Request::SetMinimized => {
+				state.minimize_window(&id);
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		_resource: &XdgToplevel,
+		data: &WlSurface,
+	) {
+		let id = data.id();
+		state.unmap_xdg_surface(&id);
+		if let Some(xdg) = state.xdg_shell.surfaces.get_mut(&id) {
+			if let XdgRole::Toplevel(Toplevel {
+				decoration: Some(decoration),
+				..
+			}) = &xdg.role
+			{
+				decoration.post_error(
+					zxdg_toplevel_decoration_v1::Error::Orphaned,
+					"xdg_toplevel destroyed before its decoration object",
+				);
+			}
+			xdg.role = XdgRole::None;
+		}
+	}
+}
+
+impl Dispatch<XdgPopup, WlSurface> for ServerState {
+	fn request(
+		state: &mut Self,
+		_client: &Client,
+		_resource: &XdgPopup,
+		request: xdg_popup::Request,
+		data: &WlSurface,
+		_dhandle: &DisplayHandle,
+		_data_init: &mut DataInit<'_, Self>,
+	) {
+		let id = data.id();
+
+		match request {
+			xdg_popup::Request::Destroy => {
+				if state
+					.xdg_shell
+					.popups(&id)
+					.iter()
+					.any(|popup| state.xdg_shell.surfaces[popup].mapped)
+				{
+					if let Some(xdg) = state.xdg_shell.surfaces.get(&id) {
+						xdg.wm_base.post_error(
+							xdg_wm_base::Error::NotTheTopmostPopup,
+							"popup destroyed before its children",
+						);
+					}
+				}
+			}
+			xdg_popup::Request::Grab { .. } => state.grab_popup(&id),
+			xdg_popup::Request::Reposition { positioner, token } => {
+				let Some(positioner) = state
+					.xdg_shell
+					.positioners
+					.get(&positioner.id())
+					.copied()
+					.filter(Positioner::is_complete)
+				else {
+					if let Some(xdg) = state.xdg_shell.surfaces.get(&id) {
+						xdg.wm_base.post_error(
+							xdg_wm_base::Error::InvalidPositioner,
+							"incomplete positioner",
+						);
+					}
+					return;
+				};
+
+				let Some(XdgRole::Popup(popup)) =
+					state.xdg_shell.surfaces.get(&id).map(|xdg| &xdg.role)
+				else {
+					return;
+				};
+				let geometry = state.popup_geometry(&popup.parent, &positioner);
+				let awaiting_parent =
+					state.awaits_parent(&popup.parent, &positioner);
+
+				let Some(XdgRole::Popup(popup)) = state
+					.xdg_shell
+					.surfaces
+					.get_mut(&id)
+					.map(|xdg| &mut xdg.role)
+				else {
+					return;
+				};
+				popup.positioner = positioner;
+				popup.geometry = geometry;
+				popup.awaiting_parent = awaiting_parent;
+				popup.handle.repositioned(token);
+
+				state.send_xdg_configure(&id);
+			}
+			_ => unreachable!(),
+		}
+	}
+
+	fn destroyed(
+		state: &mut Self,
+		_client: ClientId,
+		_resource: &XdgPopup,
+		data: &WlSurface,
+	) {
+		let id = data.id();
+		state.unmap_xdg_surface(&id);
+		if let Some(xdg) = state.xdg_shell.surfaces.get_mut(&id) {
+			xdg.role = XdgRole::None;
+		}
+	}
+}
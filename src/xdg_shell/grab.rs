@@ -0,0 +1,112 @@
+use wayland_protocols::xdg::shell::server::{xdg_popup, xdg_wm_base};
+use wayland_server::{backend::ObjectId, Resource as _};
+
+use super::{XdgRole, XdgSurface};
+use crate::ServerState;
+
+impl ServerState {
+	/// Handles `xdg_popup.grab`, putting the popup `id` on top of the popup
+	/// grab.
+	pub(super) fn grab_popup(&mut self, id: &ObjectId) {
+		let Some(xdg) = self.xdg_shell.surfaces.get(id) else {
+			return;
+		};
+		let XdgRole::Popup(popup) = &xdg.role else {
+			return;
+		};
+		let handle = popup.handle.clone();
+		let wm_base = xdg.wm_base.clone();
+		let parent = popup.parent.id();
+
+		if xdg.configure_sent {
+			handle.post_error(
+				xdg_popup::Error::InvalidGrab,
+				"popup grabbed after being mapped",
+			);
+			return;
+		}
+
+		let parent_grab = match self.xdg_shell.surfaces.get(&parent) {
+			Some(XdgSurface {
+				role: XdgRole::Popup(parent),
+				..
+			}) => Some(parent.grab),
+			_ => None,
+		};
+
+		match parent_grab {
+			Some(false) => {
+				handle.post_error(
+					xdg_popup::Error::InvalidGrab,
+					"parent popup did not take a grab",
+				);
+				return;
+			}
+			Some(true) if self.xdg_shell.popup_grab.last() != Some(&parent) => {
+				if self.xdg_shell.popup_grab.contains(&parent) {
+					wm_base.post_error(
+						xdg_wm_base::Error::NotTheTopmostPopup,
+						"parent popup is not the topmost grabbing popup",
+					);
+					return;
+				}
+
+				// The grab of the parent ended already, and this one ends
+				// with it.
+				handle.popup_done();
+			}
+			Some(true) => self.xdg_shell.popup_grab.push(id.clone()),
+			None => {
+				// A grab from a toplevel replaces any other.
+				self.dismiss_popup_grab(0);
+				self.xdg_shell.popup_grab.push(id.clone());
+			}
+		}
+
+		if let Some(XdgRole::Popup(popup)) =
+			self.xdg_shell.surfaces.get_mut(id).map(|xdg| &mut xdg.role)
+		{
+			popup.grab = true;
+		}
+	}
+
+	/// Dismisses the grabbing popups from the `start`th one up, topmost
+	/// first, moving keyboard focus back to their window if they had it.
+	pub(super) fn dismiss_popup_grab(&mut self, start: usize) {
+		if start >= self.xdg_shell.popup_grab.len() {
+			return;
+		}
+
+		let dismissed = self.xdg_shell.popup_grab.split_off(start);
+		for id in dismissed.iter().rev() {
+			if let Some(XdgRole::Popup(popup)) =
+				self.xdg_shell.surfaces.get(id).map(|xdg| &xdg.role)
+			{
+				popup.handle.popup_done();
+			}
+		}
+
+		let focus = self.seat.keyboard.focus.as_ref().map(|s| s.id());
+		if let Some(focus) = focus.filter(|id| dismissed.contains(id)) {
+			self.focus_window(&focus);
+		}
+	}
+
+	/// Ends the popup grab when a button is pressed outside the surfaces of
+	/// the client holding it.
+	pub fn popup_grab_press(&mut self) {
+		let Some(top) = self.xdg_shell.popup_grab.last() else {
+			return;
+		};
+
+		let inside = self
+			.seat
+			.pointer
+			.focus
+			.as_ref()
+			.is_some_and(|focus| focus.surface.id().same_client_as(top));
+		if !inside {
+			self.dismiss_popup_grab(0);
+		}
+	}
+}
@@ -0,0 +1,244 @@
+use wayland_protocols::xdg::shell::server::xdg_positioner::{
+	Anchor, ConstraintAdjustment, Gravity,
+};
+
+use crate::geometry::{Point, Rectangle, Size};
+
+#[cfg(test)]
+mod tests;
+
+/// The rules of an `xdg_positioner`.
+#[derive(Copy, Clone, Debug)]
+pub struct Positioner {
+	pub size: Size,
+	/// Relative to the window geometry of the parent.
+	pub anchor_rect: Rectangle,
+	/// Whether `set_anchor_rect` was called, since an empty anchor rectangle
+	/// is valid.
+	pub anchor_rect_set: bool,
+	pub anchor: Anchor,
+	pub gravity: Gravity,
+	pub constraint_adjustment: ConstraintAdjustment,
+	pub offset: Point,
+	/// Whether the popup follows its parent when it moves or resizes.
+	pub reactive: bool,
+	/// Size of the parent window geometry the popup is positioned for, when
+	/// it differs from the current one.
+	pub parent_size: Option<Size>,
+	/// Serial of the parent configure the popup is positioned for.
+	pub parent_configure: Option<u32>,
+}
+
+impl Default for Positioner {
+	fn default() -> Self {
+		Self {
+			size: Size::default(),
+			anchor_rect: Rectangle::default(),
+			anchor_rect_set: false,
+			anchor: Anchor::None,
+			gravity: Gravity::None,
+			constraint_adjustment: ConstraintAdjustment::empty(),
+			offset: Point::default(),
+			reactive: false,
+			parent_size: None,
+			parent_configure: None,
+		}
+	}
+}
+
+impl Positioner {
+	/// Whether both the size and anchor rectangle were set.
+	pub fn is_complete(&self) -> bool {
+		self.size.width > 0 && self.size.height > 0 && self.anchor_rect_set
+	}
+
+	/// Geometry of the popup relative to the parent's window geometry,
+	/// before any constraint adjustment.
+	pub fn geometry(&self) -> Rectangle {
+		let anchor = self.anchor_rect;
+
+		let x = match self.anchor {
+			Anchor::Left | Anchor::TopLeft | Anchor::BottomLeft => anchor.x,
+			Anchor::Right | Anchor::TopRight | Anchor::BottomRight => {
+				anchor.x + anchor.width
+			}
+			_ => anchor.x + anchor.width / 2,
+		};
+		let y = match self.anchor {
+			Anchor::Top | Anchor::TopLeft | Anchor::TopRight => anchor.y,
+			Anchor::Bottom | Anchor::BottomLeft | Anchor::BottomRight => {
+				anchor.y + anchor.height
+			}
+			_ => anchor.y + anchor.height / 2,
+		};
+
+		let x = match self.gravity {
+			Gravity::Left | Gravity::TopLeft | Gravity::BottomLeft => {
+				x - self.size.width
+			}
+			Gravity::Right | Gravity::TopRight | Gravity::BottomRight => x,
+			_ => x - self.size.width / 2,
+		};
+		let y = match self.gravity {
+			Gravity::Top | Gravity::TopLeft | Gravity::TopRight => {
+				y - self.size.height
+			}
+			Gravity::Bottom | Gravity::BottomLeft | Gravity::BottomRight => y,
+			_ => y - self.size.height / 2,
+		};
+
+		Rectangle::new(
+			x + self.offset.x,
+			y + self.offset.y,
+			self.size.width,
+			self.size.height,
+		)
+	}
+
+	/// Geometry of the popup adjusted to fit in `bounds`, both relative to
+	/// the parent's window geometry.
+	///
+	/// Each axis is handled separately, trying flip, then slide, then resize
+	/// as allowed by the constraint adjustment.
+	pub fn constrained_geometry(&self, bounds: Rectangle) -> Rectangle {
+		let mut geometry = self.geometry();
+		let adjustment = self.constraint_adjustment;
+
+		if !fits_x(geometry, bounds)
+			&& adjustment.contains(ConstraintAdjustment::FlipX)
+		{
+			let flipped = Self {
+				anchor: flip_anchor_x(self.anchor),
+				gravity: flip_gravity_x(self.gravity),
+				offset: Point::new(-self.offset.x, self.offset.y),
+				..*self
+			}
+			.geometry();
+
+			if fits_x(flipped, bounds) {
+				geometry.x = flipped.x;
+			}
+		}
+
+		if !fits_y(geometry, bounds)
+			&& adjustment.contains(ConstraintAdjustment::FlipY)
+		{
+			let flipped = Self {
+				anchor: flip_anchor_y(self.anchor),
+				gravity: flip_gravity_y(self.gravity),
+				offset: Point::new(self.offset.x, -self.offset.y),
+				..*self
+			}
+			.geometry();
+
+			if fits_y(flipped, bounds) {
+				geometry.y = flipped.y;
+			}
+		}
+
+		if !fits_x(geometry, bounds)
+			&& adjustment.contains(ConstraintAdjustment::SlideX)
+		{
+			// Prefer keeping the left edge visible.
+			let right = bounds.x + bounds.width;
+			if geometry.x + geometry.width > right {
+				geometry.x = right - geometry.width;
+			}
+			geometry.x = geometry.x.max(bounds.x);
+		}
+
+		if !fits_y(geometry, bounds)
+			&& adjustment.contains(ConstraintAdjustment::SlideY)
+		{
+			let bottom = bounds.y + bounds.height;
+			if geometry.y + geometry.height > bottom {
+				geometry.y = bottom - geometry.height;
+			}
+			geometry.y = geometry.y.max(bounds.y);
+		}
+
+		if !fits_x(geometry, bounds)
+			&& adjustment.contains(ConstraintAdjustment::ResizeX)
+		{
+			let left = geometry.x.max(bounds.x);
+			let right =
+				(geometry.x + geometry.width).min(bounds.x + bounds.width);
+			if right > left {
+				geometry.x = left;
+				geometry.width = right - left;
+			}
+		}
+
+		if !fits_y(geometry, bounds)
+			&& adjustment.contains(ConstraintAdjustment::ResizeY)
+		{
+			let top = geometry.y.max(bounds.y);
+			let bottom =
+				(geometry.y + geometry.height).min(bounds.y + bounds.height);
+			if bottom > top {
+				geometry.y = top;
+				geometry.height = bottom - top;
+			}
+		}
+
+		geometry
+	}
+}
+
+fn fits_x(geometry: Rectangle, bounds: Rectangle) -> bool {
+	geometry.x >= bounds.x
+		&& geometry.x + geometry.width <= bounds.x + bounds.width
+}
+
+fn fits_y(geometry: Rectangle, bounds: Rectangle) -> bool {
+	geometry.y >= bounds.y
+		&& geometry.y + geometry.height <= bounds.y + bounds.height
+}
+
+fn flip_anchor_x(anchor: Anchor) -> Anchor {
+	match anchor {
+		Anchor::Left => Anchor::Right,
+		Anchor::Right => Anchor::Left,
+		Anchor::TopLeft => Anchor::TopRight,
+		Anchor::TopRight => Anchor::TopLeft,
+		Anchor::BottomLeft => Anchor::BottomRight,
+		Anchor::BottomRight => Anchor::BottomLeft,
+		anchor => anchor,
+	}
+}
+
+fn flip_anchor_y(anchor: Anchor) -> Anchor {
+	match anchor {
+		Anchor::Top => Anchor::Bottom,
+		Anchor::Bottom => Anchor::Top,
+		Anchor::TopLeft => Anchor::BottomLeft,
+		Anchor::BottomLeft => Anchor::TopLeft,
+		Anchor::TopRight => Anchor::BottomRight,
+		Anchor::BottomRight => Anchor::TopRight,
+		anchor => anchor,
+	}
+}
+
+fn flip_gravity_x(gravity: Gravity) -> Gravity {
+	match gravity {
+		Gravity::Left => Gravity::Right,
+		Gravity::Right => Gravity::Left,
+		Gravity::TopLeft => Gravity::TopRight,
+		Gravity::TopRight => Gravity::TopLeft,
+		Gravity::BottomLeft => Gravity::BottomRight,
+		Gravity::BottomRight => Gravity::BottomLeft,
+		gravity => gravity,
+	}
+}
+
+fn flip_gravity_y(gravity: Gravity) -> Gravity {
+	match gravity {
+		Gravity::Top => Gravity::Bottom,
+		Gravity::Bottom => Gravity::Top,
+		Gravity::TopLeft => Gravity::BottomLeft,
+		Gravity::BottomLeft => Gravity::TopLeft,
+		Gravity::TopRight => Gravity::BottomRight,
+		Gravity::BottomRight => Gravity::TopRight,
+		gravity => gravity,
+	}
+}
@@ -0,0 +1,238 @@
+//! Tests of the constraint adjustments, all against an output covering
+//! `BOUNDS` relative to the parent.
+
+use wayland_protocols::xdg::shell::server::xdg_positioner::{
+	Anchor, ConstraintAdjustment, Gravity,
+};
+
+use super::Positioner;
+use crate::geometry::{Point, Rectangle, Size};
+
+const BOUNDS: Rectangle = Rectangle {
+	x: 0,
+	y: 0,
+	width: 100,
+	height: 100,
+};
+
+/// A 20x20 popup below and right of the bottom right corner of
+/// `anchor_rect`.
+fn positioner(
+	anchor_rect: Rectangle,
+	adjustment: ConstraintAdjustment,
+) -> Positioner {
+	Positioner {
+		size: Size::new(20, 20),
+		anchor_rect,
+		anchor_rect_set: true,
+		anchor: Anchor::BottomRight,
+		gravity: Gravity::BottomRight,
+		constraint_adjustment: adjustment,
+		..Default::default()
+	}
+}
+
+#[test]
+fn unconstrained() {
+	let positioner =
+		positioner(Rectangle::new(40, 40, 10, 10), ConstraintAdjustment::all());
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(50, 50, 20, 20)
+	);
+}
+
+#[test]
+fn no_adjustment() {
+	let positioner = positioner(
+		Rectangle::new(85, 85, 10, 10),
+		ConstraintAdjustment::empty(),
+	);
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(95, 95, 20, 20)
+	);
+}
+
+#[test]
+fn flip_x() {
+	let positioner =
+		positioner(Rectangle::new(85, 40, 10, 10), ConstraintAdjustment::FlipX);
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(65, 50, 20, 20)
+	);
+}
+
+#[test]
+fn flip_x_mirrors_offset() {
+	let positioner = Positioner {
+		offset: Point::new(5, 5),
+		..positioner(
+			Rectangle::new(80, 40, 10, 10),
+			ConstraintAdjustment::FlipX,
+		)
+	};
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(55, 55, 20, 20)
+	);
+}
+
+#[test]
+fn flip_y() {
+	let positioner =
+		positioner(Rectangle::new(40, 85, 10, 10), ConstraintAdjustment::FlipY);
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(50, 65, 20, 20)
+	);
+}
+
+#[test]
+fn flip_is_skipped_when_flipped_does_not_fit() {
+	let positioner = positioner(
+		Rectangle::new(5, 5, 90, 90),
+		ConstraintAdjustment::FlipX | ConstraintAdjustment::FlipY,
+	);
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(95, 95, 20, 20)
+	);
+}
+
+#[test]
+fn flip_is_preferred_to_slide() {
+	let positioner = positioner(
+		Rectangle::new(85, 40, 10, 10),
+		ConstraintAdjustment::FlipX | ConstraintAdjustment::SlideX,
+	);
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(65, 50, 20, 20)
+	);
+}
+
+#[test]
+fn slide_when_flip_does_not_fit() {
+	let positioner = positioner(
+		Rectangle::new(5, 5, 90, 90),
+		ConstraintAdjustment::FlipX
+			| ConstraintAdjustment::FlipY
+			| ConstraintAdjustment::SlideX
+			| ConstraintAdjustment::SlideY,
+	);
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(80, 80, 20, 20)
+	);
+}
+
+#[test]
+fn slide_x() {
+	let positioner = positioner(
+		Rectangle::new(85, 40, 10, 10),
+		ConstraintAdjustment::SlideX,
+	);
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(80, 50, 20, 20)
+	);
+}
+
+#[test]
+fn slide_y_from_above() {
+	let positioner = Positioner {
+		anchor: Anchor::TopLeft,
+		gravity: Gravity::TopLeft,
+		..positioner(
+			Rectangle::new(40, 15, 10, 10),
+			ConstraintAdjustment::SlideY,
+		)
+	};
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(20, 0, 20, 20)
+	);
+}
+
+#[test]
+fn slide_keeps_left_edge_of_wide_popup() {
+	let positioner = Positioner {
+		size: Size::new(120, 20),
+		..positioner(
+			Rectangle::new(40, 40, 10, 10),
+			ConstraintAdjustment::SlideX,
+		)
+	};
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(0, 50, 120, 20)
+	);
+}
+
+#[test]
+fn resize_x() {
+	let positioner = positioner(
+		Rectangle::new(85, 40, 10, 10),
+		ConstraintAdjustment::ResizeX,
+	);
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(95, 50, 5, 20)
+	);
+}
+
+#[test]
+fn resize_y() {
+	let positioner = positioner(
+		Rectangle::new(40, 85, 10, 10),
+		ConstraintAdjustment::ResizeY,
+	);
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(50, 95, 20, 5)
+	);
+}
+
+#[test]
+fn resize_after_slide() {
+	let positioner = Positioner {
+		size: Size::new(120, 20),
+		..positioner(
+			Rectangle::new(40, 40, 10, 10),
+			ConstraintAdjustment::SlideX | ConstraintAdjustment::ResizeX,
+		)
+	};
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(0, 50, 100, 20)
+	);
+}
+
+#[test]
+fn resize_is_skipped_outside_bounds() {
+	let positioner = positioner(
+		Rectangle::new(110, 40, 10, 10),
+		ConstraintAdjustment::ResizeX,
+	);
+
+	assert_eq!(
+		positioner.constrained_geometry(BOUNDS),
+		Rectangle::new(120, 50, 20, 20)
+	);
+}